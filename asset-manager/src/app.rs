@@ -1,25 +1,50 @@
 use std::fs;
 use std::str::FromStr;
- 
-use crate::models::{Fund, FiatCurrency, MarketSnapshot, PriceSheet};
+
+use crate::cash::{CashAccount, CashMovement, CashMovementKind};
+use crate::fees::{parse_fees, Fee, FeesReport};
+use crate::transaction::Transaction;
+use crate::errors::AppErrors;
+use crate::cost_basis::CostBasis;
+use crate::dashboard::Dashboard;
+use crate::fx::{FxRates, RateDate};
+use crate::inflation::InflationIndex;
+use crate::price_history::PriceHistory;
+use crate::price_provider::{CachedPrices, PriceProvider};
+use crate::net_worth::{CashValue, HoldingValue, Interval, NetWorthSeries, Valuation};
+use crate::rebalance::DriftReport;
+use crate::returns::GroupReturns;
+use crate::tax::{self, TaxReport, TAX_CURRENCY};
+use crate::models::{Buy, Expense, Fund, FiatCurrency, Income, IncomeKind, MarketSnapshot, PriceSheet, Sell};
 use crate::asset::{Asset, AssetType, AssetEvaluation};
-use crate::types::{FundName, AssetId};
+use crate::types::{FundName, AssetId, EpochMillis, HashString, MarketKey};
 use crate::user::{User, UserSettings};
 use crate::utils::now::Now;
+use crate::utils::{parse_decimal, parse_option_string, parse_option_u64, parse_u128, is_inside_dir, read_json_file, write_file};
+use crate::vault;
 use json::JsonValue;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct App {
-    owner: User,
+    pub(crate) owner: User,
     user_settings: UserSettings,
     next_asset_id: u32,
     pub funds: HashMap<FundName, Fund>,
     assets: Vec<Asset>,
     pub price_sheet: PriceSheet,
-    /// TODO: key, String, is the asset_type!!!
-    latest_prices: HashMap<AssetId, MarketSnapshot>
+    /// Exchange rates by date, loaded from a local file.
+    #[serde(default)]
+    pub fx_rates: FxRates,
+    /// Latest price of every market, shared by all the assets of the market.
+    latest_prices: HashMap<MarketKey, MarketSnapshot>,
+    /// Bank accounts and cash of the funds.
+    #[serde(default)]
+    cash_accounts: Vec<CashAccount>,
+    /// Consumer price index of the user currency, for the real returns.
+    #[serde(skip)]
+    inflation: Option<InflationIndex>
 }
 
 impl App {
@@ -31,29 +56,289 @@ impl App {
             funds: HashMap::new(),
             assets: Vec::new(),
             price_sheet: PriceSheet::default(),
-            latest_prices: HashMap::new()
+            fx_rates: FxRates::default(),
+            latest_prices: HashMap::new(),
+            cash_accounts: Vec::new(),
+            inflation: None
+        }
+    }
+
+    pub(crate) fn create_fund(&mut self, fund_name: FundName) -> Result<(), AppErrors> {
+        if self.funds.contains_key(&fund_name) {
+            return Err(AppErrors::DuplicateFund(fund_name));
+        }
+        self.funds.insert(
+            fund_name.clone(), Fund::new(fund_name)
+        );
+        Ok(())
+    }
+
+    pub(crate) fn get_fund(&mut self, fund_name: &FundName) -> Result<Fund, AppErrors> {
+        self.internal_get_fund(fund_name)
+    }
+
+    pub(crate) fn create_new_asset(&mut self, fund: Fund, asset_type: AssetType) -> AssetId {
+        let asset_id = self.next_asset_id;
+        self.assets.push(Asset::new(
+            asset_id,
+            fund,
+            asset_type,
+            self.user_settings.clone()
+        ));
+        self.next_asset_id += 1;
+        asset_id
+    }
+
+    pub(crate) fn get_asset(&self, asset_id: AssetId) -> Result<&Asset, AppErrors> {
+        self.assets
+            .iter()
+            .find(|asset| asset.id == asset_id)
+            .ok_or(AppErrors::AssetNotFound(asset_id))
+    }
+
+    pub(crate) fn get_asset_mut(&mut self, asset_id: AssetId) -> Result<&mut Asset, AppErrors> {
+        self.assets
+            .iter_mut()
+            .find(|asset| asset.id == asset_id)
+            .ok_or(AppErrors::AssetNotFound(asset_id))
+    }
+
+    pub(crate) fn get_assets(&self) -> &Vec<Asset> {
+        &self.assets
+    }
+
+    pub(crate) fn create_cash_account(
+        &mut self,
+        fund_name: &FundName,
+        name: String,
+        currency: FiatCurrency
+    ) -> Result<(), AppErrors> {
+        self.internal_get_fund(fund_name)?;
+        if self.get_cash_account(&name).is_ok() {
+            return Err(AppErrors::DuplicateCashAccount(name));
+        }
+        self.cash_accounts.push(CashAccount::new(name, fund_name.clone(), currency));
+        Ok(())
+    }
+
+    pub(crate) fn get_cash_account(&self, name: &str) -> Result<&CashAccount, AppErrors> {
+        self.cash_accounts
+            .iter()
+            .find(|account| account.get_name() == name)
+            .ok_or_else(|| AppErrors::UnknownCashAccount(name.to_string()))
+    }
+
+    pub(crate) fn get_cash_accounts(&self) -> &Vec<CashAccount> {
+        &self.cash_accounts
+    }
+
+    /// Deposits, withdrawals and the other movements of a single account.
+    pub(crate) fn add_cash_movement(&mut self, name: &str, movement: CashMovement) -> Result<(), AppErrors> {
+        self.cash_accounts
+            .iter_mut()
+            .find(|account| account.get_name() == name)
+            .ok_or_else(|| AppErrors::UnknownCashAccount(name.to_string()))?
+            .add_movement(movement)
+    }
+
+    /// Moves cash between two accounts. Accounts in different currencies
+    /// convert at the rate of the settlement.
+    pub(crate) fn transfer_cash(&mut self, from: &str, to: &str, settled_at: EpochMillis, amount: u128) -> Result<(), AppErrors> {
+        if from == to {
+            return Err(AppErrors::InvalidOperation(format!("Transfer from {} to itself.", from)));
         }
+        let received = self.fx_rates.convert_settled(
+            amount,
+            self.get_cash_account(from)?.get_currency(),
+            self.get_cash_account(to)?.get_currency(),
+            settled_at,
+            RateDate::Settlement
+        )?;
+        self.add_cash_movement(from, CashMovement::new(CashMovementKind::TransferOut(to.to_string()), settled_at, amount))?;
+        self.add_cash_movement(to, CashMovement::new(CashMovementKind::TransferIn(from.to_string()), settled_at, received))
+    }
+
+    /// Debits a buy of the asset, fees included, from an account in the
+    /// currency of the buy.
+    pub(crate) fn pay_buy(&mut self, account: &str, asset_id: AssetId, buy: &Buy) -> Result<(), AppErrors> {
+        let (amount, currency) = buy.get_transaction_amount_currency();
+        let movement = CashMovement::new(CashMovementKind::Buy(asset_id), buy.settled_at, amount);
+        self.settle_in_account(account, movement, buy.get_transaction(), currency)
     }
 
-    fn create_funds(&mut self, funds: JsonValue) {
+    /// Credits the net proceeds of a sell of the asset to an account in the
+    /// currency of the sell.
+    pub(crate) fn collect_sell(&mut self, account: &str, asset_id: AssetId, sell: &Sell) -> Result<(), AppErrors> {
+        let (amount, currency) = sell.get_net_proceeds();
+        let movement = CashMovement::new(CashMovementKind::Sell(asset_id), sell.settled_at, amount);
+        self.settle_in_account(account, movement, sell.get_transaction(), currency)
+    }
+
+    fn settle_in_account(
+        &mut self,
+        account: &str,
+        movement: CashMovement,
+        transaction: &Transaction,
+        currency: FiatCurrency
+    ) -> Result<(), AppErrors> {
+        if !transaction.is_fiat() {
+            return Err(AppErrors::InvalidOperation(format!("{} transaction does not settle in cash.", transaction)));
+        }
+        let expected = self.get_cash_account(account)?.get_currency();
+        if *expected != currency {
+            return Err(AppErrors::CurrencyMismatch { expected: expected.clone(), found: currency });
+        }
+        self.add_cash_movement(account, movement)
+    }
+
+    /// Exchanges the `units` given of one crypto asset for the units received
+    /// of another, worth `value` at the settlement. The given units are sold
+    /// and a lot of the received ones is bought, the fees add to its cost.
+    pub(crate) fn swap_assets(
+        &mut self,
+        from: AssetId,
+        to: AssetId,
+        settled_at: EpochMillis,
+        (given_units, received_units): (u128, u128),
+        (amount, currency): (u128, FiatCurrency),
+        fees: Vec<Fee>
+    ) -> Result<(), AppErrors> {
+        let given = self.get_asset(from)?.get_asset_type().clone();
+        let received = self.get_asset(to)?.get_asset_type().clone();
+        if from == to || !given.is_crypto() || !received.is_crypto() {
+            return Err(AppErrors::InvalidOperation(format!("Swap of {} for {} is not valid.", given, received)));
+        }
+        let sell = Sell::from_transaction(
+            settled_at,
+            given_units,
+            Transaction::CryptoSwap {
+                symbol: received.to_string(),
+                units: received_units,
+                amount,
+                currency: currency.clone(),
+                fees: Vec::new()
+            },
+            Vec::new()
+        )?;
+        let buy = Buy::from_transaction(
+            settled_at,
+            received_units,
+            Transaction::CryptoSwap { symbol: given.to_string(), units: given_units, amount, currency, fees }
+        );
+        self.get_asset(to)?.check_lot(received_units)?;
+        self.get_asset_mut(from)?.sell_lot(sell)?;
+        self.get_asset_mut(to)?.purchase_lot(buy)
+    }
+
+    /// `{ "name": "bbva", "fund": { "name": "liberty" }, "currency": "MXN",
+    /// "movements": [{ "kind": "deposit", "settled_at": "2022-01-01", "amount": "100000" }] }`,
+    /// kind is deposit or withdrawal. Transfers are `{ "kind": "transfer", "to": "other account" }`.
+    fn create_cash_positions(&mut self, cash_positions: JsonValue) -> Result<(), AppErrors> {
+        for cash_position in cash_positions.members() {
+            let name = cash_position["name"].to_string();
+            let currency = match parse_option_string(cash_position, "currency") {
+                Some(currency) => FiatCurrency::from_str(&currency)?,
+                None => FiatCurrency::MXN
+            };
+            self.create_cash_account(&cash_position["fund"]["name"].to_string(), name, currency)?;
+        }
+        // Every account exists before the transfers.
+        for cash_position in cash_positions.members() {
+            let name = cash_position["name"].to_string();
+            for movement in cash_position["movements"].members() {
+                let settled_at = Now::new_from_datetime_str(&movement["settled_at"].to_string(), "%Y-%m-%d")?.to_epoch_millis();
+                let amount = parse_u128(movement, "amount")?;
+                let kind = match movement["kind"].to_string().to_lowercase().as_str() {
+                    "deposit" => CashMovementKind::Deposit,
+                    "withdrawal" => CashMovementKind::Withdrawal,
+                    "transfer" => {
+                        self.transfer_cash(&name, &movement["to"].to_string(), settled_at, amount)?;
+                        continue;
+                    },
+                    kind => return Err(AppErrors::InvalidOperation(format!("Unknown cash movement {}.", kind)))
+                };
+                let note = parse_option_string(movement, "note");
+                self.add_cash_movement(&name, CashMovement::new(kind, settled_at, amount).with_note(note))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Balance of every account in the user currency, at the latest rate.
+    pub(crate) fn get_cash_balances(&self) -> Result<Vec<(&CashAccount, u128)>, AppErrors> {
+        self.cash_accounts
+            .iter()
+            .map(|account| Ok((
+                account,
+                self.fx_rates.convert(account.get_balance(), account.get_currency(), &self.user_settings.fiat_currency, None)?
+            )))
+            .collect()
+    }
+
+    pub(crate) fn load_price_sheet(&mut self, file_path: &str) -> Result<(), AppErrors> {
+        self.price_sheet = PriceSheet::from_file(file_path)?;
+        Ok(())
+    }
+
+    pub(crate) fn load_fx_rates(&mut self, file_path: &str) -> Result<(), AppErrors> {
+        self.fx_rates = FxRates::from_file(file_path)?;
+        Ok(())
+    }
+
+    pub(crate) fn load_inflation_index(&mut self, file_path: &str) -> Result<(), AppErrors> {
+        self.set_inflation_index(InflationIndex::from_file(file_path)?);
+        Ok(())
+    }
+
+    pub(crate) fn set_inflation_index(&mut self, index: InflationIndex) {
+        self.inflation = Some(index);
+    }
+
+    /// `{ "name": "liberty", "targets": { "GOLD": "40", "BTC": "60" } }`, the
+    /// targets are optional percentages.
+    fn create_funds(&mut self, funds: JsonValue) -> Result<(), AppErrors> {
         for fund in funds.members() {
             let fund_name = fund["name"].to_string();
-            if let Some(_) = self.funds.get(&fund_name) {
-                panic!("Fund already exist");
+            self.create_fund(fund_name.clone())?;
+            let mut targets = BTreeMap::new();
+            for (asset_type_str, weight) in fund["targets"].entries() {
+                let weight = weight.to_string();
+                let bps = u32::try_from(parse_decimal(&weight, 2)?).map_err(|_| AppErrors::MalformedAmount(weight))?;
+                targets.insert(asset_type_str.to_string(), bps);
             }
-            self.funds.insert(
-                fund_name.clone(), Fund::new(fund_name)
-            );
+            self.set_fund_targets(&fund_name, targets)?;
         }
+        Ok(())
+    }
+
+    /// Target weights in basis points by asset type, see `Fund::set_targets`.
+    pub(crate) fn set_fund_targets(&mut self, fund_name: &FundName, targets: BTreeMap<String, u32>) -> Result<(), AppErrors> {
+        self.funds
+            .get_mut(fund_name)
+            .ok_or_else(|| AppErrors::UnknownFund(fund_name.clone()))?
+            .set_targets(targets)
     }
 
-    fn create_assets(&mut self, assets: JsonValue) {
+    /// Compares the value held by the fund in each asset type with its targets.
+    pub(crate) fn get_drift_report(&self, fund_name: &FundName, price_provider: &dyn PriceProvider) -> Result<DriftReport, AppErrors> {
+        let fund = self.funds
+            .get(fund_name)
+            .ok_or_else(|| AppErrors::UnknownFund(fund_name.clone()))?;
+        let evaluations = self.assets
+            .iter()
+            .filter(|asset| asset.get_fund().get_name() == fund_name && !asset.is_sold())
+            .map(|asset| asset.evaluate(price_provider, &self.fx_rates))
+            .collect::<Result<Vec<AssetEvaluation>, AppErrors>>()?;
+        DriftReport::new(fund, self.user_settings.fiat_currency.clone(), &evaluations)
+    }
+
+    fn create_assets(&mut self, assets: JsonValue) -> Result<(), AppErrors> {
         for asset in assets.members() {
-            let fund = self.internal_get_fund(&asset["fund"]["name"].to_string());
+            let fund = self.internal_get_fund(&asset["fund"]["name"].to_string())?;
             let asset_type = AssetType::new(
                 asset["asset_type"]["type"].to_string(),
                 asset["asset_type"]["data"].clone()
-            );
+            )?;
 
             let mut new_asset = Asset::new(
                 self.next_asset_id,
@@ -61,78 +346,324 @@ impl App {
                 asset_type,
                 self.user_settings.clone()
             );
-            self.next_asset_id += 1;
 
+            // A single "buy" covers every unit, "buys" are lots with "units".
+            // Any of them may be paid from an "account".
             if !asset["buy"].is_null() {
-                let settled_at = Now::new_from_datetime_str(
-                    asset["buy"]["settled_at"].as_str().unwrap(),
-                    "%Y-%m-%d"
-                ).to_epoch_millis();
-                let amount = asset["buy"]["transaction"]["fiat_cash"]["amount"]
-                    .to_string()
-                    .parse::<u128>()
-                    .unwrap();
-                let currency = FiatCurrency::from_str(
-                    asset["buy"]["transaction"]["fiat_cash"]["currency"].as_str().unwrap()
-                ).unwrap();
-                new_asset.purchase(settled_at, amount, currency);
+                let (settled_at, transaction) = App::parse_settlement(&asset["buy"])?;
+                new_asset.purchase_with(settled_at, transaction)?;
+                if let Some(account) = parse_option_string(&asset["buy"], "account") {
+                    self.pay_buy(&account, new_asset.id, new_asset.get_buys().last().unwrap())?;
+                }
+            }
+            for buy in asset["buys"].members() {
+                let (settled_at, transaction) = App::parse_settlement(buy)?;
+                let units = parse_u128(buy, "units")?;
+                new_asset.purchase_lot(Buy::from_transaction(settled_at, units, transaction))?;
+                if let Some(account) = parse_option_string(buy, "account") {
+                    self.pay_buy(&account, new_asset.id, new_asset.get_buys().last().unwrap())?;
+                }
+            }
+
+            // A single "sell" disposes every unit held, "sells" may be partial.
+            if !asset["sell"].is_null() {
+                let sell = &asset["sell"];
+                let (settled_at, transaction) = App::parse_settlement(sell)?;
+                new_asset.sell_with(settled_at, transaction, parse_fees(&sell["fees"])?)?;
+                if let Some(account) = parse_option_string(sell, "account") {
+                    self.collect_sell(&account, new_asset.id, new_asset.get_sells().last().unwrap())?;
+                }
             }
+            for income in asset["income"].members() {
+                let (settled_at, transaction) = App::parse_settlement(income)?;
+                let kind = IncomeKind::from_str(&income["kind"].to_string())?;
+                new_asset.add_income(Income::from_transaction(kind, settled_at, transaction)?)?;
+            }
+            for expense in asset["expenses"].members() {
+                let (settled_at, transaction) = App::parse_settlement(expense)?;
+                let description = parse_option_string(expense, "description").unwrap_or_default();
+                new_asset.add_expense(Expense::from_transaction(description, settled_at, transaction)?)?;
+            }
+            for sell in asset["sells"].members() {
+                let (settled_at, transaction) = App::parse_settlement(sell)?;
+                let units = parse_u128(sell, "units")?;
+                let lot = parse_option_u64(sell, "lot")?.map(|lot| lot as usize);
+                let fees = parse_fees(&sell["fees"])?;
+                new_asset.sell_lot(Sell::from_transaction(settled_at, units, transaction, fees)?.with_lot(lot))?;
+                if let Some(account) = parse_option_string(sell, "account") {
+                    self.collect_sell(&account, new_asset.id, new_asset.get_sells().last().unwrap())?;
+                }
+            }
+            self.next_asset_id += 1;
             self.assets.push(new_asset);
         }
+        Ok(())
     }
 
-    pub(crate) fn update_market(&mut self, file_path: &str) {
-        let content = fs::read_to_string(file_path).expect("Error reading market file.");
-        let mut _market_json = json::parse(&content).unwrap();
+    /// Reads the date and the transaction of a buy or sell from the user file.
+    /// `{ "settled_at": "2022-01-01", "transaction": { "fiat_cash": { "amount", "currency" } } }`,
+    /// any kind of `Transaction` with optional `fees`.
+    fn parse_settlement(data: &JsonValue) -> Result<(EpochMillis, Transaction), AppErrors> {
+        let settled_at = Now::new_from_datetime_str(
+            &data["settled_at"].to_string(),
+            "%Y-%m-%d"
+        )?.to_epoch_millis();
+        let (transaction_str, transaction) = data["transaction"]
+            .entries()
+            .next()
+            .ok_or_else(|| AppErrors::UnknownTransaction(data["transaction"].dump()))?;
+        Ok((settled_at, Transaction::from_json(transaction_str, transaction)?))
+    }
 
-        let price_sheet = self.price_sheet.clone();
-        for asset in self.assets.iter() {
-            println!("{:?}", self.price_sheet);
-            let snapshot = asset.get_market_price(Some(price_sheet.clone()));
-            self.latest_prices.insert(asset.id, snapshot);
+    /// Applies the cost basis method to the settings and every asset.
+    pub(crate) fn set_cost_basis(&mut self, cost_basis: CostBasis) {
+        self.user_settings.cost_basis = cost_basis;
+        for asset in self.assets.iter_mut() {
+            asset.set_cost_basis(cost_basis);
+        }
+    }
+
+    pub(crate) fn get_fiat_currency(&self) -> &FiatCurrency {
+        &self.user_settings.fiat_currency
+    }
 
-            println!("{:?}", self.latest_prices);
-            
-            // // TODO: unimplemented()
-            // _market_json.push(serde_json::to_string(&snapshot).unwrap());
+    /// Values every asset in the new currency. Transactions keep their own.
+    pub(crate) fn set_fiat_currency(&mut self, fiat_currency: FiatCurrency) {
+        self.user_settings.fiat_currency = fiat_currency.clone();
+        for asset in self.assets.iter_mut() {
+            asset.set_fiat_currency(fiat_currency.clone());
+        }
+    }
+
+    /// Markets of the assets still held, once each. Manually priced, fixed
+    /// income and real estate assets have no market.
+    pub(crate) fn get_held_market_keys(&self) -> Result<Vec<MarketKey>, AppErrors> {
+        let mut market_keys = Vec::new();
+        for asset in self.assets.iter().filter(|asset| !asset.is_sold()) {
+            if !asset.get_asset_type().is_market_priced() {
+                continue;
+            }
+            let market_key = asset.get_asset_type().get_market_key()?;
+            if !market_keys.contains(&market_key) {
+                market_keys.push(market_key);
+            }
         }
+        Ok(market_keys)
     }
 
-    pub(crate) fn import_user_file(&mut self, file_path: &str) {
-        let content = fs::read_to_string(file_path).expect("Error reading assets file.");
-        let user_json = json::parse(&content).unwrap();
+    pub(crate) fn get_latest_price(&self, market_key: &str) -> Option<&MarketSnapshot> {
+        self.latest_prices.get(market_key)
+    }
 
-        // notimplemented
-        // let _cash_positions = user_json["cash_positions"].clone();
+    /// Markets held without a price or with a price older than the max age.
+    pub(crate) fn get_stale_market_keys(&self, max_age_millis: EpochMillis) -> Result<Vec<MarketKey>, AppErrors> {
+        Ok(self.get_held_market_keys()?
+            .into_iter()
+            .filter(|market_key| match self.latest_prices.get(market_key) {
+                Some(snapshot) => snapshot.is_stale(max_age_millis),
+                None => true
+            })
+            .collect())
+    }
+
+    /// Fresh latest prices, to be asked before any other provider.
+    pub(crate) fn get_cached_prices(&self, max_age_millis: EpochMillis) -> CachedPrices {
+        CachedPrices::new(self.latest_prices.clone(), max_age_millis)
+    }
+
+    /// Refreshes the stale markets held, one price per market, and appends
+    /// them to the price history file. Returns the refreshed markets.
+    pub(crate) fn update_market(
+        &mut self,
+        price_provider: &dyn PriceProvider,
+        file_path: &str,
+        max_age_millis: EpochMillis
+    ) -> Result<Vec<MarketKey>, AppErrors> {
+        let mut price_history = PriceHistory::from_file(file_path)?;
+
+        let market_keys = self.get_stale_market_keys(max_age_millis)?;
+        for market_key in market_keys.iter() {
+            let snapshot = price_provider.get_price(market_key)?;
+            price_history.append(snapshot.clone())?;
+            self.latest_prices.insert(market_key.clone(), snapshot);
+        }
+        price_history.save(file_path)?;
+        Ok(market_keys)
+    }
+
+    pub(crate) fn import_user_file(&mut self, file_path: &str) -> Result<(), AppErrors> {
+        let user_json = read_json_file(file_path)?;
 
         let funds = user_json["funds"].clone();
-        self.create_funds(funds);
+        self.create_funds(funds)?;
+
+        let cash_positions = user_json["cash_positions"].clone();
+        self.create_cash_positions(cash_positions)?;
 
         let assets = user_json["assets"].clone();
-        self.create_assets(assets);
+        self.create_assets(assets)
+    }
 
+    /// Seals the whole app state with the owner password and writes it to
+    /// `files/<env>/app_<name>.vault`. Returns the hash of the sealed file.
+    pub(crate) fn save_state(&self, password: &str) -> Result<HashString, AppErrors> {
+        let plaintext = serde_json::to_vec(&self)
+            .map_err(|err| AppErrors::MalformedFile(err.to_string()))?;
+        let sealed = vault::seal(&plaintext, password)?;
+        write_file(&self.owner.app_state_file_path(), &sealed)?;
+        Ok(vault::state_hash(&sealed))
+    }
 
+    /// Opens the sealed app state of the owner. The file must match the
+    /// `app_state_hash` recorded in the user.
+    pub(crate) fn load_state(owner: User, password: &str) -> Result<Self, AppErrors> {
+        let file_path = owner.app_state_file_path();
+        let sealed = fs::read(&file_path)
+            .map_err(|err| AppErrors::Io(format!("{}: {}", file_path, err)))?;
+        if owner.app_state_hash != Some(vault::state_hash(&sealed)) {
+            return Err(AppErrors::CorruptedAppState);
+        }
+        let plaintext = vault::unseal(&sealed, password)?;
+        let mut app: App = serde_json::from_slice(&plaintext)
+            .map_err(|_| AppErrors::CorruptedAppState)?;
+        app.owner = owner;
+        Ok(app)
+    }
 
-        // println!("{:?} 👺", self.assets)
-    
-        // let res = Vec::<Asset>::new();
-        // for asset in assets.members() {
-        //     let fund = Fund::new(asset["fund"]["name"].to_string());
-        //     let new = Asset::new(self.next_asset_id, fund, asset_type)
-        // }
+    /// Writes the decrypted app state as plain JSON. Handle the file with care,
+    /// it is never written in the data dir next to the vault.
+    pub(crate) fn export(&self, file_path: &str) -> Result<(), AppErrors> {
+        let data_dir = self.owner.get_data_dir();
+        if is_inside_dir(file_path, &data_dir) {
+            return Err(AppErrors::InvalidOperation(format!("The plain JSON export can't be written in {}.", data_dir)));
+        }
+        let content = serde_json::to_string_pretty(&self)
+            .map_err(|err| AppErrors::MalformedFile(err.to_string()))?;
+        write_file(file_path, content)
     }
 
-    /// Asset Id | Type | Entrance | Now
-    fn view_asset_eval(&self) {
-        let assets: Vec<AssetEvaluation> = self.assets.iter().map(|asset| asset.evaluate(FiatCurrency::MXN)).collect();
+    /// Evaluates every asset, in the order they were created. With an
+    /// inflation index loaded the real returns are included.
+    pub(crate) fn get_asset_evaluations(&self, price_provider: &dyn PriceProvider) -> Result<Vec<AssetEvaluation>, AppErrors> {
+        self.assets
+            .iter()
+            .map(|asset| asset.evaluate_with_inflation(price_provider, &self.fx_rates, self.inflation.as_ref()))
+            .collect()
+    }
 
-        println!("FRIDAY -> print the evaluated assets: {:#?}", assets);
+    /// The time weighted returns value the assets at each buy and sell with
+    /// the `price_history`.
+    pub(crate) fn get_dashboard(&self, price_provider: &dyn PriceProvider, price_history: &PriceHistory) -> Result<Dashboard, AppErrors> {
+        let evaluations = self.get_asset_evaluations(price_provider)?;
+        let now = Now::new().to_epoch_millis();
+        let mut by_fund: BTreeMap<&FundName, (Vec<&Asset>, u128)> = BTreeMap::new();
+        for (asset, evaluation) in self.assets.iter().zip(evaluations.iter()) {
+            let (assets, now_amount) = by_fund.entry(asset.get_fund().get_name()).or_default();
+            assets.push(asset);
+            *now_amount += evaluation.get_now_amount();
+        }
+        let mut returns = Vec::with_capacity(by_fund.len() + 1);
+        for (fund, (assets, now_amount)) in by_fund {
+            returns.push(GroupReturns::new(fund.clone(), &assets, now_amount, price_history, &self.fx_rates, now)?);
+        }
+        if !self.assets.is_empty() {
+            let assets: Vec<&Asset> = self.assets.iter().collect();
+            let now_amount = evaluations.iter().map(|evaluation| evaluation.get_now_amount()).sum();
+            returns.push(GroupReturns::new("TOTAL".to_string(), &assets, now_amount, price_history, &self.fx_rates, now)?);
+        }
+        Ok(Dashboard::new(
+            self.user_settings.fiat_currency.clone(),
+            self.assets.iter().zip(evaluations).collect(),
+            self.get_cash_balances()?,
+            returns
+        ))
+    }
 
+    /// Values the assets held and the cash of every fund at the date, with
+    /// the last prices of the history on or before it and the rates of the
+    /// date.
+    pub(crate) fn get_valuation_at(&self, at: EpochMillis, price_history: &PriceHistory) -> Result<Valuation, AppErrors> {
+        let currency = &self.user_settings.fiat_currency;
+        let mut holdings = Vec::new();
+        for asset in self.assets.iter() {
+            let held_units = asset.get_held_units_at(at);
+            if held_units == 0 {
+                continue;
+            }
+            holdings.push(HoldingValue {
+                asset_id: asset.id,
+                fund: asset.get_fund().get_name().clone(),
+                asset_type_str: asset.get_asset_type().to_string(),
+                held_units,
+                amount: asset.get_value_at(at, held_units, price_history, &self.fx_rates)?
+            });
+        }
+        let mut cash = Vec::new();
+        for account in self.cash_accounts.iter() {
+            let balance = account.get_balance_at(at);
+            if balance == 0 {
+                continue;
+            }
+            cash.push(CashValue {
+                name: account.get_name().clone(),
+                fund: account.get_fund().clone(),
+                amount: self.fx_rates.convert(balance, account.get_currency(), currency, Some(at))?
+            });
+        }
+        Ok(Valuation { currency: currency.clone(), at, holdings, cash })
     }
 
-    pub(crate) fn dashboard(&self) {
-        self.view_asset_eval();
-        // self.view_entrance_point();
+    /// Valuations every interval until `to`, from `from` or the first buy or
+    /// cash movement.
+    pub(crate) fn get_net_worth_series(
+        &self,
+        from: Option<EpochMillis>,
+        to: EpochMillis,
+        interval: Interval,
+        price_history: &PriceHistory
+    ) -> Result<NetWorthSeries, AppErrors> {
+        let first_at = self.assets
+            .iter()
+            .flat_map(|asset| asset.get_buys().iter().map(|buy| buy.settled_at))
+            .chain(self.cash_accounts.iter().flat_map(|account| account.get_movements().iter().map(|movement| movement.settled_at)))
+            .min();
+        let from = from
+            .or(first_at)
+            .ok_or_else(|| AppErrors::InvalidOperation("Nothing to value, no buys or cash movements.".to_string()))?;
+        let points = interval
+            .get_dates(from, to)
+            .into_iter()
+            .map(|at| self.get_valuation_at(at, price_history))
+            .collect::<Result<Vec<Valuation>, AppErrors>>()?;
+        Ok(NetWorthSeries { currency: self.user_settings.fiat_currency.clone(), interval, points })
+    }
 
+    /// Fees of every asset by fund and year, in the user currency at the
+    /// rate of each settlement.
+    pub(crate) fn get_fees_report(&self) -> Result<FeesReport, AppErrors> {
+        let currency = &self.user_settings.fiat_currency;
+        let mut fees = Vec::new();
+        for asset in self.assets.iter() {
+            for (settled_at, fee_currency, fee) in asset.get_fees() {
+                let amount = self.fx_rates.convert_settled(fee.amount, &fee_currency, currency, settled_at, RateDate::Settlement)?;
+                fees.push((
+                    asset.get_fund().get_name().clone(),
+                    Now::new_from_epoch_millis(settled_at).get_year(),
+                    Fee::new(fee.kind, amount)
+                ));
+            }
+        }
+        Ok(FeesReport::new(currency.clone(), fees))
+    }
+
+    /// ISR of the disposals of every fund, in the year or every year. Each
+    /// lot is converted to pesos at the rate of its settlement.
+    pub(crate) fn get_tax_report(&self, index: &InflationIndex, year: Option<i32>) -> Result<TaxReport, AppErrors> {
+        let mut disposals = Vec::new();
+        for asset in self.assets.iter() {
+            let report = asset.get_cost_basis_report_in(&TAX_CURRENCY, &self.fx_rates, RateDate::Settlement)?;
+            disposals.extend(tax::get_disposals(asset, &report, index, year)?);
+        }
+        Ok(TaxReport::new(disposals))
     }
 }
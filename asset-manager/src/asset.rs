@@ -1,158 +1,497 @@
 use std::fmt;
+use std::str::FromStr;
 
-// use std::fmt;
 use json::JsonValue;
 use serde::{Serialize, Deserialize};
-use crate::{types::{AssetId, EpochMillis}, models::{Fund, Buy, Sell, FiatCurrency, MarketSnapshot, PriceSheet}, utils::{now::Now, parse_option_string, parse_option_u16, proportional}, user::UserSettings};
+use crate::{errors::AppErrors, fx::{FxRates, RateDate}, types::{AssetId, EpochMillis, MarketKey}, models::{Fund, Buy, Sell, Expense, FiatCurrency, Income, IncomeKind, MarketSnapshot}, price_provider::PriceProvider, utils::{now::Now, annualized_bps, parse_decimal, parse_option_string, parse_option_u16, parse_u128, proportional}, user::UserSettings};
+use crate::cost_basis::{self, CostBasis, CostBasisReport};
+use crate::fees::{self, Fee};
+use crate::inflation::InflationIndex;
+use crate::price_history::PriceHistory;
+use crate::fixed_income::{FixedIncomeKind, FixedIncomeTerms};
+use crate::metals::{Metal, WeightUnit, WEIGHT_DECIMALS};
+use crate::real_estate::{Appraisal, Property};
+use crate::transaction::Transaction;
+
+/// Shares are stored with 6 decimals, 1.5 shares is 1_500_000.
+pub const SHARE_DECIMALS: u32 = 6;
+
+/// Other assets have at most 18 decimals, like the wei of ether.
+pub const MAX_OTHER_DECIMALS: u32 = 18;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum AssetType {
-    Gold { presentation: String, weight: Option<String>, purity: Option<u16>, note: Option<String> },
+    /// Precious metals. `weight` is a decimal in `weight_unit` and `purity`
+    /// is in parts per 10_000. Known coin presentations don't need them.
+    Gold {
+        presentation: String,
+        weight: Option<String>,
+        #[serde(default)]
+        weight_unit: WeightUnit,
+        purity: Option<u16>,
+        note: Option<String>
+    },
+    Silver {
+        presentation: String,
+        weight: Option<String>,
+        #[serde(default)]
+        weight_unit: WeightUnit,
+        purity: Option<u16>,
+        note: Option<String>
+    },
+    Platinum {
+        presentation: String,
+        weight: Option<String>,
+        #[serde(default)]
+        weight_unit: WeightUnit,
+        purity: Option<u16>,
+        note: Option<String>
+    },
+    Palladium {
+        presentation: String,
+        weight: Option<String>,
+        #[serde(default)]
+        weight_unit: WeightUnit,
+        purity: Option<u16>,
+        note: Option<String>
+    },
     Bitcoin { address: Option<String>, sats: u128 },
     Litecoin { address: Option<String>, lits: u128 },
     Ethereum { address: Option<String>, wei: u128 },
     Dogecoin { address: Option<String>, dogs: u128 },
-    RealState { name: String, deed_date: Option<String> },
+    /// Valued by the appraisals of the property.
+    RealState {
+        name: String,
+        deed_date: Option<String>,
+        #[serde(default)]
+        property: Property
+    },
+    /// Equities, `shares` have `SHARE_DECIMALS` to allow fractional shares.
+    Stock { ticker: String, exchange: Option<String>, shares: u128 },
+    Etf { ticker: String, exchange: Option<String>, shares: u128 },
+    MutualFund { ticker: String, exchange: Option<String>, shares: u128 },
+    /// Anything else: stocks, silver coins, art. `quantity` has `decimals`,
+    /// "2.5" with 4 decimals is 25000. `unit_price` is a manual price of
+    /// one unit in `currency`, without it the price comes from the
+    /// providers under the symbol.
+    Other {
+        symbol: String,
+        quantity: u128,
+        decimals: u32,
+        unit_price: Option<u64>,
+        currency: FiatCurrency,
+        note: Option<String>
+    },
+    /// Bonds, CETES and term deposits. Valued by their terms instead of a
+    /// market price, `titles` are whole titles of the instrument.
+    FixedIncome { name: String, titles: u128, terms: FixedIncomeTerms },
 }
 
 impl fmt::Display for AssetType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", match self {
-            AssetType::Gold { presentation, weight, purity, note } => "GOLD",
-            AssetType::Bitcoin { address, sats } => "BTC",
-            AssetType::Dogecoin { address, dogs } => "DOGE",
-            AssetType::Litecoin { address, lits } => "LTC",
-            AssetType::Ethereum { address, wei } => "ETH",
-            AssetType::RealState { name, deed_date } => "REAL_STATE"
+            AssetType::Gold { .. } => "GOLD",
+            AssetType::Silver { .. } => "SILVER",
+            AssetType::Platinum { .. } => "PLATINUM",
+            AssetType::Palladium { .. } => "PALLADIUM",
+            AssetType::Bitcoin { .. } => "BTC",
+            AssetType::Dogecoin { .. } => "DOGE",
+            AssetType::Litecoin { .. } => "LTC",
+            AssetType::Ethereum { .. } => "ETH",
+            AssetType::RealState { .. } => "REAL_STATE",
+            AssetType::Stock { .. } => "STOCK",
+            AssetType::Etf { .. } => "ETF",
+            AssetType::MutualFund { .. } => "MUTUAL_FUND",
+            AssetType::Other { .. } => "OTHER",
+            AssetType::FixedIncome { terms, .. } => terms.kind.as_str()
         })
     }
 }
 
 impl AssetType {
-    pub fn new(type_str: String, data: JsonValue) -> Self {
-        match type_str.to_lowercase().as_str() {
+    pub fn new(type_str: String, data: JsonValue) -> Result<Self, AppErrors> {
+        let asset_type = match type_str.to_lowercase().as_str() {
             "bitcoin" => {
                 Self::Bitcoin {
                     address: parse_option_string(&data, "address"),
-                    sats: data["sats"].to_string().parse::<u128>().unwrap()
+                    sats: parse_u128(&data, "sats")?
                 }
             },
             "litecoin" => {
                 Self::Litecoin {
                     address: parse_option_string(&data, "address"),
-                    lits: data["lits"].to_string().parse::<u128>().unwrap()
+                    lits: parse_u128(&data, "lits")?
                 }
             },
             "ethereum" => {
                 Self::Ethereum {
                     address: parse_option_string(&data, "address"),
-                    wei: data["wei"].to_string().parse::<u128>().unwrap()
+                    wei: parse_u128(&data, "wei")?
                 }
             },
             "dogecoin" => {
                 Self::Dogecoin {
                     address: parse_option_string(&data, "address"),
-                    dogs: data["dogs"].to_string().parse::<u128>().unwrap()
+                    dogs: parse_u128(&data, "dogs")?
                 }
             },
             "real_state" => {
                 Self::RealState {
                     deed_date: parse_option_string(&data, "deed_date"),
-                    name: data["name"].to_string()
+                    name: data["name"].to_string(),
+                    property: Property::from_json(&data)?
+                }
+            },
+            "gold" | "silver" | "platinum" | "palladium" => {
+                let presentation = data["presentation"].to_string();
+                let weight = parse_option_string(&data, "weight");
+                let weight_unit = match parse_option_string(&data, "weight_unit") {
+                    Some(weight_unit) => WeightUnit::from_str(&weight_unit)?,
+                    None => WeightUnit::Gram
+                };
+                let purity = parse_option_u16(&data, "purity")?;
+                let note = parse_option_string(&data, "note");
+                match type_str.to_lowercase().as_str() {
+                    "gold" => Self::Gold { presentation, weight, weight_unit, purity, note },
+                    "silver" => Self::Silver { presentation, weight, weight_unit, purity, note },
+                    "platinum" => Self::Platinum { presentation, weight, weight_unit, purity, note },
+                    _ => Self::Palladium { presentation, weight, weight_unit, purity, note }
+                }
+            },
+            "stock" | "etf" | "mutual_fund" => {
+                let ticker = parse_option_string(&data, "ticker")
+                    .ok_or_else(|| AppErrors::MalformedFile(format!("{} without ticker", type_str)))?
+                    .to_uppercase();
+                let exchange = parse_option_string(&data, "exchange").map(|exchange| exchange.to_uppercase());
+                let shares = parse_decimal(&data["shares"].to_string(), SHARE_DECIMALS)?;
+                match type_str.to_lowercase().as_str() {
+                    "stock" => Self::Stock { ticker, exchange, shares },
+                    "etf" => Self::Etf { ticker, exchange, shares },
+                    _ => Self::MutualFund { ticker, exchange, shares }
                 }
             },
-            "gold" => {
-                Self::Gold {
-                    presentation: data["presentation"].to_string(),
-                    weight: parse_option_string(&data, "weight"),
-                    purity: parse_option_u16(&data, "purity"),
+            "other" => {
+                let symbol = parse_option_string(&data, "symbol")
+                    .ok_or_else(|| AppErrors::MalformedFile("other asset without symbol".to_string()))?;
+                let decimals = parse_option_u16(&data, "decimals")?.unwrap_or(8) as u32;
+                if decimals > MAX_OTHER_DECIMALS {
+                    return Err(AppErrors::MalformedFile(format!(
+                        "{} with {} decimals, at most {}", symbol, decimals, MAX_OTHER_DECIMALS
+                    )));
+                }
+                let currency = match parse_option_string(&data, "currency") {
+                    Some(currency) => FiatCurrency::from_str(&currency)?,
+                    None => FiatCurrency::MXN
+                };
+                let unit_price = match parse_option_string(&data, "unit_price") {
+                    Some(unit_price) => Some(
+                        u64::try_from(parse_decimal(&unit_price, currency.get_decimals())?)
+                            .map_err(|_| AppErrors::MalformedAmount(unit_price))?
+                    ),
+                    None => None
+                };
+                Self::Other {
+                    symbol: symbol.to_uppercase(),
+                    quantity: parse_decimal(&data["quantity"].to_string(), decimals)?,
+                    decimals,
+                    unit_price,
+                    currency,
                     note: parse_option_string(&data, "note")
                 }
             },
+            "bond" | "cetes" | "term_deposit" => {
+                let kind = FixedIncomeKind::from_str(&type_str)?;
+                let name = parse_option_string(&data, "name")
+                    .ok_or_else(|| AppErrors::MalformedFile(format!("{} without name", kind)))?;
+                Self::FixedIncome {
+                    name: name.to_uppercase(),
+                    titles: parse_decimal(&parse_option_string(&data, "titles").unwrap_or("1".to_string()), 0)?,
+                    terms: FixedIncomeTerms::from_json(kind, &data)?
+                }
+            },
             _ => {
-                // TODO: Create a new Default Other Asset
-                unimplemented!();
+                return Err(AppErrors::UnknownAssetType(type_str));
             }
+        };
+        Ok(asset_type)
+    }
+
+    /// Units of the whole position, in the asset base unit. Metals and real
+    /// state are indivisible holdings.
+    pub fn get_units(&self) -> u128 {
+        match self {
+            AssetType::Bitcoin { sats, .. } => *sats,
+            AssetType::Litecoin { lits, .. } => *lits,
+            AssetType::Ethereum { wei, .. } => *wei,
+            AssetType::Dogecoin { dogs, .. } => *dogs,
+            AssetType::Other { quantity, .. } => *quantity,
+            AssetType::FixedIncome { titles, .. } => *titles,
+            AssetType::Stock { shares, .. }
+            | AssetType::Etf { shares, .. }
+            | AssetType::MutualFund { shares, .. } => *shares,
+            AssetType::Gold { .. }
+            | AssetType::Silver { .. }
+            | AssetType::Platinum { .. }
+            | AssetType::Palladium { .. }
+            | AssetType::RealState { .. } => 1
         }
+    }
 
+    /// Coins that can be swapped for each other.
+    pub fn is_crypto(&self) -> bool {
+        matches!(
+            self,
+            AssetType::Bitcoin { .. } | AssetType::Litecoin { .. } | AssetType::Ethereum { .. } | AssetType::Dogecoin { .. }
+        )
     }
 
-    fn naive_market_price(&self, use_price_sheet: PriceSheet) -> MarketSnapshot {
-        println!("{}", self);
-        println!("{:?}", use_price_sheet);
-        let median = match &self {
-            AssetType::Bitcoin { address, sats } => {
-                // (10k each btc) 10_000_00 * 3_7000_0000 / (magic) 100_000_000
-                proportional(
-                    use_price_sheet.btc.unwrap() as u128,
-                    sats.clone(),
-                    10u128.pow(8)
-                )
-            },
-            AssetType::Gold { presentation, weight, purity, note }  => {
-                let weight = weight.as_ref().unwrap().parse::<u128>().unwrap();
-                let price = match purity.unwrap() {
-                    9999 => use_price_sheet.gold_gram_24k.unwrap(),
-                    9000 => use_price_sheet.gold_gram_21k.unwrap(),
-                    _ => unimplemented!()
-                };
-                proportional(price as u128, weight, 1_u128)
-            },
-            AssetType::Litecoin { address, lits } => {
-                proportional(
-                    use_price_sheet.ltc.unwrap() as u128,
-                    lits.clone(),
-                    10u128.pow(8)
-                )
+    /// Market of the asset, every holding of the same market shares the
+    /// price history. Metals of any purity are priced from the gram of the
+    /// reference purity, "GOLD-GRAM-24K".
+    pub fn get_market_key(&self) -> Result<MarketKey, AppErrors> {
+        if let Some(metal) = self.get_metal() {
+            return Ok(metal.get_market_key().to_string());
+        }
+        match &self {
+            AssetType::Other { symbol, .. } => Ok(symbol.clone()),
+            AssetType::FixedIncome { name, .. } => Ok(name.clone()),
+            AssetType::RealState { name, .. } => Ok(format!("REAL_STATE:{}", name.to_uppercase())),
+            AssetType::Stock { ticker, exchange, .. }
+            | AssetType::Etf { ticker, exchange, .. }
+            | AssetType::MutualFund { ticker, exchange, .. } => match exchange {
+                Some(exchange) => Ok(format!("{}:{}", exchange, ticker)),
+                None => Ok(ticker.clone())
             },
-            AssetType::Ethereum { address, wei } => {
-                proportional(
-                    use_price_sheet.eth.unwrap() as u128,
-                    wei.clone(),
-                    10u128.pow(18)
-                )
+            _ => Ok(format!("{}", &self))
+        }
+    }
+
+    /// Value of the whole position given the price of one market unit.
+    pub fn get_value(&self, unit_price: u128) -> Result<u128, AppErrors> {
+        let value = match &self {
+            // (10k each btc) 10_000_00 * 3_7000_0000 / (magic) 100_000_000
+            AssetType::Bitcoin { sats, .. } => proportional(unit_price, *sats, 10u128.pow(8)),
+            AssetType::Litecoin { lits, .. } => proportional(unit_price, *lits, 10u128.pow(8)),
+            AssetType::Ethereum { wei, .. } => proportional(unit_price, *wei, 10u128.pow(18)),
+            AssetType::Dogecoin { dogs, .. } => proportional(unit_price, *dogs, 10u128.pow(10)),
+            AssetType::Gold { .. }
+            | AssetType::Silver { .. }
+            | AssetType::Platinum { .. }
+            | AssetType::Palladium { .. } => proportional(unit_price, self.get_fine_weight()?, 10u128.pow(WEIGHT_DECIMALS)),
+            AssetType::RealState { .. } => unit_price,
+            AssetType::Other { quantity, decimals, .. } => proportional(unit_price, *quantity, 10u128.pow(*decimals)),
+            AssetType::FixedIncome { titles, .. } => unit_price * titles,
+            AssetType::Stock { shares, .. }
+            | AssetType::Etf { shares, .. }
+            | AssetType::MutualFund { shares, .. } => proportional(unit_price, *shares, 10u128.pow(SHARE_DECIMALS))
+        };
+        Ok(value)
+    }
+
+    pub fn get_metal(&self) -> Option<Metal> {
+        match &self {
+            AssetType::Gold { .. } => Some(Metal::Gold),
+            AssetType::Silver { .. } => Some(Metal::Silver),
+            AssetType::Platinum { .. } => Some(Metal::Platinum),
+            AssetType::Palladium { .. } => Some(Metal::Palladium),
+            _ => None
+        }
+    }
+
+    /// Metal content in micrograms of the reference purity. The weight and
+    /// purity when given, otherwise the content of a known coin.
+    pub fn get_fine_weight(&self) -> Result<u128, AppErrors> {
+        let (metal, presentation, weight, weight_unit, purity) = match &self {
+            AssetType::Gold { presentation, weight, weight_unit, purity, .. }
+            | AssetType::Silver { presentation, weight, weight_unit, purity, .. }
+            | AssetType::Platinum { presentation, weight, weight_unit, purity, .. }
+            | AssetType::Palladium { presentation, weight, weight_unit, purity, .. } => {
+                (self.get_metal().unwrap(), presentation, weight, weight_unit, purity)
             },
-            AssetType::Dogecoin { address, dogs } => {
-                proportional(
-                    use_price_sheet.doge_4_decimals.unwrap() as u128,
-                    dogs.clone(),
-                    10u128.pow(10)
-                )
+            _ => return Err(AppErrors::InvalidOperation(format!("{} is not a precious metal.", self)))
+        };
+        match (weight, purity) {
+            (Some(weight), Some(purity)) if *purity <= 10_000 => {
+                Ok(proportional(weight_unit.to_micrograms(weight)?, *purity as u128, metal.get_reference_purity()))
             },
-            AssetType::RealState { name, deed_date } => {
-                2_000_000_u128
+            (Some(_), Some(purity)) => Err(AppErrors::MalformedAmount(format!("{} purity {}", self, purity))),
+            _ => metal.get_coin_content(presentation).ok_or_else(|| AppErrors::MalformedAmount(format!(
+                "{} {} without weight and purity", self, presentation
+            )))
+        }
+    }
+
+    /// Income events the asset type can produce.
+    pub fn accepts_income(&self, kind: IncomeKind) -> bool {
+        match kind {
+            IncomeKind::Dividend => matches!(
+                self,
+                AssetType::Stock { .. } | AssetType::Etf { .. } | AssetType::MutualFund { .. } | AssetType::Other { .. }
+            ),
+            IncomeKind::Coupon => matches!(self, AssetType::FixedIncome { .. }),
+            IncomeKind::Rent => matches!(self, AssetType::RealState { .. })
+        }
+    }
+
+    /// Whether the price comes from the providers, false for manually
+    /// priced, fixed income and real estate assets.
+    pub fn is_market_priced(&self) -> bool {
+        self.get_manual_price().is_none()
+            && !matches!(self, AssetType::FixedIncome { .. } | AssetType::RealState { .. })
+    }
+
+    /// Value of the held units of assets valued without a market price,
+    /// fixed income by its terms and real estate by its appraisals.
+    pub fn get_model_value(&self, at: EpochMillis, held_units: u128) -> Result<Option<(u128, FiatCurrency)>, AppErrors> {
+        match &self {
+            AssetType::FixedIncome { terms, .. } => Ok(Some((terms.get_value_at(at, held_units), terms.currency.clone()))),
+            AssetType::RealState { property, .. } => {
+                let (value, currency) = property.get_value_at(at)?;
+                Ok(Some((value * held_units, currency)))
             },
-        };
-        MarketSnapshot::new(
-            Now::new().to_epoch_millis(),
-            format!("{}", &self),
-            None,
-            FiatCurrency::MXN,
-            None,
-            None,
-            None,
-            median as u64
-        )
+            _ => Ok(None)
+        }
+    }
+
+    /// The manual price of one unit, only other assets have one.
+    pub fn get_manual_price(&self) -> Option<(u64, FiatCurrency)> {
+        match &self {
+            AssetType::Other { unit_price: Some(unit_price), currency, .. } => Some((*unit_price, currency.clone())),
+            _ => None
+        }
     }
 }
 
+/// Returns in money of the evaluation date, every amount updated by the
+/// consumer price index since it was paid or received.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RealReturn {
+    /// Cost of the units still held, updated since each purchase.
+    pub entrance_amount: u128,
+    /// Unrealized and realized gain plus income minus expenses, after
+    /// inflation.
+    pub gain: i128,
+    /// Compound yearly real return over the holding time, like the nominal
+    /// one, in basis points.
+    pub annualized_bps: Option<i128>,
+    /// The index of the evaluation month is not published yet, the amounts
+    /// are in money of the last month published.
+    pub stale: bool
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct AssetEvaluation {
     asset_id: AssetId,
+    /// Since the first lot was acquired.
     millisec_since_purchase: EpochMillis,
+    /// Time from the first purchase to the last sell, or to now if still held.
+    holding_millis: EpochMillis,
     asset_type_str: String,
+    held_units: u128,
+    /// Cost of the units still held, at the rate of each purchase.
     entrance_amount: u128,
+    /// Dividends and other income received, at the rate of each payment.
+    income_amount: u128,
+    /// Expenses paid, at the rate of each payment.
+    expense_amount: u128,
+    /// Cost of the units still held, at the latest rate.
+    entrance_amount_latest_fx: u128,
+    /// Current market value of the units still held.
     now_amount: u128,
-    currency: FiatCurrency
+    /// Sale proceeds minus fees, only when there are sells.
+    exit_amount: Option<u128>,
+    /// exit_amount - cost of the sold units, only when there are sells.
+    realized_gain: Option<i128>,
+    cost_basis: CostBasis,
+    currency: FiatCurrency,
+    /// Compound yearly return over the holding time, CAGR, in basis points.
+    #[serde(default)]
+    annualized_bps: Option<i128>,
+    /// Only when a consumer price index of the currency is loaded.
+    #[serde(default)]
+    real_return: Option<RealReturn>
+}
+
+impl AssetEvaluation {
+    pub fn get_asset_id(&self) -> AssetId {
+        self.asset_id
+    }
+
+    pub fn get_asset_type_str(&self) -> &str {
+        &self.asset_type_str
+    }
+
+    pub fn get_entrance_amount(&self) -> u128 {
+        self.entrance_amount
+    }
+
+    pub fn get_income_amount(&self) -> u128 {
+        self.income_amount
+    }
+
+    pub fn get_expense_amount(&self) -> u128 {
+        self.expense_amount
+    }
+
+    #[allow(dead_code)]
+    pub fn get_entrance_amount_latest_fx(&self) -> u128 {
+        self.entrance_amount_latest_fx
+    }
+
+    pub fn get_now_amount(&self) -> u128 {
+        self.now_amount
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exit_amount(&self) -> Option<u128> {
+        self.exit_amount
+    }
+
+    pub fn get_realized_gain(&self) -> Option<i128> {
+        self.realized_gain
+    }
+
+    /// Cost of the units sold, zero without sells.
+    pub fn get_realized_basis(&self) -> u128 {
+        match (self.exit_amount, self.realized_gain) {
+            (Some(exit_amount), Some(realized_gain)) => (exit_amount as i128 - realized_gain) as u128,
+            _ => 0
+        }
+    }
+
+    pub fn get_holding_millis(&self) -> EpochMillis {
+        self.holding_millis
+    }
+
+    pub fn get_held_units(&self) -> u128 {
+        self.held_units
+    }
+
+    pub fn get_annualized_bps(&self) -> Option<i128> {
+        self.annualized_bps
+    }
+
+    pub fn get_real_return(&self) -> Option<&RealReturn> {
+        self.real_return.as_ref()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Asset {
     pub id: AssetId,
     fund: Fund,
+    /// Describes the whole position, every lot acquired.
     asset_type: AssetType,
-    buy: Option<Buy>,
-    sell: Option<Sell>,
+    /// Acquisition lots.
+    buys: Vec<Buy>,
+    /// Disposals, partial or total.
+    sells: Vec<Sell>,
+    /// Dividends and other cash received while holding the asset.
+    #[serde(default)]
+    income: Vec<Income>,
+    /// Cash paid while holding the asset.
+    #[serde(default)]
+    expenses: Vec<Expense>,
     owner_settings: UserSettings
 }
 
@@ -162,41 +501,456 @@ impl Asset {
             id,
             fund,
             asset_type,
-            buy: None,
-            sell: None,
+            buys: Vec::new(),
+            sells: Vec::new(),
+            income: Vec::new(),
+            expenses: Vec::new(),
             owner_settings
         }
     }
 
-    pub fn purchase(&mut self, settled_at: EpochMillis, amount: u128, currency: FiatCurrency) {
-        assert!(self.buy.is_none(), "Asset already has a Buy process.");
-        let buy = Buy::new(settled_at, "fiat_cash".to_string(), amount, currency);
-        self.buy = Some(buy);
+    fn get_bought_units(&self) -> u128 {
+        self.buys.iter().map(|buy| buy.units).sum()
     }
 
-    /// Entrance amount is the SINGLE total value the user paid. Expect fiat currancy.
-    fn get_entrance_amount(&self) -> u128 {
-        assert!(self.buy.is_some());
-        self.buy
-            .as_ref()
-            .unwrap()
-            .get_entrance_amount(self.owner_settings.fiat_currency.clone())
+    /// Buys every unit of the asset not covered by a lot yet.
+    #[allow(dead_code)]
+    pub fn purchase(&mut self, settled_at: EpochMillis, amount: u128, currency: FiatCurrency) -> Result<(), AppErrors> {
+        self.purchase_with(settled_at, Transaction::new("fiat_cash", amount, currency)?)
+    }
+
+    /// Like `purchase`, settled by any transaction.
+    pub fn purchase_with(&mut self, settled_at: EpochMillis, transaction: Transaction) -> Result<(), AppErrors> {
+        let units = self.asset_type.get_units() - self.get_bought_units();
+        if units == 0 {
+            return Err(AppErrors::InvalidOperation("Asset already has a Buy process.".to_string()));
+        }
+        self.purchase_lot(Buy::from_transaction(settled_at, units, transaction))
+    }
+
+    /// Adds an acquisition lot. Lots can't exceed the units of the asset type.
+    pub fn purchase_lot(&mut self, buy: Buy) -> Result<(), AppErrors> {
+        self.check_lot(buy.units)?;
+        self.buys.push(buy);
+        Ok(())
+    }
+
+    pub(crate) fn check_lot(&self, units: u128) -> Result<(), AppErrors> {
+        if units == 0 || self.get_bought_units() + units > self.asset_type.get_units() {
+            return Err(AppErrors::InvalidOperation(format!(
+                "Lot of {} units exceeds the {} units of the asset.", units, self.asset_type.get_units()
+            )));
+        }
+        Ok(())
     }
 
-    pub fn evaluate(&self, currency: FiatCurrency) -> AssetEvaluation {
-        let buy_settled_at = self.buy.as_ref().expect("Asset without a Buy object.").settled_at;
-        AssetEvaluation {
+    /// Disposes every unit still held. Fees are deducted from the proceeds.
+    #[allow(dead_code)]
+    pub fn sell(
+        &mut self,
+        settled_at: EpochMillis,
+        transaction_str: String,
+        amount: u128,
+        currency: FiatCurrency,
+        fees: u128
+    ) -> Result<(), AppErrors> {
+        self.sell_with(settled_at, Transaction::new(&transaction_str, amount, currency)?, fees::from_total(fees))
+    }
+
+    /// Like `sell`, settled by any transaction that exchanges the units.
+    pub fn sell_with(&mut self, settled_at: EpochMillis, transaction: Transaction, fees: Vec<Fee>) -> Result<(), AppErrors> {
+        if self.buys.is_empty() {
+            return Err(AppErrors::InvalidOperation("Asset must be purchased before selling.".to_string()));
+        }
+        let units = self.get_held_units()?;
+        if units == 0 {
+            return Err(AppErrors::InvalidOperation("Asset already has a Sell process.".to_string()));
+        }
+        self.sell_lot(Sell::from_transaction(settled_at, units, transaction, fees)?)
+    }
+
+    /// Adds a partial or total disposal. The sells are replayed with the
+    /// owner cost basis method to validate there are enough units held.
+    pub fn sell_lot(&mut self, sell: Sell) -> Result<(), AppErrors> {
+        if sell.units == 0 {
+            return Err(AppErrors::InvalidOperation("Sell of zero units.".to_string()));
+        }
+        if let Some(lot) = sell.lot {
+            if lot >= self.buys.len() {
+                return Err(AppErrors::InvalidOperation(format!("Lot {} does not exist.", lot)));
+            }
+        }
+        self.sells.push(sell);
+        if let Err(err) = self.get_units_report() {
+            self.sells.pop();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Records cash received for holding the asset, after the first buy.
+    pub fn add_income(&mut self, income: Income) -> Result<(), AppErrors> {
+        if !self.asset_type.accepts_income(income.kind) {
+            return Err(AppErrors::InvalidOperation(format!(
+                "{} income is not valid for {}.", income.kind, self.asset_type
+            )));
+        }
+        if income.settled_at < self.get_first_buy()?.settled_at {
+            return Err(AppErrors::InvalidOperation("Income received before the asset was bought.".to_string()));
+        }
+        self.income.push(income);
+        Ok(())
+    }
+
+    /// Records cash paid for holding the asset, after the first buy.
+    pub fn add_expense(&mut self, expense: Expense) -> Result<(), AppErrors> {
+        if expense.settled_at < self.get_first_buy()?.settled_at {
+            return Err(AppErrors::InvalidOperation("Expense paid before the asset was bought.".to_string()));
+        }
+        self.expenses.push(expense);
+        Ok(())
+    }
+
+    /// Adds a dated valuation to a real estate asset.
+    pub fn add_appraisal(&mut self, appraisal: Appraisal) -> Result<(), AppErrors> {
+        match &mut self.asset_type {
+            AssetType::RealState { property, .. } => property.add_appraisal(appraisal),
+            _ => Err(AppErrors::InvalidOperation(format!(
+                "Only real estate has appraisals, asset {} is {}.", self.id, self.asset_type
+            )))
+        }
+    }
+
+    pub fn get_fund(&self) -> &Fund {
+        &self.fund
+    }
+
+    pub fn get_asset_type(&self) -> &AssetType {
+        &self.asset_type
+    }
+
+    pub fn get_buys(&self) -> &Vec<Buy> {
+        &self.buys
+    }
+
+    pub fn get_sells(&self) -> &Vec<Sell> {
+        &self.sells
+    }
+
+    pub fn get_held_units(&self) -> Result<u128, AppErrors> {
+        Ok(self.get_units_report()?.held_units())
+    }
+
+    /// Every fee component paid on the buys, sells, income and expenses,
+    /// with the settlement and currency of its transaction.
+    pub fn get_fees(&self) -> Vec<(EpochMillis, FiatCurrency, Fee)> {
+        let buys = self.buys.iter().map(|buy| (buy.settled_at, buy.get_transaction(), buy.get_transaction().get_fee_components().to_vec()));
+        let sells = self.sells.iter().map(|sell| (sell.settled_at, sell.get_transaction(), sell.get_fee_components()));
+        let income = self.income.iter().map(|income| (income.settled_at, income.get_transaction(), income.get_transaction().get_fee_components().to_vec()));
+        let expenses = self.expenses.iter().map(|expense| (expense.settled_at, expense.get_transaction(), expense.get_transaction().get_fee_components().to_vec()));
+        buys.chain(sells)
+            .chain(income)
+            .chain(expenses)
+            .flat_map(|(settled_at, transaction, fees)| {
+                let currency = transaction.get_amount_currency().1;
+                fees.into_iter().map(move |fee| (settled_at, currency.clone(), fee))
+            })
+            .collect()
+    }
+
+    /// Sold means every unit bought was disposed.
+    pub fn is_sold(&self) -> bool {
+        !self.sells.is_empty() && matches!(self.get_held_units(), Ok(0))
+    }
+
+    pub(crate) fn set_cost_basis(&mut self, cost_basis: CostBasis) {
+        self.owner_settings.cost_basis = cost_basis;
+    }
+
+    pub(crate) fn set_fiat_currency(&mut self, fiat_currency: FiatCurrency) {
+        self.owner_settings.fiat_currency = fiat_currency;
+    }
+
+    fn get_first_buy(&self) -> Result<&Buy, AppErrors> {
+        self.buys
+            .iter()
+            .min_by_key(|buy| buy.settled_at)
+            .ok_or_else(|| AppErrors::InvalidOperation(format!("Asset {} without a Buy object.", self.id)))
+    }
+
+    fn get_cost_basis_report(&self, fx_rates: &FxRates, rate_date: RateDate) -> Result<CostBasisReport, AppErrors> {
+        self.get_cost_basis_report_in(&self.owner_settings.fiat_currency, fx_rates, rate_date)
+    }
+
+    /// Cost basis of the lots and sells with the amounts in `currency`.
+    pub(crate) fn get_cost_basis_report_in(
+        &self,
+        currency: &FiatCurrency,
+        fx_rates: &FxRates,
+        rate_date: RateDate
+    ) -> Result<CostBasisReport, AppErrors> {
+        cost_basis::apply(
+            self.owner_settings.cost_basis,
+            &self.buys,
+            &self.sells,
+            |buy| buy.get_entrance_amount(currency, fx_rates, rate_date),
+            |sell| sell.get_exit_amount(currency, fx_rates, rate_date)
+        )
+    }
+
+    /// Replays the sells without exchange rates. Only the units are
+    /// meaningful, amounts stay in the currency of each transaction.
+    fn get_units_report(&self) -> Result<CostBasisReport, AppErrors> {
+        cost_basis::apply(
+            self.owner_settings.cost_basis,
+            &self.buys,
+            &self.sells,
+            |buy| Ok(buy.get_transaction_amount_currency().0),
+            |sell| Ok(sell.get_transaction_amount_currency().0)
+        )
+    }
+
+    /// Entrance amount is the cost of the units still held, following the
+    /// owner cost basis method. Sold units are valued by their exit amount,
+    /// the price sheet is only used for the units still held. Fixed income
+    /// and real estate are valued by their terms and appraisals instead.
+    /// Transactions in another currency use the rate of their settlement,
+    /// the market price uses the latest rate.
+    pub fn evaluate(&self, price_provider: &dyn PriceProvider, fx_rates: &FxRates) -> Result<AssetEvaluation, AppErrors> {
+        self.evaluate_with_inflation(price_provider, fx_rates, None)
+    }
+
+    /// Like `evaluate`, with the real return when the consumer price index
+    /// of the owner currency is given.
+    pub fn evaluate_with_inflation(
+        &self,
+        price_provider: &dyn PriceProvider,
+        fx_rates: &FxRates,
+        inflation: Option<&InflationIndex>
+    ) -> Result<AssetEvaluation, AppErrors> {
+        let first_buy_at = self.get_first_buy()?.settled_at;
+        let report = self.get_cost_basis_report(fx_rates, RateDate::Settlement)?;
+        let held_units = report.held_units();
+        let income_amount = self.income
+            .iter()
+            .map(|income| income.get_amount(&self.owner_settings.fiat_currency, fx_rates, RateDate::Settlement))
+            .sum::<Result<u128, AppErrors>>()?;
+        let expense_amount = self.expenses
+            .iter()
+            .map(|expense| expense.get_amount(&self.owner_settings.fiat_currency, fx_rates, RateDate::Settlement))
+            .sum::<Result<u128, AppErrors>>()?;
+        let entrance_amount_latest_fx = self
+            .get_cost_basis_report(fx_rates, RateDate::Latest)?
+            .held_cost();
+
+        let now = Now::new().to_epoch_millis();
+        let now_amount = if held_units == 0 {
+            0
+        } else if let Some((value, currency)) = self.asset_type.get_model_value(now, held_units)? {
+            fx_rates.convert(value, &currency, &self.owner_settings.fiat_currency, None)?
+        } else {
+            let snapshot = self.get_market_price(price_provider)?;
+            let unit_price = fx_rates.convert(
+                snapshot.get_median() as u128,
+                snapshot.get_currency(),
+                &self.owner_settings.fiat_currency,
+                None
+            )?;
+            proportional(self.asset_type.get_value(unit_price)?, held_units, self.asset_type.get_units())
+        };
+        let holding_millis = match report.disposals.last() {
+            Some(disposal) if held_units == 0 => disposal.settled_at - first_buy_at,
+            _ => Now::get_millis_since(first_buy_at)
+        };
+        let (exit_amount, realized_gain) = if report.disposals.is_empty() {
+            (None, None)
+        } else {
+            (
+                Some(report.exit_amount()),
+                Some(report.exit_amount() as i128 - report.realized_basis() as i128)
+            )
+        };
+
+        let invested = report.held_cost() + report.realized_basis();
+        let returned = (now_amount + report.exit_amount() + income_amount) as i128 - expense_amount as i128;
+        let millisec_since_purchase = now.saturating_sub(first_buy_at);
+        let real_return = match inflation {
+            Some(index) => Some(self.get_real_return(&report, index, fx_rates, now, now_amount, holding_millis)?),
+            None => None
+        };
+
+        Ok(AssetEvaluation {
             asset_id: self.id,
-            millisec_since_purchase: Now::get_millis_since(buy_settled_at),
+            millisec_since_purchase,
+            holding_millis,
             asset_type_str: format!("{}", self.asset_type),
-            entrance_amount: self.get_entrance_amount(),
-            now_amount: self.get_market_price(Some(self.price_sheet)), // TODO: First get the market update.
-            currency: self.owner_settings.fiat_currency.clone()
+            held_units,
+            entrance_amount: report.held_cost(),
+            income_amount,
+            expense_amount,
+            entrance_amount_latest_fx,
+            now_amount,
+            exit_amount,
+            realized_gain,
+            cost_basis: self.owner_settings.cost_basis,
+            currency: self.owner_settings.fiat_currency.clone(),
+            annualized_bps: annualized_bps(invested, returned, holding_millis),
+            real_return
+        })
+    }
+
+    /// Every lot, sell, income and expense of the `report` updated to `now`.
+    fn get_real_return(
+        &self,
+        report: &CostBasisReport,
+        index: &InflationIndex,
+        fx_rates: &FxRates,
+        now: EpochMillis,
+        now_amount: u128,
+        holding_millis: EpochMillis
+    ) -> Result<RealReturn, AppErrors> {
+        let currency = &self.owner_settings.fiat_currency;
+        let entrance_amount = report.lots
+            .iter()
+            .map(|lot| index.adjust(lot.cost, lot.settled_at, now))
+            .sum::<Result<u128, AppErrors>>()?;
+        let mut realized_basis = 0;
+        let mut exit_amount = 0;
+        for disposal in report.disposals.iter() {
+            exit_amount += index.adjust(disposal.exit_amount, disposal.settled_at, now)?;
+            for piece in disposal.pieces.iter() {
+                realized_basis += index.adjust(piece.cost, piece.acquired_at, now)?;
+            }
+        }
+        let income_amount = self.income
+            .iter()
+            .map(|income| index.adjust(income.get_amount(currency, fx_rates, RateDate::Settlement)?, income.settled_at, now))
+            .sum::<Result<u128, AppErrors>>()?;
+        let expense_amount = self.expenses
+            .iter()
+            .map(|expense| index.adjust(expense.get_amount(currency, fx_rates, RateDate::Settlement)?, expense.settled_at, now))
+            .sum::<Result<u128, AppErrors>>()?;
+
+        let invested = entrance_amount + realized_basis;
+        let gain = (now_amount + exit_amount + income_amount) as i128 - (invested + expense_amount) as i128;
+        Ok(RealReturn {
+            entrance_amount,
+            gain,
+            annualized_bps: annualized_bps(invested, invested as i128 + gain, holding_millis),
+            stale: index.is_stale_at(now)
+        })
+    }
+
+    /// Units held after the buys and sells settled until the date.
+    pub fn get_held_units_at(&self, at: EpochMillis) -> u128 {
+        let bought: u128 = self.buys.iter().filter(|buy| buy.settled_at <= at).map(|buy| buy.units).sum();
+        let sold: u128 = self.sells.iter().filter(|sell| sell.settled_at <= at).map(|sell| sell.units).sum();
+        bought.saturating_sub(sold)
+    }
+
+    /// Value of `units` at the date in the owner currency, at the rate of
+    /// the date. Fixed income and real estate are valued by their terms and
+    /// appraisals. A manual price wins over the history, as in
+    /// `get_market_price`, the rest use the last price on or before the date.
+    pub fn get_value_at(
+        &self,
+        at: EpochMillis,
+        units: u128,
+        price_history: &PriceHistory,
+        fx_rates: &FxRates
+    ) -> Result<u128, AppErrors> {
+        let currency = &self.owner_settings.fiat_currency;
+        if units == 0 {
+            return Ok(0);
+        }
+        if let Some((value, value_currency)) = self.asset_type.get_model_value(at, units)? {
+            return fx_rates.convert(value, &value_currency, currency, Some(at));
+        }
+        let market_key = self.asset_type.get_market_key()?;
+        let (unit_price, price_currency) = match self.asset_type.get_manual_price() {
+            Some(manual_price) => manual_price,
+            None => price_history
+                .price_at(&market_key, at)
+                .map(|snapshot| (snapshot.get_median(), snapshot.get_currency().clone()))
+                .ok_or_else(|| AppErrors::MissingPrice(format!(
+                    "{} at {}", market_key, Now::new_from_epoch_millis(at).to_date_string()
+                )))?
+        };
+        let unit_price = fx_rates.convert(unit_price as u128, &price_currency, currency, Some(at))?;
+        Ok(proportional(self.asset_type.get_value(unit_price)?, units, self.asset_type.get_units()))
+    }
+
+    /// Money in and out of the asset in the owner currency, at the rate of
+    /// each settlement: buys and expenses are negative, sells and income
+    /// positive.
+    pub fn get_cash_flows(&self, fx_rates: &FxRates) -> Result<Vec<(EpochMillis, i128)>, AppErrors> {
+        let currency = &self.owner_settings.fiat_currency;
+        let mut flows = Vec::new();
+        for buy in self.buys.iter() {
+            flows.push((buy.settled_at, -(buy.get_entrance_amount(currency, fx_rates, RateDate::Settlement)? as i128)));
+        }
+        for sell in self.sells.iter() {
+            flows.push((sell.settled_at, sell.get_exit_amount(currency, fx_rates, RateDate::Settlement)? as i128));
+        }
+        flows.extend(self.get_payment_flows(fx_rates)?);
+        Ok(flows)
+    }
+
+    /// Only the income, positive, and the expenses, negative.
+    pub fn get_payment_flows(&self, fx_rates: &FxRates) -> Result<Vec<(EpochMillis, i128)>, AppErrors> {
+        let currency = &self.owner_settings.fiat_currency;
+        let mut flows = Vec::new();
+        for income in self.income.iter() {
+            flows.push((income.settled_at, income.get_amount(currency, fx_rates, RateDate::Settlement)? as i128));
+        }
+        for expense in self.expenses.iter() {
+            flows.push((expense.settled_at, -(expense.get_amount(currency, fx_rates, RateDate::Settlement)? as i128)));
         }
+        Ok(flows)
     }
 
-    // TODO: implement other ways to get the price
-    pub fn get_market_price(&self, use_price_sheet: Option<PriceSheet>) -> MarketSnapshot {
-        self.asset_type.naive_market_price(use_price_sheet.unwrap())
+    /// Price of one unit of the market of the asset. A manual price wins
+    /// over the provider, fixed income is the value of one title by its terms
+    /// and real estate the appraised value.
+    pub fn get_market_price(&self, price_provider: &dyn PriceProvider) -> Result<MarketSnapshot, AppErrors> {
+        let market_key = self.asset_type.get_market_key()?;
+        let now = Now::new().to_epoch_millis();
+        let (source, unit_price, currency) = match (&self.asset_type, self.asset_type.get_manual_price()) {
+            (AssetType::FixedIncome { terms, .. }, _) => {
+                let value = terms.get_value_at(now, 1);
+                (
+                    if terms.kind == FixedIncomeKind::Cetes { "yield" } else { "accrued_interest" },
+                    u64::try_from(value).map_err(|_| AppErrors::MalformedAmount(format!("{} = {}", market_key, value)))?,
+                    terms.currency.clone()
+                )
+            },
+            (AssetType::RealState { property, .. }, _) => {
+                let (value, currency) = property.get_value_at(now)?;
+                let value = u64::try_from(value).map_err(|_| AppErrors::MalformedAmount(format!("{} = {}", market_key, value)))?;
+                ("appraisal", value, currency)
+            },
+            (_, Some((unit_price, currency))) => ("manual", unit_price, currency),
+            _ => return price_provider.get_price(&market_key)
+        };
+        Ok(MarketSnapshot::new(now, market_key, Some(source.to_string()), currency, None, None, None, unit_price))
+    }
+
+    /// Sets or clears the manual unit price of an other asset.
+    pub fn set_manual_price(&mut self, price: Option<(u64, FiatCurrency)>) -> Result<(), AppErrors> {
+        match &mut self.asset_type {
+            AssetType::Other { unit_price, currency, .. } => {
+                match price {
+                    Some((price, price_currency)) => {
+                        *unit_price = Some(price);
+                        *currency = price_currency;
+                    },
+                    None => *unit_price = None
+                }
+                Ok(())
+            },
+            _ => Err(AppErrors::InvalidOperation(format!(
+                "Only other assets have a manual price, asset {} is {}.", self.id, self.asset_type
+            )))
+        }
     }
 }
@@ -0,0 +1,131 @@
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use crate::errors::AppErrors;
+use crate::models::FiatCurrency;
+use crate::types::{AssetId, EpochMillis, FundName};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CashMovementKind {
+    Deposit,
+    Withdrawal,
+    /// From another account, by name.
+    TransferIn(String),
+    /// To another account, by name.
+    TransferOut(String),
+    /// Paid for a buy of the asset.
+    Buy(AssetId),
+    /// Proceeds of a sell of the asset, net of fees.
+    Sell(AssetId)
+}
+
+impl CashMovementKind {
+    /// Credits add to the balance, debits subtract from it.
+    pub fn is_credit(&self) -> bool {
+        matches!(self, CashMovementKind::Deposit | CashMovementKind::TransferIn(_) | CashMovementKind::Sell(_))
+    }
+}
+
+impl fmt::Display for CashMovementKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            CashMovementKind::Deposit => write!(f, "DEPOSIT"),
+            CashMovementKind::Withdrawal => write!(f, "WITHDRAWAL"),
+            CashMovementKind::TransferIn(account) => write!(f, "TRANSFER FROM {}", account),
+            CashMovementKind::TransferOut(account) => write!(f, "TRANSFER TO {}", account),
+            CashMovementKind::Buy(asset_id) => write!(f, "BUY ASSET {}", asset_id),
+            CashMovementKind::Sell(asset_id) => write!(f, "SELL ASSET {}", asset_id),
+        }
+    }
+}
+
+/// Amounts are in the currency of the account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CashMovement {
+    pub kind: CashMovementKind,
+    pub settled_at: EpochMillis,
+    pub amount: u128,
+    pub note: Option<String>
+}
+
+impl CashMovement {
+    pub fn new(kind: CashMovementKind, settled_at: EpochMillis, amount: u128) -> Self {
+        CashMovement { kind, settled_at, amount, note: None }
+    }
+
+    pub fn with_note(mut self, note: Option<String>) -> Self {
+        self.note = note;
+        self
+    }
+
+    pub fn get_signed_amount(&self) -> i128 {
+        if self.kind.is_credit() { self.amount as i128 } else { -(self.amount as i128) }
+    }
+}
+
+/// A bank account or cash position of a fund, in a single currency.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CashAccount {
+    name: String,
+    fund: FundName,
+    currency: FiatCurrency,
+    /// Sorted by date.
+    movements: Vec<CashMovement>
+}
+
+impl CashAccount {
+    pub fn new(name: String, fund: FundName, currency: FiatCurrency) -> Self {
+        CashAccount { name, fund, currency, movements: Vec::new() }
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_fund(&self) -> &FundName {
+        &self.fund
+    }
+
+    pub fn get_currency(&self) -> &FiatCurrency {
+        &self.currency
+    }
+
+    pub fn get_movements(&self) -> &Vec<CashMovement> {
+        &self.movements
+    }
+
+    pub fn get_balance(&self) -> u128 {
+        self.movements.iter().map(|movement| movement.get_signed_amount()).sum::<i128>() as u128
+    }
+
+    /// Balance after every movement settled up to `at`.
+    pub fn get_balance_at(&self, at: EpochMillis) -> u128 {
+        self.movements
+            .iter()
+            .take_while(|movement| movement.settled_at <= at)
+            .map(|movement| movement.get_signed_amount())
+            .sum::<i128>() as u128
+    }
+
+    /// Adds a movement in its date. The balance can't be negative at any
+    /// date, a debit needs the cash to be there when it settles.
+    pub fn add_movement(&mut self, movement: CashMovement) -> Result<(), AppErrors> {
+        if movement.amount == 0 {
+            return Err(AppErrors::InvalidOperation(format!("Cash movement of zero in account {}.", self.name)));
+        }
+        let index = self.movements.partition_point(|recorded| recorded.settled_at <= movement.settled_at);
+        self.movements.insert(index, movement);
+        let mut balance: i128 = 0;
+        for recorded in self.movements.iter() {
+            balance += recorded.get_signed_amount();
+            if balance < 0 {
+                let movement = self.movements.remove(index);
+                return Err(AppErrors::InvalidOperation(format!(
+                    "Insufficient cash in account {} for {}.", self.name, movement.kind
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,865 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
+
+use clap::{Parser, Subcommand};
+
+use crate::app::App;
+use crate::asset::AssetType;
+use crate::cash::{CashMovement, CashMovementKind};
+use crate::errors::AppErrors;
+use crate::fees::Fee;
+use crate::cost_basis::CostBasis;
+use crate::inflation::InflationIndex;
+use crate::net_worth::Interval;
+use crate::models::{AppEnv, Buy, Expense, FiatCurrency, Income, IncomeKind, Sell};
+use crate::price_history::PriceHistory;
+use crate::price_provider::{CsvPriceProvider, HttpPriceProvider, PriceProviders};
+use crate::real_estate::Appraisal;
+use crate::transaction::Transaction;
+use crate::types::{AssetId, EpochMillis, FundName};
+use crate::user::User;
+use crate::utils::now::Now;
+use crate::utils::{format_amount, parse_decimal};
+
+#[derive(Parser, Debug)]
+#[command(name = "asset-manager", about = "Asset Manager for the Mina Libertad portfolio.")]
+pub(crate) struct Cli {
+    /// Environment, dev or prod. Files are stored under ./files/<env>/.
+    #[arg(long, default_value = "dev")]
+    pub(crate) env: AppEnv,
+
+    #[arg(long, short, env = "ASSET_MANAGER_USER")]
+    pub(crate) user: String,
+
+    /// Price sheet file. Default: ./files/<env>/naive_prices_mxn.json
+    #[arg(long)]
+    price_sheet: Option<String>,
+
+    /// Price sources in fallback order: sheet, csv:<file> or http:<url>.
+    /// `{market_key}` in the url is replaced by the market. Default: sheet.
+    #[arg(long = "price-source")]
+    price_sources: Vec<String>,
+
+    /// Cached prices older than this are refreshed from the price sources.
+    #[arg(long, default_value_t = 24)]
+    max_price_age_hours: u64,
+
+    /// Market file. Default: ./files/<env>/market.json
+    #[arg(long)]
+    market: Option<String>,
+
+    /// Exchange rates file. Default: ./files/<env>/fx_rates.json, if it exists.
+    #[arg(long)]
+    fx_rates: Option<String>,
+
+    /// Monthly INPC file, as published by INEGI. Needed by the tax report.
+    #[arg(long)]
+    inpc: Option<String>,
+
+    /// Monthly consumer price index of the user currency for the real
+    /// returns. Default: the INPC file when the user currency is MXN.
+    #[arg(long)]
+    cpi: Option<String>,
+
+    #[command(subcommand)]
+    pub(crate) command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Create a new user with an empty app state.
+    Init,
+    /// Verify the user credentials.
+    Login,
+    /// Import funds and assets from a user file.
+    Import { file: String },
+    /// Manage funds.
+    Fund {
+        #[command(subcommand)]
+        command: FundCommand,
+    },
+    /// Manage assets.
+    Asset {
+        #[command(subcommand)]
+        command: AssetCommand,
+    },
+    /// Manage the cash accounts of the funds.
+    Cash {
+        #[command(subcommand)]
+        command: CashCommand,
+    },
+    /// Manage market prices.
+    Prices {
+        #[command(subcommand)]
+        command: PricesCommand,
+    },
+    /// Show the evaluated assets.
+    Dashboard {
+        /// Print the dashboard as JSON, amounts in minor units.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Fees paid by fund and year, by component.
+    Fees,
+    /// What the funds held at a date, or their net worth every interval,
+    /// valued with the price history.
+    NetWorth {
+        /// Only the valuation at the date, %Y-%m-%d.
+        #[arg(long)]
+        at: Option<String>,
+        /// Default: the first buy or cash movement.
+        #[arg(long)]
+        from: Option<String>,
+        /// Default: today.
+        #[arg(long)]
+        to: Option<String>,
+        /// daily, weekly or monthly.
+        #[arg(long, default_value = "monthly")]
+        interval: Interval,
+        /// Write the series to a CSV file.
+        #[arg(long)]
+        export: Option<String>,
+    },
+    /// Capital gains of the disposals for the ISR, with the basis updated
+    /// by the INPC.
+    Tax {
+        /// Only the disposals of the year.
+        #[arg(long)]
+        year: Option<i32>,
+        /// Write the yearly summary to a CSV file.
+        #[arg(long)]
+        export: Option<String>,
+    },
+    /// Change the user settings.
+    Settings {
+        /// fifo, lifo, average or specific.
+        #[arg(long)]
+        cost_basis: Option<CostBasis>,
+        /// MXN, USD, EUR or CAD.
+        #[arg(long)]
+        fiat_currency: Option<String>,
+    },
+    /// Write the decrypted app state as plain JSON, outside ./files/<env>/.
+    Export {
+        file: String,
+        /// Confirm the file is not encrypted, wallet addresses and deeds
+        /// included.
+        #[arg(long)]
+        plaintext: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum FundCommand {
+    Add { name: FundName },
+    List,
+    /// Set the target allocation, `--set GOLD=40 --set BTC=60`. Percentages
+    /// must add up to 100, no targets clears them.
+    Target {
+        name: FundName,
+        #[arg(long = "set")]
+        targets: Vec<String>,
+    },
+    /// Compare the fund with its targets and suggest buys and sells.
+    Drift { name: FundName },
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum CashCommand {
+    /// Open a cash account of a fund.
+    Add {
+        name: String,
+        #[arg(long)]
+        fund: FundName,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+    },
+    List,
+    /// Every movement of the account.
+    Show { name: String },
+    Deposit {
+        name: String,
+        /// Settlement date, %Y-%m-%d.
+        #[arg(long)]
+        at: String,
+        /// In the account currency.
+        #[arg(long)]
+        amount: u128,
+        #[arg(long)]
+        note: Option<String>,
+    },
+    Withdraw {
+        name: String,
+        /// Settlement date, %Y-%m-%d.
+        #[arg(long)]
+        at: String,
+        /// In the account currency.
+        #[arg(long)]
+        amount: u128,
+        #[arg(long)]
+        note: Option<String>,
+    },
+    /// Move cash between accounts, converted at the rate of the date.
+    Transfer {
+        from: String,
+        to: String,
+        /// Settlement date, %Y-%m-%d.
+        #[arg(long)]
+        at: String,
+        /// In the currency of the `from` account.
+        #[arg(long)]
+        amount: u128,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum AssetCommand {
+    /// Add a purchased asset. `data` uses the same JSON as the user file.
+    Add {
+        #[arg(long)]
+        fund: FundName,
+        #[arg(long = "type")]
+        asset_type: String,
+        #[arg(long, default_value = "{}")]
+        data: String,
+        /// Purchase date, %Y-%m-%d.
+        #[arg(long)]
+        bought_at: String,
+        #[arg(long)]
+        amount: u128,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+        /// fiat_cash, bank_transfer, card, gift, inheritance, mining or staking.
+        #[arg(long, default_value = "fiat_cash")]
+        transaction: String,
+        /// Paid on top of the amount, part of the cost. Repeat for each
+        /// component, `--fee premium:1500 --fee commission:250`.
+        #[arg(long = "fee")]
+        fees: Vec<Fee>,
+        /// Cash account paying the buy, in the same currency.
+        #[arg(long)]
+        account: Option<String>,
+    },
+    /// Add an acquisition lot to an existing asset.
+    Buy {
+        id: AssetId,
+        /// Purchase date, %Y-%m-%d.
+        #[arg(long)]
+        bought_at: String,
+        /// Units of the lot in the asset base unit (sats, wei...).
+        #[arg(long)]
+        units: u128,
+        #[arg(long)]
+        amount: u128,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+        /// fiat_cash, bank_transfer, card, gift, inheritance, mining or staking.
+        #[arg(long, default_value = "fiat_cash")]
+        transaction: String,
+        /// Paid on top of the amount, part of the cost. Repeat for each
+        /// component, `--fee premium:1500 --fee commission:250`.
+        #[arg(long = "fee")]
+        fees: Vec<Fee>,
+        /// Cash account paying the buy, in the same currency.
+        #[arg(long)]
+        account: Option<String>,
+    },
+    List,
+    Show { id: AssetId },
+    /// Set the manual unit price of an other asset, "12.50". Without a
+    /// price the providers are used again.
+    Price {
+        id: AssetId,
+        #[arg(long)]
+        unit_price: Option<String>,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+    },
+    /// Record a dividend, coupon, rent or other income of the asset.
+    Income {
+        id: AssetId,
+        /// dividend, coupon or rent.
+        #[arg(long, default_value = "dividend")]
+        kind: IncomeKind,
+        /// Payment date, %Y-%m-%d.
+        #[arg(long)]
+        received_at: String,
+        #[arg(long)]
+        amount: u128,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+    },
+    /// Record an expense of the asset: maintenance, property taxes.
+    Expense {
+        id: AssetId,
+        /// Payment date, %Y-%m-%d.
+        #[arg(long)]
+        paid_at: String,
+        #[arg(long)]
+        amount: u128,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    /// Add an appraisal of a real estate asset.
+    Appraisal {
+        id: AssetId,
+        /// Appraisal date, %Y-%m-%d.
+        #[arg(long)]
+        appraised_at: String,
+        /// Value of the whole property.
+        #[arg(long)]
+        amount: u128,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+        #[arg(long)]
+        appraiser: Option<String>,
+    },
+    /// Swap units of a crypto asset for a lot of another one.
+    Swap {
+        from: AssetId,
+        to: AssetId,
+        /// Swap date, %Y-%m-%d.
+        #[arg(long)]
+        swapped_at: String,
+        /// Units given of `from`, in its base unit.
+        #[arg(long)]
+        given_units: u128,
+        /// Units received of `to`, in its base unit.
+        #[arg(long)]
+        received_units: u128,
+        /// Value of the swap at the date.
+        #[arg(long)]
+        amount: u128,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+        /// Network or exchange fees, part of the cost of the lot received,
+        /// `--fee network:150`.
+        #[arg(long = "fee")]
+        fees: Vec<Fee>,
+    },
+    /// Sell the asset. `amount` is the gross proceeds.
+    Sell {
+        id: AssetId,
+        /// Sell date, %Y-%m-%d.
+        #[arg(long)]
+        sold_at: String,
+        /// Units sold in the asset base unit. Default: every unit held.
+        #[arg(long)]
+        units: Option<u128>,
+        /// Lot sold, for the specific lot cost basis. Needs the units.
+        #[arg(long, requires = "units")]
+        lot: Option<usize>,
+        #[arg(long)]
+        amount: u128,
+        #[arg(long, default_value = "MXN")]
+        currency: String,
+        /// Paid on the disposal, deducted from the proceeds. Repeat for each
+        /// component, `--fee commission:250 --fee spread:900`.
+        #[arg(long = "fee")]
+        fees: Vec<Fee>,
+        #[arg(long, default_value = "fiat_cash")]
+        transaction: String,
+        /// Cash account receiving the proceeds net of fees.
+        #[arg(long)]
+        account: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum PricesCommand {
+    /// Refresh the stale prices and append them to the market file.
+    Update {
+        /// Refresh every market, even the fresh ones.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Cached price of every market held and whether it is stale.
+    Status,
+    /// Every recorded price of a market, "BTC" or "GOLD-GRAM-24K".
+    History {
+        market_key: String,
+        /// From date, %Y-%m-%d.
+        #[arg(long)]
+        from: Option<String>,
+        /// To date, %Y-%m-%d.
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Last recorded price of a market on or before the date, %Y-%m-%d.
+    At { market_key: String, date: String },
+    /// Last recorded price of every market.
+    Latest,
+}
+
+impl Cli {
+    fn price_sheet_path(&self) -> String {
+        self.price_sheet
+            .clone()
+            .unwrap_or(format!("./files/{}/naive_prices_mxn.json", self.env))
+    }
+
+    fn max_price_age_millis(&self) -> EpochMillis {
+        self.max_price_age_hours * 60 * 60 * 1000
+    }
+
+    fn market_path(&self) -> String {
+        self.market
+            .clone()
+            .unwrap_or(format!("./files/{}/market.json", self.env))
+    }
+
+    /// Builds the price providers in the order of `--price-source`. The
+    /// fresh cached prices go first when `use_cache`.
+    fn price_providers(&self, app: &mut App, use_cache: bool) -> Result<PriceProviders, AppErrors> {
+        let mut price_providers = PriceProviders::new();
+        if use_cache {
+            price_providers.push(Box::new(app.get_cached_prices(self.max_price_age_millis())));
+        }
+        let default_sources = vec!["sheet".to_string()];
+        let sources = if self.price_sources.is_empty() { &default_sources } else { &self.price_sources };
+        for source in sources {
+            let (kind, location) = source.split_once(':').unwrap_or((source.as_str(), ""));
+            match kind {
+                "sheet" => {
+                    let file_path = if location.is_empty() { self.price_sheet_path() } else { location.to_string() };
+                    app.load_price_sheet(&file_path)?;
+                    price_providers.push(Box::new(app.price_sheet.clone()));
+                },
+                "csv" => price_providers.push(Box::new(CsvPriceProvider::from_file(location)?)),
+                "http" => price_providers.push(Box::new(HttpPriceProvider::new(location))),
+                _ => return Err(AppErrors::InvalidOperation(format!("Unknown price source {}.", source)))
+            }
+        }
+        Ok(price_providers)
+    }
+
+    /// The default rates file is optional, an explicit one must exist.
+    fn load_fx_rates(&self, app: &mut App) -> Result<(), AppErrors> {
+        match &self.fx_rates {
+            Some(file_path) => app.load_fx_rates(file_path),
+            None => {
+                let file_path = format!("./files/{}/fx_rates.json", self.env);
+                if Path::new(&file_path).exists() {
+                    app.load_fx_rates(&file_path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// There is no default, the updated basis is only as good as the index.
+    fn load_inflation_index(&self) -> Result<InflationIndex, AppErrors> {
+        match &self.inpc {
+            Some(file_path) => InflationIndex::from_file(file_path),
+            None => Err(AppErrors::InvalidOperation(
+                "The tax report needs the INPC published by INEGI, --inpc <file>.".to_string()
+            ))
+        }
+    }
+
+    /// Without an index the dashboard only has nominal returns.
+    fn load_cpi(&self, app: &mut App) -> Result<(), AppErrors> {
+        match (&self.cpi, &self.inpc) {
+            (Some(file_path), _) => app.load_inflation_index(file_path),
+            (None, Some(file_path)) if *app.get_fiat_currency() == FiatCurrency::MXN => app.load_inflation_index(file_path),
+            _ => Ok(())
+        }
+    }
+
+    /// From `ASSET_MANAGER_PASSWORD`, or asked without echo. Never a flag,
+    /// it would end in the shell history and `ps`.
+    fn read_password() -> Result<String, AppErrors> {
+        match env::var("ASSET_MANAGER_PASSWORD") {
+            Ok(password) => Ok(password),
+            Err(_) => rpassword::prompt_password("Password: ").map_err(|err| AppErrors::Io(err.to_string()))
+        }
+    }
+
+    pub(crate) fn run(&self) -> Result<(), AppErrors> {
+        let password = Cli::read_password()?;
+        if let Command::Init = self.command {
+            let mut user = User::create(&self.user, &password, self.env.clone())?;
+            let app = App::new(user.clone());
+            user.new_app_state(&app, &password)?;
+            user.save()?;
+            println!("User {} created.", user.get_name());
+            return Ok(());
+        }
+
+        let mut user = User::open(&self.user, &password, self.env.clone())?;
+        let mut app = user.load_app_state(&password)?;
+
+        let changed = match &self.command {
+            Command::Init => unreachable!(),
+            Command::Login => {
+                println!("Welcome back {}.", user.get_name());
+                false
+            },
+            Command::Import { file } => {
+                self.load_fx_rates(&mut app)?;
+                app.import_user_file(file)?;
+                true
+            },
+            Command::Cash { command } => self.run_cash(&mut app, command)?,
+            Command::Fund { command } => match command {
+                FundCommand::Add { name } => {
+                    app.create_fund(name.clone())?;
+                    true
+                },
+                FundCommand::List => {
+                    let mut names: Vec<&FundName> = app.funds.keys().collect();
+                    names.sort();
+                    for name in names {
+                        println!("{}", name);
+                    }
+                    false
+                },
+                FundCommand::Target { name, targets } => {
+                    let mut weights = BTreeMap::new();
+                    for target in targets {
+                        let (asset_type_str, weight) = target
+                            .split_once('=')
+                            .ok_or_else(|| AppErrors::MalformedAmount(target.clone()))?;
+                        let bps = u32::try_from(parse_decimal(weight, 2)?)
+                            .map_err(|_| AppErrors::MalformedAmount(weight.to_string()))?;
+                        weights.insert(asset_type_str.to_string(), bps);
+                    }
+                    app.set_fund_targets(name, weights)?;
+                    true
+                },
+                FundCommand::Drift { name } => {
+                    let price_providers = self.price_providers(&mut app, true)?;
+                    self.load_fx_rates(&mut app)?;
+                    print!("{}", app.get_drift_report(name, &price_providers)?);
+                    false
+                }
+            },
+            Command::Asset { command } => self.run_asset(&mut app, command)?,
+            Command::Prices { command } => self.run_prices(&mut app, command)?,
+            Command::Dashboard { json } => {
+                let price_providers = self.price_providers(&mut app, true)?;
+                self.load_fx_rates(&mut app)?;
+                self.load_cpi(&mut app)?;
+                let price_history = PriceHistory::from_file(&self.market_path())?;
+                let dashboard = app.get_dashboard(&price_providers, &price_history)?;
+                if *json {
+                    let content = serde_json::to_string_pretty(&dashboard)
+                        .map_err(|err| AppErrors::MalformedFile(err.to_string()))?;
+                    println!("{}", content);
+                } else {
+                    print!("{}", dashboard);
+                }
+                false
+            },
+            Command::Fees => {
+                self.load_fx_rates(&mut app)?;
+                print!("{}", app.get_fees_report()?);
+                false
+            },
+            Command::NetWorth { at, from, to, interval, export } => {
+                self.load_fx_rates(&mut app)?;
+                let price_history = PriceHistory::from_file(&self.market_path())?;
+                match at {
+                    Some(at) => {
+                        let at = Now::new_from_datetime_str(at, "%Y-%m-%d")?.to_epoch_millis();
+                        print!("{}", app.get_valuation_at(at, &price_history)?);
+                    },
+                    None => {
+                        let from = match from {
+                            Some(from) => Some(Now::new_from_datetime_str(from, "%Y-%m-%d")?.to_epoch_millis()),
+                            None => None
+                        };
+                        let to = match to {
+                            Some(to) => Now::new_from_datetime_str(to, "%Y-%m-%d")?.to_epoch_millis(),
+                            None => Now::new().to_epoch_millis()
+                        };
+                        let series = app.get_net_worth_series(from, to, *interval, &price_history)?;
+                        print!("{}", series);
+                        if let Some(file_path) = export {
+                            series.export_csv(file_path)?;
+                            println!("Net worth written to {}.", file_path);
+                        }
+                    }
+                }
+                false
+            },
+            Command::Tax { year, export } => {
+                self.load_fx_rates(&mut app)?;
+                let report = app.get_tax_report(&self.load_inflation_index()?, *year)?;
+                print!("{}", report);
+                if let Some(file_path) = export {
+                    report.export_csv(file_path)?;
+                    println!("Tax summary written to {}.", file_path);
+                }
+                false
+            },
+            Command::Settings { cost_basis, fiat_currency } => {
+                if let Some(cost_basis) = cost_basis {
+                    app.set_cost_basis(*cost_basis);
+                }
+                if let Some(fiat_currency) = fiat_currency {
+                    app.set_fiat_currency(FiatCurrency::from_str(fiat_currency)?);
+                }
+                true
+            },
+            Command::Export { file, plaintext } => {
+                if !plaintext {
+                    return Err(AppErrors::InvalidOperation(
+                        "The export is not encrypted, pass --plaintext to write it anyway.".to_string()
+                    ));
+                }
+                app.export(file)?;
+                println!("Exported to {}, this file is NOT encrypted.", file);
+                false
+            }
+        };
+
+        if changed {
+            user.save_app_state(&app, &password)?;
+            user.save()?;
+        }
+        Ok(())
+    }
+
+    fn run_cash(&self, app: &mut App, command: &CashCommand) -> Result<bool, AppErrors> {
+        let changed = match command {
+            CashCommand::Add { name, fund, currency } => {
+                app.create_cash_account(fund, name.clone(), FiatCurrency::from_str(currency)?)?;
+                true
+            },
+            CashCommand::List => {
+                for account in app.get_cash_accounts() {
+                    println!(
+                        "{:<21} | {:<16} | {:>18} {:?}",
+                        account.get_name(),
+                        account.get_fund(),
+                        format_amount(account.get_balance() as i128, account.get_currency().get_decimals()),
+                        account.get_currency()
+                    );
+                }
+                false
+            },
+            CashCommand::Show { name } => {
+                let account = app.get_cash_account(name)?;
+                let decimals = account.get_currency().get_decimals();
+                for movement in account.get_movements() {
+                    println!(
+                        "{} | {:<24} | {:>18} | {}",
+                        Now::new_from_epoch_millis(movement.settled_at).to_date_string(),
+                        movement.kind.to_string(),
+                        format_amount(movement.get_signed_amount(), decimals),
+                        movement.note.clone().unwrap_or_default()
+                    );
+                }
+                println!("Balance {} {:?}", format_amount(account.get_balance() as i128, decimals), account.get_currency());
+                false
+            },
+            CashCommand::Deposit { name, at, amount, note } | CashCommand::Withdraw { name, at, amount, note } => {
+                let kind = match command {
+                    CashCommand::Deposit { .. } => CashMovementKind::Deposit,
+                    _ => CashMovementKind::Withdrawal
+                };
+                let settled_at = Now::new_from_datetime_str(at, "%Y-%m-%d")?.to_epoch_millis();
+                app.add_cash_movement(name, CashMovement::new(kind, settled_at, *amount).with_note(note.clone()))?;
+                true
+            },
+            CashCommand::Transfer { from, to, at, amount } => {
+                self.load_fx_rates(app)?;
+                let settled_at = Now::new_from_datetime_str(at, "%Y-%m-%d")?.to_epoch_millis();
+                app.transfer_cash(from, to, settled_at, *amount)?;
+                true
+            }
+        };
+        Ok(changed)
+    }
+
+    fn run_prices(&self, app: &mut App, command: &PricesCommand) -> Result<bool, AppErrors> {
+        match command {
+            PricesCommand::Update { force } => {
+                let price_providers = self.price_providers(app, false)?;
+                let max_age_millis = if *force { 0 } else { self.max_price_age_millis() };
+                for market_key in app.update_market(&price_providers, &self.market_path(), max_age_millis)? {
+                    println!("{} updated.", market_key);
+                }
+                return Ok(true);
+            },
+            PricesCommand::Status => {
+                let stale_market_keys = app.get_stale_market_keys(self.max_price_age_millis())?;
+                for market_key in app.get_held_market_keys()? {
+                    let status = if stale_market_keys.contains(&market_key) { "STALE" } else { "FRESH" };
+                    match app.get_latest_price(&market_key) {
+                        Some(snapshot) => println!(
+                            "{} | {:<16} | {:>16} {:?} | {}",
+                            Now::new_from_epoch_millis(snapshot.get_timestamp()).to_date_string(),
+                            market_key,
+                            snapshot.get_median(),
+                            snapshot.get_currency(),
+                            status
+                        ),
+                        None => println!("{:<10} | {:<16} | MISSING", "-", market_key)
+                    }
+                }
+                return Ok(false);
+            },
+            _ => {}
+        }
+
+        let price_history = PriceHistory::from_file(&self.market_path())?;
+        let snapshots = match command {
+            PricesCommand::Update { .. } | PricesCommand::Status => unreachable!(),
+            PricesCommand::History { market_key, from, to } => {
+                let from = match from {
+                    Some(from) => Now::new_from_datetime_str(from, "%Y-%m-%d")?.to_epoch_millis(),
+                    None => 0
+                };
+                let to = match to {
+                    Some(to) => Now::new_from_datetime_str(to, "%Y-%m-%d")?.to_epoch_millis(),
+                    None => EpochMillis::MAX
+                };
+                price_history.range(market_key, from, to)
+            },
+            PricesCommand::At { market_key, date } => {
+                let at = Now::new_from_datetime_str(date, "%Y-%m-%d")?.to_epoch_millis();
+                let snapshot = price_history
+                    .price_at(market_key, at)
+                    .ok_or_else(|| AppErrors::MissingPrice(format!("{} at {}", market_key, date)))?;
+                vec![snapshot]
+            },
+            PricesCommand::Latest => price_history
+                .get_market_keys()
+                .into_iter()
+                .filter_map(|market_key| price_history.latest(market_key))
+                .collect()
+        };
+        for snapshot in snapshots {
+            println!(
+                "{} | {:<16} | {:>16} {:?}",
+                Now::new_from_epoch_millis(snapshot.get_timestamp()).to_date_string(),
+                snapshot.get_market_key(),
+                snapshot.get_median(),
+                snapshot.get_currency()
+            );
+        }
+        Ok(false)
+    }
+
+    fn run_asset(&self, app: &mut App, command: &AssetCommand) -> Result<bool, AppErrors> {
+        let changed = match command {
+            AssetCommand::Add { fund, asset_type, data, bought_at, amount, currency, transaction, fees, account } => {
+                let fund = app.get_fund(fund)?;
+                let data = json::parse(data)
+                    .map_err(|err| AppErrors::MalformedFile(format!("asset data: {}", err)))?;
+                let asset_type = AssetType::new(asset_type.clone(), data)?;
+                let settled_at = Now::new_from_datetime_str(bought_at, "%Y-%m-%d")?.to_epoch_millis();
+                let transaction = Transaction::new(transaction, *amount, FiatCurrency::from_str(currency)?)?.with_fees(fees.clone());
+                let asset_id = app.create_new_asset(fund, asset_type);
+                app.get_asset_mut(asset_id)?.purchase_with(settled_at, transaction)?;
+                if let Some(account) = account {
+                    let buy = app.get_asset(asset_id)?.get_buys()[0].clone();
+                    app.pay_buy(account, asset_id, &buy)?;
+                }
+                println!("Asset {} created.", asset_id);
+                true
+            },
+            AssetCommand::Buy { id, bought_at, units, amount, currency, transaction, fees, account } => {
+                let settled_at = Now::new_from_datetime_str(bought_at, "%Y-%m-%d")?.to_epoch_millis();
+                let transaction = Transaction::new(transaction, *amount, FiatCurrency::from_str(currency)?)?.with_fees(fees.clone());
+                let asset = app.get_asset_mut(*id)?;
+                asset.purchase_lot(Buy::from_transaction(settled_at, *units, transaction))?;
+                if let Some(account) = account {
+                    let buy = asset.get_buys().last().unwrap().clone();
+                    app.pay_buy(account, *id, &buy)?;
+                }
+                println!("Lot added to asset {}.", id);
+                true
+            },
+            AssetCommand::List => {
+                for asset in app.get_assets() {
+                    println!(
+                        "{:>4} | {:<16} | {:<10} | {:>24} | {}",
+                        asset.id,
+                        asset.get_fund().get_name(),
+                        asset.get_asset_type().to_string(),
+                        asset.get_held_units()?,
+                        if asset.is_sold() { "SOLD" } else { "HOLD" }
+                    );
+                }
+                false
+            },
+            AssetCommand::Show { id } => {
+                println!("{:#?}", app.get_asset(*id)?);
+                false
+            },
+            AssetCommand::Income { id, kind, received_at, amount, currency } => {
+                let settled_at = Now::new_from_datetime_str(received_at, "%Y-%m-%d")?.to_epoch_millis();
+                let currency = FiatCurrency::from_str(currency)?;
+                let income = Income::new(*kind, settled_at, "fiat_cash".to_string(), *amount, currency)?;
+                app.get_asset_mut(*id)?.add_income(income)?;
+                println!("{} added to asset {}.", kind, id);
+                true
+            },
+            AssetCommand::Expense { id, paid_at, amount, currency, description } => {
+                let settled_at = Now::new_from_datetime_str(paid_at, "%Y-%m-%d")?.to_epoch_millis();
+                let currency = FiatCurrency::from_str(currency)?;
+                let expense = Expense::new(description.clone(), settled_at, "fiat_cash".to_string(), *amount, currency)?;
+                app.get_asset_mut(*id)?.add_expense(expense)?;
+                println!("Expense added to asset {}.", id);
+                true
+            },
+            AssetCommand::Appraisal { id, appraised_at, amount, currency, appraiser } => {
+                let appraisal = Appraisal {
+                    appraised_at: Now::new_from_datetime_str(appraised_at, "%Y-%m-%d")?.to_epoch_millis(),
+                    amount: *amount,
+                    currency: FiatCurrency::from_str(currency)?,
+                    appraiser: appraiser.clone()
+                };
+                app.get_asset_mut(*id)?.add_appraisal(appraisal)?;
+                println!("Appraisal added to asset {}.", id);
+                true
+            },
+            AssetCommand::Price { id, unit_price, currency } => {
+                let currency = FiatCurrency::from_str(currency)?;
+                let price = match unit_price {
+                    Some(unit_price) => Some((
+                        u64::try_from(parse_decimal(unit_price, currency.get_decimals())?)
+                            .map_err(|_| AppErrors::MalformedAmount(unit_price.to_string()))?,
+                        currency
+                    )),
+                    None => None
+                };
+                app.get_asset_mut(*id)?.set_manual_price(price)?;
+                true
+            },
+            AssetCommand::Swap { from, to, swapped_at, given_units, received_units, amount, currency, fees } => {
+                let settled_at = Now::new_from_datetime_str(swapped_at, "%Y-%m-%d")?.to_epoch_millis();
+                let value = (*amount, FiatCurrency::from_str(currency)?);
+                app.swap_assets(*from, *to, settled_at, (*given_units, *received_units), value, fees.clone())?;
+                println!("Asset {} swapped for asset {}.", from, to);
+                true
+            },
+            AssetCommand::Sell { id, sold_at, units, lot, amount, currency, fees, transaction, account } => {
+                let settled_at = Now::new_from_datetime_str(sold_at, "%Y-%m-%d")?.to_epoch_millis();
+                let transaction = Transaction::new(transaction, *amount, FiatCurrency::from_str(currency)?)?;
+                let asset = app.get_asset_mut(*id)?;
+                match units {
+                    Some(units) => {
+                        let sell = Sell::from_transaction(settled_at, *units, transaction, fees.clone())?;
+                        asset.sell_lot(sell.with_lot(*lot))?;
+                    },
+                    None => asset.sell_with(settled_at, transaction, fees.clone())?
+                }
+                if let Some(account) = account {
+                    let sell = asset.get_sells().last().unwrap().clone();
+                    app.collect_sell(account, *id, &sell)?;
+                }
+                println!("Asset {} sold.", id);
+                true
+            }
+        };
+        Ok(changed)
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::errors::AppErrors;
+use crate::models::{Buy, Sell};
+use crate::types::EpochMillis;
+use crate::utils::proportional;
+
+/// How the cost of the sold units is taken from the acquisition lots.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CostBasis {
+    /// Oldest lots are sold first.
+    Fifo,
+    /// Newest lots are sold first.
+    Lifo,
+    /// Every sold unit costs the average of the units held at the sale.
+    Average,
+    /// Each sell names the lot it disposes.
+    SpecificLot
+}
+
+impl FromStr for CostBasis {
+    type Err = AppErrors;
+
+    fn from_str(input: &str) -> Result<CostBasis, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "fifo" => Ok(CostBasis::Fifo),
+            "lifo" => Ok(CostBasis::Lifo),
+            "average" | "avg" => Ok(CostBasis::Average),
+            "specific" | "specific_lot" => Ok(CostBasis::SpecificLot),
+            _ => Err(AppErrors::InvalidOperation(format!("Unknown cost basis method {}.", input)))
+        }
+    }
+}
+
+impl fmt::Display for CostBasis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            CostBasis::Fifo => "FIFO",
+            CostBasis::Lifo => "LIFO",
+            CostBasis::Average => "AVERAGE",
+            CostBasis::SpecificLot => "SPECIFIC_LOT"
+        })
+    }
+}
+
+/// Remaining units and cost of a single acquisition lot.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LotBalance {
+    pub settled_at: EpochMillis,
+    pub units: u128,
+    pub cost: u128
+}
+
+/// Units and cost taken from an acquisition lot by a sell.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LotPiece {
+    pub acquired_at: EpochMillis,
+    pub units: u128,
+    pub cost: u128
+}
+
+/// Basis and proceeds of a single sell, in the order of the sells.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Disposal {
+    pub settled_at: EpochMillis,
+    pub units: u128,
+    pub basis: u128,
+    pub exit_amount: u128,
+    /// The lots sold, their costs add up to the basis.
+    pub pieces: Vec<LotPiece>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CostBasisReport {
+    pub lots: Vec<LotBalance>,
+    pub disposals: Vec<Disposal>
+}
+
+impl CostBasisReport {
+    pub fn held_units(&self) -> u128 {
+        self.lots.iter().map(|lot| lot.units).sum()
+    }
+
+    /// Entrance amount of the units still held.
+    pub fn held_cost(&self) -> u128 {
+        self.lots.iter().map(|lot| lot.cost).sum()
+    }
+
+    pub fn realized_basis(&self) -> u128 {
+        self.disposals.iter().map(|disposal| disposal.basis).sum()
+    }
+
+    pub fn exit_amount(&self) -> u128 {
+        self.disposals.iter().map(|disposal| disposal.exit_amount).sum()
+    }
+}
+
+/// Takes `units` out of the lot, returning the proportional cost.
+fn consume(lot: &mut LotBalance, units: u128) -> u128 {
+    let cost = if units == lot.units {
+        lot.cost
+    } else {
+        proportional(lot.cost, units, lot.units)
+    };
+    lot.units -= units;
+    lot.cost -= cost;
+    cost
+}
+
+/// Takes `units` out of the lot as a piece of a disposal.
+fn consume_piece(lot: &mut LotBalance, units: u128) -> LotPiece {
+    LotPiece { acquired_at: lot.settled_at, units, cost: consume(lot, units) }
+}
+
+/// Consumes the lots in the given order until `units` are covered.
+fn consume_in_order(lots: &mut [LotBalance], order: &[usize], mut units: u128) -> Vec<LotPiece> {
+    let mut pieces = Vec::new();
+    for index in order {
+        if units == 0 {
+            break;
+        }
+        let taken = units.min(lots[*index].units);
+        pieces.push(consume_piece(&mut lots[*index], taken));
+        units -= taken;
+    }
+    pieces
+}
+
+/// Replays the sells, in date order, against the lots using the method.
+/// `lot_cost` and `exit_amount` value each buy and sell, so the caller
+/// chooses the currency and the exchange rate.
+pub(crate) fn apply<C, E>(
+    method: CostBasis,
+    buys: &[Buy],
+    sells: &[Sell],
+    lot_cost: C,
+    exit_amount: E
+) -> Result<CostBasisReport, AppErrors>
+where
+    C: Fn(&Buy) -> Result<u128, AppErrors>,
+    E: Fn(&Sell) -> Result<u128, AppErrors>
+{
+    let mut lots = buys
+        .iter()
+        .map(|buy| Ok(LotBalance {
+            settled_at: buy.settled_at,
+            units: buy.units,
+            cost: lot_cost(buy)?
+        }))
+        .collect::<Result<Vec<LotBalance>, AppErrors>>()?;
+
+    let mut order: Vec<usize> = (0..sells.len()).collect();
+    order.sort_by_key(|index| sells[*index].settled_at);
+
+    let mut disposals = Vec::with_capacity(sells.len());
+    for sell in order.iter().map(|index| &sells[*index]) {
+        // Lots acquired after the sale are not available.
+        let mut available: Vec<usize> = (0..lots.len())
+            .filter(|index| lots[*index].settled_at <= sell.settled_at && lots[*index].units > 0)
+            .collect();
+        let available_units: u128 = available.iter().map(|index| lots[*index].units).sum();
+        if sell.units > available_units {
+            return Err(AppErrors::InvalidOperation(format!(
+                "Selling {} units but only {} are held at that date.", sell.units, available_units
+            )));
+        }
+
+        let pieces = match method {
+            CostBasis::Fifo => {
+                available.sort_by_key(|index| lots[*index].settled_at);
+                consume_in_order(&mut lots, &available, sell.units)
+            },
+            CostBasis::Lifo => {
+                available.sort_by_key(|index| std::cmp::Reverse(lots[*index].settled_at));
+                consume_in_order(&mut lots, &available, sell.units)
+            },
+            CostBasis::Average => {
+                let pool_cost: u128 = available.iter().map(|index| lots[*index].cost).sum();
+                let basis = proportional(pool_cost, sell.units, available_units);
+                available.sort_by_key(|index| lots[*index].settled_at);
+                let mut pieces = consume_in_order(&mut lots, &available, sell.units);
+
+                // Re-average what is left of the pool.
+                let mut left_cost = pool_cost - basis;
+                let mut left_units = available_units - sell.units;
+                for index in available.iter() {
+                    let lot = &mut lots[*index];
+                    if lot.units == 0 {
+                        continue;
+                    }
+                    lot.cost = proportional(left_cost, lot.units, left_units);
+                    left_cost -= lot.cost;
+                    left_units -= lot.units;
+                }
+
+                // Every piece costs the average, the last one takes the rounding.
+                let mut left_basis = basis;
+                let last = pieces.len() - 1;
+                for (index, piece) in pieces.iter_mut().enumerate() {
+                    piece.cost = if index == last { left_basis } else { proportional(basis, piece.units, sell.units) };
+                    left_basis -= piece.cost;
+                }
+                pieces
+            },
+            CostBasis::SpecificLot => {
+                let index = sell.lot.ok_or_else(|| {
+                    AppErrors::InvalidOperation("Specific lot cost basis requires the lot of each sell.".to_string())
+                })?;
+                if !available.contains(&index) || lots[index].units < sell.units {
+                    return Err(AppErrors::InvalidOperation(format!(
+                        "Lot {} does not hold {} units at the sell date.", index, sell.units
+                    )));
+                }
+                vec![consume_piece(&mut lots[index], sell.units)]
+            }
+        };
+
+        disposals.push(Disposal {
+            settled_at: sell.settled_at,
+            units: sell.units,
+            basis: pieces.iter().map(|piece| piece.cost).sum(),
+            exit_amount: exit_amount(sell)?,
+            pieces
+        });
+    }
+
+    Ok(CostBasisReport { lots, disposals })
+}
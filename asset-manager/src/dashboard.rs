@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::asset::{Asset, AssetEvaluation, AssetType, RealReturn};
+use crate::cash::CashAccount;
+use crate::fixed_income::Payment;
+use crate::models::FiatCurrency;
+use crate::returns::GroupReturns;
+use crate::types::{AssetId, FundName};
+use crate::utils::format_amount;
+use crate::utils::now::Now;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// Amounts of a group of assets, in the user currency.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct DashboardTotal {
+    pub label: String,
+    /// Cost of the units still held.
+    pub entrance_amount: u128,
+    /// Market value of the units still held.
+    pub now_amount: u128,
+    /// Cost of the units sold.
+    pub realized_basis: u128,
+    pub realized_gain: i128,
+    /// Dividends and other income received.
+    pub income: u128,
+    /// Expenses paid for holding the assets.
+    pub expenses: u128,
+    /// Balance of the cash accounts, not part of the gain.
+    pub cash: u128,
+    /// Gain after inflation, only with an inflation index.
+    pub real_gain: Option<i128>
+}
+
+impl DashboardTotal {
+    fn new(label: String) -> Self {
+        DashboardTotal { label, ..DashboardTotal::default() }
+    }
+
+    fn add(&mut self, evaluation: &AssetEvaluation) {
+        self.entrance_amount += evaluation.get_entrance_amount();
+        self.now_amount += evaluation.get_now_amount();
+        self.realized_basis += evaluation.get_realized_basis();
+        self.realized_gain += evaluation.get_realized_gain().unwrap_or(0);
+        self.income += evaluation.get_income_amount();
+        self.expenses += evaluation.get_expense_amount();
+        if let Some(real_return) = evaluation.get_real_return() {
+            self.real_gain = Some(self.real_gain.unwrap_or(0) + real_return.gain);
+        }
+    }
+
+    /// Unrealized gain of the units held plus the realized gain of the sells
+    /// and the income received, minus the expenses.
+    pub fn get_gain(&self) -> i128 {
+        self.now_amount as i128 - self.entrance_amount as i128 + self.realized_gain + self.income as i128
+            - self.expenses as i128
+    }
+
+    /// Market value of the assets held plus the cash.
+    pub fn get_net_worth(&self) -> u128 {
+        self.now_amount + self.cash
+    }
+
+    /// Gain over everything invested, in basis points. None without cost.
+    pub fn get_gain_bps(&self) -> Option<i128> {
+        let invested = self.entrance_amount + self.realized_basis;
+        if invested == 0 {
+            None
+        } else {
+            Some(self.get_gain() * 10_000 / invested as i128)
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DashboardRow {
+    pub asset_id: AssetId,
+    pub fund: FundName,
+    pub asset_type_str: String,
+    pub holding_days: u64,
+    pub sold: bool,
+    pub amounts: DashboardTotal,
+    /// CAGR over the holding time, in basis points.
+    pub annualized_bps: Option<i128>,
+    pub real_return: Option<RealReturn>
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CashRow {
+    pub name: String,
+    pub fund: FundName,
+    /// In the account currency.
+    pub balance: u128,
+    pub account_currency: FiatCurrency,
+    /// In the dashboard currency, at the latest rate.
+    pub amount: u128
+}
+
+/// A coupon or maturity of a fixed income asset, in the instrument currency.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UpcomingPayment {
+    pub asset_id: AssetId,
+    pub name: String,
+    pub payment: Payment
+}
+
+/// Every asset with its subtotals per fund and per asset type.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Dashboard {
+    pub currency: FiatCurrency,
+    pub rows: Vec<DashboardRow>,
+    pub by_fund: Vec<DashboardTotal>,
+    pub by_asset_type: Vec<DashboardTotal>,
+    pub total: DashboardTotal,
+    pub cash: Vec<CashRow>,
+    /// XIRR and TWR of every fund, then of every asset together.
+    pub returns: Vec<GroupReturns>,
+    /// Next coupon and maturity of every fixed income asset, by date.
+    pub upcoming: Vec<UpcomingPayment>
+}
+
+impl Dashboard {
+    /// `cash` is the balance of every account in the dashboard currency.
+    pub fn new(
+        currency: FiatCurrency,
+        evaluated: Vec<(&Asset, AssetEvaluation)>,
+        cash: Vec<(&CashAccount, u128)>,
+        returns: Vec<GroupReturns>
+    ) -> Self {
+        let mut by_fund: BTreeMap<FundName, DashboardTotal> = BTreeMap::new();
+        let mut by_asset_type: BTreeMap<String, DashboardTotal> = BTreeMap::new();
+        let mut total = DashboardTotal::new("TOTAL".to_string());
+        let mut rows = Vec::with_capacity(evaluated.len());
+        let mut upcoming = Vec::new();
+        let now = Now::new().to_epoch_millis();
+
+        for (asset, evaluation) in evaluated {
+            let fund = asset.get_fund().get_name().clone();
+            let asset_type_str = evaluation.get_asset_type_str().to_string();
+            by_fund
+                .entry(fund.clone())
+                .or_insert_with(|| DashboardTotal::new(fund.clone()))
+                .add(&evaluation);
+            by_asset_type
+                .entry(asset_type_str.clone())
+                .or_insert_with(|| DashboardTotal::new(asset_type_str.clone()))
+                .add(&evaluation);
+            total.add(&evaluation);
+            if let AssetType::FixedIncome { name, terms, .. } = asset.get_asset_type() {
+                upcoming.extend(
+                    terms
+                        .get_upcoming_payments(now, evaluation.get_held_units())
+                        .into_iter()
+                        .map(|payment| UpcomingPayment { asset_id: asset.id, name: name.clone(), payment })
+                );
+            }
+
+            let mut amounts = DashboardTotal::new(asset_type_str.clone());
+            amounts.add(&evaluation);
+            rows.push(DashboardRow {
+                asset_id: evaluation.get_asset_id(),
+                fund,
+                asset_type_str,
+                holding_days: evaluation.get_holding_millis() / DAY_MILLIS,
+                sold: asset.is_sold(),
+                amounts,
+                annualized_bps: evaluation.get_annualized_bps(),
+                real_return: evaluation.get_real_return().cloned()
+            });
+        }
+        upcoming.sort_by_key(|upcoming| upcoming.payment.date);
+
+        let cash = cash
+            .into_iter()
+            .map(|(account, amount)| {
+                let fund = account.get_fund().clone();
+                by_fund
+                    .entry(fund.clone())
+                    .or_insert_with(|| DashboardTotal::new(fund.clone()))
+                    .cash += amount;
+                total.cash += amount;
+                CashRow {
+                    name: account.get_name().clone(),
+                    fund,
+                    balance: account.get_balance(),
+                    account_currency: account.get_currency().clone(),
+                    amount
+                }
+            })
+            .collect();
+
+        Dashboard {
+            currency,
+            rows,
+            by_fund: by_fund.into_values().collect(),
+            by_asset_type: by_asset_type.into_values().collect(),
+            total,
+            cash,
+            returns,
+            upcoming
+        }
+    }
+
+    fn format_bps(bps: Option<i128>) -> String {
+        match bps {
+            Some(bps) => format!("{}%", format_amount(bps, 2)),
+            None => "-".to_string()
+        }
+    }
+
+    fn write_amounts(&self, f: &mut fmt::Formatter, amounts: &DashboardTotal) -> fmt::Result {
+        let decimals = self.currency.get_decimals();
+        write!(
+            f,
+            "{:>18} | {:>18} | {:>16} | {:>14} | {:>14} | {:>18} | {:>10}",
+            format_amount(amounts.entrance_amount as i128, decimals),
+            format_amount(amounts.now_amount as i128, decimals),
+            format_amount(amounts.realized_gain, decimals),
+            format_amount(amounts.income as i128, decimals),
+            format_amount(amounts.expenses as i128, decimals),
+            format_amount(amounts.get_gain(), decimals),
+            Dashboard::format_bps(amounts.get_gain_bps())
+        )
+    }
+
+    fn write_totals(&self, f: &mut fmt::Formatter, title: &str, totals: &[DashboardTotal]) -> fmt::Result {
+        writeln!(f)?;
+        writeln!(f, "{}", title)?;
+        for total in totals {
+            write!(f, "{:<40} | ", total.label)?;
+            self.write_amounts(f, total)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Id | Fund | Type | Entrance | Now | Realized | Income | Expenses | Gain | Gain % | Yearly % | Days
+impl fmt::Display for Dashboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>4} | {:<16} | {:<14} | {:>18} | {:>18} | {:>16} | {:>14} | {:>14} | {:>18} | {:>10} | {:>10} | {:>10}",
+            "Id", "Fund", "Type", format!("Entrance {:?}", self.currency), "Now", "Realized", "Income", "Expenses",
+            "Gain", "Gain %", "Yearly %", "Held"
+        )?;
+        for row in self.rows.iter() {
+            write!(
+                f,
+                "{:>4} | {:<16} | {:<14} | ",
+                row.asset_id,
+                row.fund,
+                if row.sold { format!("{} SOLD", row.asset_type_str) } else { row.asset_type_str.clone() }
+            )?;
+            self.write_amounts(f, &row.amounts)?;
+            writeln!(f, " | {:>10} | {:>5} days", Dashboard::format_bps(row.annualized_bps), row.holding_days)?;
+        }
+        self.write_totals(f, "By fund", &self.by_fund)?;
+        self.write_totals(f, "By asset type", &self.by_asset_type)?;
+        writeln!(f)?;
+        write!(f, "{:<40} | ", self.total.label)?;
+        self.write_amounts(f, &self.total)?;
+        writeln!(f)?;
+        let decimals = self.currency.get_decimals();
+        if !self.cash.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Cash")?;
+        }
+        for row in self.cash.iter() {
+            writeln!(
+                f,
+                "{:<21} | {:<16} | {:>18} {:?} | {:>18}",
+                row.name,
+                row.fund,
+                format_amount(row.balance as i128, row.account_currency.get_decimals()),
+                row.account_currency,
+                format_amount(row.amount as i128, decimals)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Net worth")?;
+        for total in self.by_fund.iter().chain([&self.total]) {
+            writeln!(f, "{:<40} | {:>18}", total.label, format_amount(total.get_net_worth() as i128, decimals))?;
+        }
+        if !self.returns.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{:<40} | {:>10} | {:>10}", "Yearly returns", "XIRR", "TWR")?;
+        }
+        for returns in self.returns.iter() {
+            writeln!(
+                f,
+                "{:<40} | {:>10} | {:>10}",
+                returns.label,
+                Dashboard::format_bps(returns.xirr_bps),
+                Dashboard::format_bps(returns.twr_bps)
+            )?;
+        }
+        if self.total.real_gain.is_some() {
+            writeln!(f)?;
+            writeln!(f, "Real return, after inflation")?;
+        }
+        for row in self.rows.iter() {
+            if let Some(real_return) = &row.real_return {
+                writeln!(
+                    f,
+                    "{:>4} | {:<16} | {:<14} | {:>18} | {:>18} | {:>10} yearly{}",
+                    row.asset_id,
+                    row.fund,
+                    row.asset_type_str,
+                    format_amount(real_return.entrance_amount as i128, decimals),
+                    format_amount(real_return.gain, decimals),
+                    Dashboard::format_bps(real_return.annualized_bps),
+                    if real_return.stale { ", STALE index" } else { "" }
+                )?;
+            }
+        }
+        for total in self.by_fund.iter().chain([&self.total]) {
+            if let Some(real_gain) = total.real_gain {
+                writeln!(f, "{:<40} | {:>18} | {:>18}", total.label, "", format_amount(real_gain, decimals))?;
+            }
+        }
+        if !self.upcoming.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Upcoming payments")?;
+        }
+        for upcoming in self.upcoming.iter() {
+            writeln!(
+                f,
+                "{} | {:>4} | {:<16} | {:<8} | {:>18} {:?}",
+                Now::new_from_epoch_millis(upcoming.payment.date).to_date_string(),
+                upcoming.asset_id,
+                upcoming.name,
+                upcoming.payment.kind.to_string(),
+                format_amount(upcoming.payment.amount as i128, upcoming.payment.currency.get_decimals()),
+                upcoming.payment.currency
+            )?;
+        }
+        Ok(())
+    }
+}
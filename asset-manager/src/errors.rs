@@ -1,14 +1,78 @@
-use std::fmt;
+use std::{fmt, io};
 
-#[derive(Debug)]
+use crate::models::FiatCurrency;
+use crate::types::{AssetId, FundName, UserName};
+
+#[derive(Debug, PartialEq)]
 pub enum AppErrors {
-    InvalidPassword
+    // User and app state.
+    InvalidPassword,
+    UserNotFound(UserName),
+    UserAlreadyExists(UserName),
+    InvalidUserName(UserName),
+    AppStateNotFound,
+    AppStateAlreadyExists,
+    CorruptedAppState,
+
+    // Portfolio.
+    UnknownFund(FundName),
+    DuplicateFund(FundName),
+    AssetNotFound(AssetId),
+    UnknownCashAccount(String),
+    DuplicateCashAccount(String),
+    UnknownAssetType(String),
+    UnknownCurrency(String),
+    UnknownTransaction(String),
+    InvalidOperation(String),
+
+    // Parsing and valuation.
+    MalformedAmount(String),
+    MalformedDate(String),
+    MalformedFile(String),
+    MissingPrice(String),
+    CurrencyMismatch { expected: FiatCurrency, found: FiatCurrency },
+
+    // System.
+    Io(String),
+    Crypto(String)
 }
 
 impl fmt::Display for AppErrors {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self {
             AppErrors::InvalidPassword => write!(f, "Invalid password."),
+            AppErrors::UserNotFound(name) => write!(f, "User {} not found.", name),
+            AppErrors::UserAlreadyExists(name) => write!(f, "User {} already exists.", name),
+            AppErrors::InvalidUserName(name) => write!(f, "Invalid user name {:?}, use letters, digits, _ or -.", name),
+            AppErrors::AppStateNotFound => write!(f, "Create a new app state first."),
+            AppErrors::AppStateAlreadyExists => write!(f, "User already have a running app."),
+            AppErrors::CorruptedAppState => write!(f, "App state file is corrupted or does not match the user."),
+            AppErrors::UnknownFund(name) => write!(f, "Fund {} not found.", name),
+            AppErrors::DuplicateFund(name) => write!(f, "Fund {} already exist.", name),
+            AppErrors::AssetNotFound(id) => write!(f, "Asset {} not found.", id),
+            AppErrors::UnknownCashAccount(name) => write!(f, "Cash account {} not found.", name),
+            AppErrors::DuplicateCashAccount(name) => write!(f, "Cash account {} already exist.", name),
+            AppErrors::UnknownAssetType(type_str) => write!(f, "Unknown asset type: {}.", type_str),
+            AppErrors::UnknownCurrency(currency) => write!(f, "Unknown currency: {}.", currency),
+            AppErrors::UnknownTransaction(kind) => write!(f, "Unknown transaction: {}.", kind),
+            AppErrors::InvalidOperation(msg) => write!(f, "Invalid operation: {}", msg),
+            AppErrors::MalformedAmount(value) => write!(f, "Malformed amount: {}.", value),
+            AppErrors::MalformedDate(value) => write!(f, "Malformed date: {}.", value),
+            AppErrors::MalformedFile(msg) => write!(f, "Malformed file: {}.", msg),
+            AppErrors::MissingPrice(what) => write!(f, "Missing price for {}.", what),
+            AppErrors::CurrencyMismatch { expected, found } => {
+                write!(f, "Currency mismatch, expected {:?} found {:?}.", expected, found)
+            },
+            AppErrors::Io(msg) => write!(f, "IO error: {}.", msg),
+            AppErrors::Crypto(msg) => write!(f, "Crypto error: {}.", msg),
         }
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for AppErrors {}
+
+impl From<io::Error> for AppErrors {
+    fn from(err: io::Error) -> Self {
+        AppErrors::Io(err.to_string())
+    }
+}
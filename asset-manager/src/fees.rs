@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use json::JsonValue;
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::{self, SeqAccess, Visitor};
+
+use crate::errors::AppErrors;
+use crate::models::FiatCurrency;
+use crate::types::FundName;
+use crate::utils::format_amount;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FeeKind {
+    /// Broker or exchange commission.
+    Commission,
+    /// Difference between the price paid and the market price.
+    Spread,
+    /// Dealer premium over the metal content of coins and bars.
+    Premium,
+    /// Notary, deed and registry costs of real estate.
+    Notary,
+    /// Blockchain network fee.
+    Network,
+    /// Bank transfer or card charges.
+    Bank,
+    Tax,
+    Other
+}
+
+impl FromStr for FeeKind {
+    type Err = AppErrors;
+
+    fn from_str(input: &str) -> Result<FeeKind, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "commission" => Ok(FeeKind::Commission),
+            "spread" => Ok(FeeKind::Spread),
+            "premium" => Ok(FeeKind::Premium),
+            "notary" => Ok(FeeKind::Notary),
+            "network" => Ok(FeeKind::Network),
+            "bank" => Ok(FeeKind::Bank),
+            "tax" => Ok(FeeKind::Tax),
+            "other" => Ok(FeeKind::Other),
+            _ => Err(AppErrors::InvalidOperation(format!("Unknown fee kind {}.", input)))
+        }
+    }
+}
+
+impl fmt::Display for FeeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            FeeKind::Commission => write!(f, "COMMISSION"),
+            FeeKind::Spread => write!(f, "SPREAD"),
+            FeeKind::Premium => write!(f, "PREMIUM"),
+            FeeKind::Notary => write!(f, "NOTARY"),
+            FeeKind::Network => write!(f, "NETWORK"),
+            FeeKind::Bank => write!(f, "BANK"),
+            FeeKind::Tax => write!(f, "TAX"),
+            FeeKind::Other => write!(f, "OTHER"),
+        }
+    }
+}
+
+/// A fee component, in the currency of its transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fee {
+    pub kind: FeeKind,
+    pub amount: u128
+}
+
+impl Fee {
+    pub fn new(kind: FeeKind, amount: u128) -> Self {
+        Fee { kind, amount }
+    }
+}
+
+/// "premium:50000", or only the amount for other fees.
+impl FromStr for Fee {
+    type Err = AppErrors;
+
+    fn from_str(input: &str) -> Result<Fee, Self::Err> {
+        let (kind, amount) = match input.split_once(':') {
+            Some((kind, amount)) => (FeeKind::from_str(kind)?, amount),
+            None => (FeeKind::Other, input)
+        };
+        let amount = amount.parse::<u128>().map_err(|_| AppErrors::MalformedAmount(input.to_string()))?;
+        Ok(Fee::new(kind, amount))
+    }
+}
+
+/// Fees that are not itemized, none when zero.
+pub fn from_total(amount: u128) -> Vec<Fee> {
+    if amount == 0 { Vec::new() } else { vec![Fee::new(FeeKind::Other, amount)] }
+}
+
+pub fn get_total(fees: &[Fee]) -> u128 {
+    fees.iter().map(|fee| fee.amount).sum()
+}
+
+/// Parses the `fees` of the user file, `"350"` for other fees or
+/// `{ "commission": "250", "premium": "12000" }` itemized.
+pub fn parse_fees(data: &JsonValue) -> Result<Vec<Fee>, AppErrors> {
+    if data.is_null() {
+        return Ok(Vec::new());
+    }
+    if data.is_object() {
+        return data
+            .entries()
+            .map(|(kind, amount)| Fee::from_str(&format!("{}:{}", kind, amount)))
+            .collect();
+    }
+    Ok(vec![Fee::from_str(&data.to_string())?])
+}
+
+/// App states saved before the fee components have a single total.
+pub fn deserialize_fees<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Fee>, D::Error> {
+    deserializer.deserialize_any(FeesVisitor)
+}
+
+struct FeesVisitor;
+
+impl<'de> Visitor<'de> for FeesVisitor {
+    type Value = Vec<Fee>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a total of fees or the fee components")
+    }
+
+    fn visit_u64<E: de::Error>(self, amount: u64) -> Result<Vec<Fee>, E> {
+        Ok(from_total(amount as u128))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Fee>, A::Error> {
+        let mut fees = Vec::new();
+        while let Some(fee) = seq.next_element()? {
+            fees.push(fee);
+        }
+        Ok(fees)
+    }
+}
+
+/// Fees paid by a fund in a year, in the user currency.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct FeesLine {
+    pub fund: FundName,
+    pub year: i32,
+    pub by_kind: BTreeMap<FeeKind, u128>,
+    pub total: u128
+}
+
+impl FeesLine {
+    fn add(&mut self, kind: FeeKind, amount: u128) {
+        *self.by_kind.entry(kind).or_default() += amount;
+        self.total += amount;
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FeesReport {
+    pub currency: FiatCurrency,
+    /// By fund and year.
+    pub lines: Vec<FeesLine>,
+    /// Every fund, by year.
+    pub by_year: Vec<FeesLine>,
+    pub total: u128
+}
+
+impl FeesReport {
+    /// `fees` are the fund, year and fee of every transaction, converted.
+    pub fn new(currency: FiatCurrency, fees: Vec<(FundName, i32, Fee)>) -> Self {
+        let mut lines: BTreeMap<(FundName, i32), FeesLine> = BTreeMap::new();
+        let mut by_year: BTreeMap<i32, FeesLine> = BTreeMap::new();
+        let mut total = 0;
+        for (fund, year, fee) in fees {
+            lines
+                .entry((fund.clone(), year))
+                .or_insert_with(|| FeesLine { fund, year, ..FeesLine::default() })
+                .add(fee.kind, fee.amount);
+            by_year
+                .entry(year)
+                .or_insert_with(|| FeesLine { fund: "TOTAL".to_string(), year, ..FeesLine::default() })
+                .add(fee.kind, fee.amount);
+            total += fee.amount;
+        }
+        FeesReport {
+            currency,
+            lines: lines.into_values().collect(),
+            by_year: by_year.into_values().collect(),
+            total
+        }
+    }
+}
+
+/// Fund | Year | Total | Components
+impl fmt::Display for FeesReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = self.currency.get_decimals();
+        writeln!(f, "{:<16} | {:>4} | {:>18} | Components", "Fund", "Year", format!("Fees {:?}", self.currency))?;
+        for line in self.lines.iter().chain(self.by_year.iter()) {
+            let components: Vec<String> = line.by_kind
+                .iter()
+                .map(|(kind, amount)| format!("{} {}", kind, format_amount(*amount as i128, decimals)))
+                .collect();
+            writeln!(
+                f,
+                "{:<16} | {:>4} | {:>18} | {}",
+                line.fund,
+                line.year,
+                format_amount(line.total as i128, decimals),
+                components.join(", ")
+            )?;
+        }
+        writeln!(f, "{:<16} | {:>4} | {:>18} |", "TOTAL", "", format_amount(self.total as i128, decimals))
+    }
+}
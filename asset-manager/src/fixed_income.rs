@@ -0,0 +1,212 @@
+use std::fmt;
+use std::str::FromStr;
+
+use json::JsonValue;
+use serde::{Serialize, Deserialize};
+
+use crate::errors::AppErrors;
+use crate::models::FiatCurrency;
+use crate::types::EpochMillis;
+use crate::utils::now::Now;
+use crate::utils::{parse_decimal, parse_option_string, parse_option_u16, proportional};
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+/// Mexican money market convention, actual days over a 360 days year.
+const YEAR_DAYS: u128 = 360;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FixedIncomeKind {
+    /// Pays a coupon every `coupon_days` and the face value at maturity.
+    Bond,
+    /// Zero coupon, bought at a discount of the face value.
+    Cetes,
+    /// Bank deposit, the interest is paid with the principal at maturity.
+    TermDeposit
+}
+
+impl FromStr for FixedIncomeKind {
+    type Err = AppErrors;
+
+    fn from_str(input: &str) -> Result<FixedIncomeKind, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "bond" => Ok(FixedIncomeKind::Bond),
+            "cetes" => Ok(FixedIncomeKind::Cetes),
+            "term_deposit" => Ok(FixedIncomeKind::TermDeposit),
+            _ => Err(AppErrors::UnknownAssetType(input.to_string()))
+        }
+    }
+}
+
+impl FixedIncomeKind {
+    pub fn as_str(&self) -> &'static str {
+        match &self {
+            FixedIncomeKind::Bond => "BOND",
+            FixedIncomeKind::Cetes => "CETES",
+            FixedIncomeKind::TermDeposit => "TERM_DEPOSIT"
+        }
+    }
+}
+
+impl fmt::Display for FixedIncomeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PaymentKind {
+    Coupon,
+    Maturity
+}
+
+impl fmt::Display for PaymentKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            PaymentKind::Coupon => write!(f, "COUPON"),
+            PaymentKind::Maturity => write!(f, "MATURITY"),
+        }
+    }
+}
+
+/// A scheduled payment, in the currency of the instrument.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Payment {
+    pub kind: PaymentKind,
+    pub date: EpochMillis,
+    pub amount: u128,
+    pub currency: FiatCurrency
+}
+
+/// Terms of one title of a fixed income instrument.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FixedIncomeTerms {
+    pub kind: FixedIncomeKind,
+    /// Paid back per title at maturity, in `currency` minor units.
+    pub face_value: u64,
+    pub currency: FiatCurrency,
+    /// Yearly rate in basis points. The coupon rate of bonds and term
+    /// deposits, the discount rate of CETES.
+    pub rate_bps: u32,
+    pub issued_at: EpochMillis,
+    pub maturity_at: EpochMillis,
+    /// Days between bond coupons, counted back from the maturity. Without
+    /// it the interest is paid at maturity.
+    pub coupon_days: Option<u32>
+}
+
+impl FixedIncomeTerms {
+    /// Parses the asset `data` of the user file. Dates are %Y-%m-%d, the
+    /// rate is a yearly percentage, "7.25". CETES have a face value of 10.
+    pub fn from_json(kind: FixedIncomeKind, data: &JsonValue) -> Result<Self, AppErrors> {
+        let required = |key: &str| parse_option_string(data, key)
+            .ok_or_else(|| AppErrors::MalformedFile(format!("{} without {}", kind, key)));
+        let date = |key: &str| -> Result<EpochMillis, AppErrors> {
+            Ok(Now::new_from_datetime_str(&required(key)?, "%Y-%m-%d")?.to_epoch_millis())
+        };
+        let currency = match parse_option_string(data, "currency") {
+            Some(currency) => FiatCurrency::from_str(&currency)?,
+            None => FiatCurrency::MXN
+        };
+        let face_value = match (kind, parse_option_string(data, "face_value")) {
+            (_, Some(face_value)) => parse_decimal(&face_value, currency.get_decimals())?,
+            (FixedIncomeKind::Cetes, None) => 10 * 10u128.pow(currency.get_decimals()),
+            (_, None) => return Err(AppErrors::MalformedFile(format!("{} without face_value", kind)))
+        };
+        let rate = required("rate")?;
+        let terms = FixedIncomeTerms {
+            kind,
+            face_value: u64::try_from(face_value).map_err(|_| AppErrors::MalformedAmount(format!("face_value = {}", face_value)))?,
+            currency,
+            rate_bps: u32::try_from(parse_decimal(&rate, 2)?).map_err(|_| AppErrors::MalformedAmount(format!("rate = {}", rate)))?,
+            issued_at: date("issued_at")?,
+            maturity_at: date("maturity_at")?,
+            coupon_days: parse_option_u16(data, "coupon_days")?.map(|days| days as u32)
+        };
+        if terms.maturity_at <= terms.issued_at || terms.coupon_days == Some(0) {
+            return Err(AppErrors::MalformedFile(format!("{} with an invalid schedule", kind)));
+        }
+        Ok(terms)
+    }
+
+    /// Simple interest of `titles` over the whole days between the dates.
+    fn interest(&self, titles: u128, from: EpochMillis, to: EpochMillis) -> u128 {
+        let days = (to.saturating_sub(from) / DAY_MILLIS) as u128;
+        proportional(self.face_value as u128 * titles * self.rate_bps as u128, days, 10_000 * YEAR_DAYS)
+    }
+
+    /// Dates of the interest payments, ascending. CETES pay no interest.
+    pub fn get_coupon_dates(&self) -> Vec<EpochMillis> {
+        match (self.kind, self.coupon_days) {
+            (FixedIncomeKind::Cetes, _) => Vec::new(),
+            (FixedIncomeKind::Bond, Some(coupon_days)) => {
+                let period = coupon_days as u64 * DAY_MILLIS;
+                let mut dates = Vec::new();
+                let mut date = self.maturity_at;
+                while date > self.issued_at {
+                    dates.push(date);
+                    date = date.saturating_sub(period);
+                }
+                dates.reverse();
+                dates
+            },
+            _ => vec![self.maturity_at]
+        }
+    }
+
+    /// Every payment of `titles`. The first coupon pays from the issue.
+    pub fn get_payments(&self, titles: u128) -> Vec<Payment> {
+        let mut payments = Vec::new();
+        let mut previous = self.issued_at;
+        for date in self.get_coupon_dates() {
+            payments.push(Payment {
+                kind: PaymentKind::Coupon,
+                date,
+                amount: self.interest(titles, previous, date),
+                currency: self.currency.clone()
+            });
+            previous = date;
+        }
+        payments.push(Payment {
+            kind: PaymentKind::Maturity,
+            date: self.maturity_at,
+            amount: self.face_value as u128 * titles,
+            currency: self.currency.clone()
+        });
+        payments
+    }
+
+    /// The next coupon and the maturity of `titles`, after `at`.
+    pub fn get_upcoming_payments(&self, at: EpochMillis, titles: u128) -> Vec<Payment> {
+        if titles == 0 {
+            return Vec::new();
+        }
+        let payments: Vec<Payment> = self.get_payments(titles)
+            .into_iter()
+            .filter(|payment| payment.date > at)
+            .collect();
+        let next_date = payments.first().map(|payment| payment.date);
+        payments
+            .into_iter()
+            .filter(|payment| Some(payment.date) == next_date || payment.kind == PaymentKind::Maturity)
+            .collect()
+    }
+
+    /// Value of `titles` at a date. CETES are valued by their discount
+    /// yield, the rest by the face value plus the interest accrued since
+    /// the last coupon. Stops accruing at maturity.
+    pub fn get_value_at(&self, at: EpochMillis, titles: u128) -> u128 {
+        let at = at.clamp(self.issued_at, self.maturity_at);
+        let face_value = self.face_value as u128 * titles;
+        match self.kind {
+            FixedIncomeKind::Cetes => face_value.saturating_sub(self.interest(titles, at, self.maturity_at)),
+            _ => {
+                let last_coupon = self.get_coupon_dates()
+                    .into_iter()
+                    .rev()
+                    .find(|date| *date <= at)
+                    .unwrap_or(self.issued_at);
+                face_value + self.interest(titles, last_coupon, at)
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::errors::AppErrors;
+use crate::models::FiatCurrency;
+use crate::types::EpochMillis;
+use crate::utils::{now::Now, parse_decimal, proportional, read_json_file};
+
+/// Rates are fixed point with 6 decimals, 19.8752 is 19_875_200.
+pub const RATE_DECIMALS: u32 = 6;
+
+/// Which rate converts a past transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateDate {
+    /// The rate of the day the transaction settled.
+    Settlement,
+    /// The latest rate in the table.
+    Latest
+}
+
+/// Date indexed exchange rates. A pair "USD/MXN" is the MXN paid for one USD.
+///
+/// File format:
+/// `{ "USD/MXN": { "2021-01-04": "19.9087", "2021-01-05": "19.8752" } }`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct FxRates {
+    pairs: HashMap<String, BTreeMap<EpochMillis, u128>>
+}
+
+impl FxRates {
+    pub fn from_file(file_path: &str) -> Result<Self, AppErrors> {
+        let fx_json = read_json_file(file_path)?;
+        let mut fx_rates = FxRates::default();
+        for (pair, rates) in fx_json.entries() {
+            let (base, quote) = pair
+                .split_once('/')
+                .ok_or_else(|| AppErrors::MalformedFile(format!("{}: pair {}", file_path, pair)))?;
+            let base = FiatCurrency::from_str(base)?;
+            let quote = FiatCurrency::from_str(quote)?;
+            for (date, rate) in rates.entries() {
+                let settled_at = Now::new_from_datetime_str(date, "%Y-%m-%d")?.to_epoch_millis();
+                fx_rates.insert(&base, &quote, settled_at, parse_decimal(&rate.to_string(), RATE_DECIMALS)?);
+            }
+        }
+        Ok(fx_rates)
+    }
+
+    fn pair_key(base: &FiatCurrency, quote: &FiatCurrency) -> String {
+        format!("{:?}/{:?}", base, quote)
+    }
+
+    pub fn insert(&mut self, base: &FiatCurrency, quote: &FiatCurrency, at: EpochMillis, rate: u128) {
+        self.pairs
+            .entry(FxRates::pair_key(base, quote))
+            .or_default()
+            .insert(at, rate);
+    }
+
+    /// Last rate on or before the date. `None` for the latest rate.
+    fn find_rate(&self, base: &FiatCurrency, quote: &FiatCurrency, at: Option<EpochMillis>) -> Option<u128> {
+        let rates = self.pairs.get(&FxRates::pair_key(base, quote))?;
+        match at {
+            Some(at) => rates.range(..=at).next_back().map(|(_, rate)| *rate),
+            None => rates.values().next_back().copied()
+        }
+    }
+
+    /// Converts the amount from one currency to another. Uses the inverse
+    /// pair when the direct one is not in the table.
+    pub fn convert(
+        &self,
+        amount: u128,
+        from: &FiatCurrency,
+        to: &FiatCurrency,
+        at: Option<EpochMillis>
+    ) -> Result<u128, AppErrors> {
+        if from == to {
+            return Ok(amount);
+        }
+        if let Some(rate) = self.find_rate(from, to, at) {
+            return Ok(proportional(amount, rate, 10u128.pow(RATE_DECIMALS)));
+        }
+        match self.find_rate(to, from, at) {
+            Some(rate) if rate > 0 => Ok(proportional(amount, 10u128.pow(RATE_DECIMALS), rate)),
+            _ => Err(AppErrors::MissingPrice(format!(
+                "{} at {}",
+                FxRates::pair_key(from, to),
+                at.map_or("latest".to_string(), |at| at.to_string())
+            )))
+        }
+    }
+
+    /// Converts a transaction settled at `settled_at` using the rate date.
+    pub fn convert_settled(
+        &self,
+        amount: u128,
+        from: &FiatCurrency,
+        to: &FiatCurrency,
+        settled_at: EpochMillis,
+        rate_date: RateDate
+    ) -> Result<u128, AppErrors> {
+        match rate_date {
+            RateDate::Settlement => self.convert(amount, from, to, Some(settled_at)),
+            RateDate::Latest => self.convert(amount, from, to, None)
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, TimeZone, Utc};
+
+use crate::errors::AppErrors;
+use crate::types::EpochMillis;
+use crate::utils::{parse_decimal, proportional, read_json_file};
+
+/// Index values are fixed point with 6 decimals.
+pub const INDEX_DECIMALS: u32 = 6;
+
+/// Months a date may go past the last index published. INEGI publishes the
+/// INPC of a month around the 10th of the next one.
+pub const MAX_INDEX_LAG_MONTHS: i32 = 2;
+
+/// Monthly consumer price index, like the INPC published by INEGI in Mexico
+/// or the CPI of the user currency. Persisted as `{ "2023-01": "127.336" }`, by year and month.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InflationIndex {
+    months: BTreeMap<(i32, u32), u128>
+}
+
+fn get_month(at: EpochMillis) -> (i32, u32) {
+    let date = Utc.timestamp_millis_opt(at as i64).unwrap();
+    (date.year(), date.month())
+}
+
+fn get_previous_month((year, month): (i32, u32)) -> (i32, u32) {
+    if month == 1 { (year - 1, 12) } else { (year, month - 1) }
+}
+
+fn get_months_between((from_year, from_month): (i32, u32), (to_year, to_month): (i32, u32)) -> i32 {
+    (to_year - from_year) * 12 + to_month as i32 - from_month as i32
+}
+
+impl InflationIndex {
+    pub fn from_file(file_path: &str) -> Result<Self, AppErrors> {
+        let content = read_json_file(file_path)?;
+        let mut index = InflationIndex::default();
+        for (month_str, value) in content.entries() {
+            let malformed = || AppErrors::MalformedDate(format!("{}: {}", file_path, month_str));
+            let (year, month) = month_str.split_once('-').ok_or_else(malformed)?;
+            let year = year.parse::<i32>().map_err(|_| malformed())?;
+            let month = month.parse::<u32>().ok().filter(|month| (1..=12).contains(month)).ok_or_else(malformed)?;
+            index.insert(year, month, parse_decimal(&value.to_string(), INDEX_DECIMALS)?);
+        }
+        Ok(index)
+    }
+
+    pub fn insert(&mut self, year: i32, month: u32, value: u128) {
+        self.months.insert((year, month), value);
+    }
+
+    pub fn get(&self, year: i32, month: u32) -> Result<u128, AppErrors> {
+        self.months
+            .get(&(year, month))
+            .copied()
+            .filter(|value| *value > 0)
+            .ok_or_else(|| AppErrors::MissingPrice(format!("inflation index of {}-{:02}", year, month)))
+    }
+
+    /// Index of the month of the date.
+    pub fn get_at(&self, at: EpochMillis) -> Result<u128, AppErrors> {
+        let (year, month) = get_month(at);
+        self.get(year, month)
+    }
+
+    /// Index of the month of the date, or of the last month published before
+    /// when it is at most `MAX_INDEX_LAG_MONTHS` older.
+    pub fn get_latest_at(&self, at: EpochMillis) -> Result<u128, AppErrors> {
+        let (year, month) = get_month(at);
+        let missing = || AppErrors::MissingPrice(format!("inflation index of {}-{:02}", year, month));
+        let (published, value) = self.months.range(..=(year, month)).next_back().ok_or_else(missing)?;
+        if get_months_between(*published, (year, month)) > MAX_INDEX_LAG_MONTHS {
+            return Err(AppErrors::MissingPrice(format!(
+                "inflation index of {}-{:02}, the last one is of {}-{:02}", year, month, published.0, published.1
+            )));
+        }
+        Some(*value).filter(|value| *value > 0).ok_or_else(missing)
+    }
+
+    /// The month of the date is not published yet, `get_latest_at` uses an
+    /// older one.
+    pub fn is_stale_at(&self, at: EpochMillis) -> bool {
+        self.get_at(at).is_err()
+    }
+
+    /// An amount of `from` in money of `to`, deflation included.
+    pub fn adjust(&self, amount: u128, from: EpochMillis, to: EpochMillis) -> Result<u128, AppErrors> {
+        Ok(proportional(amount, self.get_latest_at(to)?, self.get_latest_at(from)?))
+    }
+
+    /// Updates an amount acquired at `acquired_at` for an ISR disposal at
+    /// `disposed_at`: the index of the month before the disposal over the
+    /// index of the month of the acquisition. The factor is never below 1.
+    pub fn update_for_disposal(&self, amount: u128, acquired_at: EpochMillis, disposed_at: EpochMillis) -> Result<u128, AppErrors> {
+        let acquired = self.get_at(acquired_at)?;
+        let (year, month) = get_previous_month(get_month(disposed_at));
+        let disposed = self.get(year, month)?;
+        if disposed <= acquired {
+            return Ok(amount);
+        }
+        Ok(proportional(amount, disposed, acquired))
+    }
+}
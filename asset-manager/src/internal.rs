@@ -1,17 +1,13 @@
-
-use std::fs;
-use std::path::Path;
-
-use serde::{Serialize, Deserialize};
 use crate::app::App;
-use crate::types::{UserName, HashString, FundName};
-use crate::models::{AppEnv, Fund};
-// use crate::errors::AppErrors;
-// use std::fs;
-// use std::path::Path;
+use crate::errors::AppErrors;
+use crate::types::FundName;
+use crate::models::Fund;
 
 impl App {
-    pub(crate) fn internal_get_fund(&mut self, name: &FundName) -> Fund {
-        self.funds.get(name).expect("Fund not found!").clone()
+    pub(crate) fn internal_get_fund(&mut self, name: &FundName) -> Result<Fund, AppErrors> {
+        self.funds
+            .get(name)
+            .cloned()
+            .ok_or_else(|| AppErrors::UnknownFund(name.clone()))
     }
-}
\ No newline at end of file
+}
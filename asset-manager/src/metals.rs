@@ -0,0 +1,108 @@
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::errors::AppErrors;
+use crate::utils::{normal_input_string, parse_decimal, proportional};
+
+/// Metal weights are handled in micrograms, 6 decimals of a gram.
+pub const WEIGHT_DECIMALS: u32 = 6;
+/// 31.1034768 grams, in micrograms.
+pub const TROY_OUNCE_MICROGRAMS: u128 = 31_103_477;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Metal {
+    Gold,
+    Silver,
+    Platinum,
+    Palladium
+}
+
+impl Metal {
+    /// Market of a gram of the reference purity.
+    pub fn get_market_key(self) -> &'static str {
+        match self {
+            Metal::Gold => "GOLD-GRAM-24K",
+            Metal::Silver => "SILVER-GRAM-999",
+            Metal::Platinum => "PLATINUM-GRAM-9995",
+            Metal::Palladium => "PALLADIUM-GRAM-9995"
+        }
+    }
+
+    /// Purity of the market reference, in parts per 10_000.
+    pub fn get_reference_purity(self) -> u128 {
+        match self {
+            Metal::Gold => 9_999,
+            Metal::Silver => 9_990,
+            Metal::Platinum | Metal::Palladium => 9_995
+        }
+    }
+
+    /// Fine metal of the known coins, in micrograms. "Centenario",
+    /// "Libertad 1/2oz", "Libertad onza" or "American Eagle 1/10 oz".
+    pub fn get_coin_content(self, presentation: &str) -> Option<u128> {
+        let presentation = normal_input_string(presentation);
+        if self == Metal::Gold && presentation.starts_with("CENTENARIO") {
+            return Some(37_500_000);
+        }
+        let coins: &[&str] = match self {
+            Metal::Gold | Metal::Silver => &["LIBERTAD", "AMERICANEAGLE"],
+            Metal::Platinum | Metal::Palladium => &["AMERICANEAGLE"]
+        };
+        let ounces = coins
+            .iter()
+            .find_map(|coin| presentation.strip_prefix(coin))?
+            .trim_end_matches("ONZAS")
+            .trim_end_matches("ONZA")
+            .trim_end_matches("OZ");
+        parse_ounces(ounces)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum WeightUnit {
+    #[default]
+    Gram,
+    TroyOunce,
+    Kilogram
+}
+
+impl FromStr for WeightUnit {
+    type Err = AppErrors;
+
+    fn from_str(input: &str) -> Result<WeightUnit, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "g" | "gram" | "grams" => Ok(WeightUnit::Gram),
+            "oz" | "ozt" | "troy_oz" | "troy_ounce" => Ok(WeightUnit::TroyOunce),
+            "kg" | "kilogram" | "kilograms" => Ok(WeightUnit::Kilogram),
+            _ => Err(AppErrors::MalformedAmount(format!("weight unit {}", input)))
+        }
+    }
+}
+
+impl WeightUnit {
+    /// Parses a decimal weight in the unit, "1.5", into micrograms.
+    pub fn to_micrograms(self, weight: &str) -> Result<u128, AppErrors> {
+        let weight = parse_decimal(weight, WEIGHT_DECIMALS)?;
+        Ok(match self {
+            WeightUnit::Gram => weight,
+            WeightUnit::TroyOunce => proportional(weight, TROY_OUNCE_MICROGRAMS, 10u128.pow(WEIGHT_DECIMALS)),
+            WeightUnit::Kilogram => weight * 1_000
+        })
+    }
+}
+
+/// Parses the ounces of a coin presentation, "1/2" or "1". No number is
+/// one ounce.
+fn parse_ounces(fraction: &str) -> Option<u128> {
+    let ounce = TROY_OUNCE_MICROGRAMS;
+    match fraction.split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator = numerator.parse::<u128>().ok()?;
+            let denominator = denominator.parse::<u128>().ok().filter(|denominator| *denominator > 0)?;
+            Some(proportional(ounce, numerator, denominator))
+        },
+        None if fraction.is_empty() => Some(ounce),
+        None => fraction.parse::<u128>().ok().map(|ounces| ounces * ounce)
+    }
+}
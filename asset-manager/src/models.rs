@@ -1,30 +1,44 @@
-use std::{fmt, fs};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
 use std::str::FromStr;
 use serde::{Serialize, Deserialize};
-use crate::PRICE_SHEET_FILEPATH;
-use crate::types::{EpochMillis, FundName};
+use crate::errors::AppErrors;
+use crate::fees::{self, deserialize_fees, get_total, Fee};
+use crate::fx::{FxRates, RateDate};
+use crate::types::{EpochMillis, FundName, MarketKey};
 
 use crate::transaction::Transaction;
 use crate::utils::now::Now;
-use crate::utils::{normal_input_string, parse_option_u64};
+use crate::utils::{normal_input_string, parse_option_u64, read_json_file};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum FiatCurrency {
     MXN,
-    USD
+    USD,
+    EUR,
+    CAD
 }
 
 impl FromStr for FiatCurrency {
-    type Err = ();
+    type Err = AppErrors;
 
     fn from_str(input: &str) -> Result<FiatCurrency, Self::Err> {
-        println!("HERE: {}", input);
-        let result = match normal_input_string(&input).as_str() {
-            "MXN" => FiatCurrency::MXN,
-            "USD" => FiatCurrency::USD,
-            _ => unimplemented!()
-        };
-        return Ok(result)
+        match normal_input_string(input).as_str() {
+            "MXN" => Ok(FiatCurrency::MXN),
+            "USD" => Ok(FiatCurrency::USD),
+            "EUR" => Ok(FiatCurrency::EUR),
+            "CAD" => Ok(FiatCurrency::CAD),
+            _ => Err(AppErrors::UnknownCurrency(input.to_string()))
+        }
+    }
+}
+
+impl FiatCurrency {
+    /// Amounts are integers in the minor unit of the currency, cents.
+    pub fn get_decimals(&self) -> u32 {
+        2
     }
 }
 
@@ -34,6 +48,30 @@ pub enum AppEnv {
     Prod
 }
 
+impl AppEnv {
+    /// Where the user and app state files live, `./files/<env>`. Tests use
+    /// the temp dir instead, to keep the data dir clean.
+    pub fn get_data_dir(&self) -> String {
+        if cfg!(test) {
+            format!("{}/asset-manager/{}", env::temp_dir().display(), self)
+        } else {
+            format!("./files/{}", self)
+        }
+    }
+}
+
+impl FromStr for AppEnv {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<AppEnv, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "dev" => Ok(AppEnv::Dev),
+            "prod" => Ok(AppEnv::Prod),
+            _ => Err(format!("Unknown env {}, use dev or prod.", input))
+        }
+    }
+}
+
 impl fmt::Display for AppEnv {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self {
@@ -46,76 +84,307 @@ impl fmt::Display for AppEnv {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Buy {
     transaction: Transaction,
-    pub settled_at: EpochMillis
+    pub settled_at: EpochMillis,
+    /// Units acquired in this lot, in the asset base unit (sats, wei...).
+    pub units: u128
 }
 
 impl Buy {
+    #[allow(dead_code)]
     pub(crate) fn new(
         settled_at: EpochMillis,
+        units: u128,
         transaction_str: String,
         amount: u128,
         currency: FiatCurrency
-    ) -> Self {
-        match transaction_str.to_lowercase().as_str() {
-            "fiat_cash" => {
-                Buy {
-                    transaction: Transaction::FiatCash { amount, currency },
-                    settled_at
-                }
-            },
-            _ => panic!(),
+    ) -> Result<Self, AppErrors> {
+        Ok(Buy::from_transaction(settled_at, units, Transaction::new(&transaction_str, amount, currency)?))
+    }
+
+    /// Any transaction acquires units.
+    pub(crate) fn from_transaction(settled_at: EpochMillis, units: u128, transaction: Transaction) -> Self {
+        Buy { transaction, settled_at, units }
+    }
+
+    pub(crate) fn get_transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    /// Cost of the lot, the amount plus the fees.
+    pub(crate) fn get_transaction_amount_currency(&self) -> (u128, FiatCurrency) {
+        self.transaction.get_cost()
+    }
+
+    /// Amount paid for the lot in the user currency. Purchases in another
+    /// currency are converted with the rate of the settlement or the latest.
+    pub(crate) fn get_entrance_amount(
+        &self,
+        user_fiat_currency: &FiatCurrency,
+        fx_rates: &FxRates,
+        rate_date: RateDate
+    ) -> Result<u128, AppErrors> {
+        let (amount, currency) = self.get_transaction_amount_currency();
+        fx_rates.convert_settled(amount, &currency, user_fiat_currency, self.settled_at, rate_date)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sell {
+    transaction: Transaction,
+    pub settled_at: EpochMillis,
+    /// Units disposed, in the asset base unit.
+    pub units: u128,
+    /// Index of the lot sold, required by the specific lot cost basis.
+    pub lot: Option<usize>,
+    /// Paid on the disposal, in the transaction currency.
+    #[serde(deserialize_with = "deserialize_fees")]
+    fees: Vec<Fee>
+}
+
+impl Sell {
+    #[allow(dead_code)]
+    pub(crate) fn new(
+        settled_at: EpochMillis,
+        units: u128,
+        transaction_str: String,
+        amount: u128,
+        currency: FiatCurrency,
+        fees: u128
+    ) -> Result<Self, AppErrors> {
+        Sell::from_transaction(settled_at, units, Transaction::new(&transaction_str, amount, currency)?, fees::from_total(fees))
+    }
+
+    /// Only fiat or crypto swaps dispose units. `fees` are paid on the
+    /// disposal, on top of the fees of the transaction.
+    pub(crate) fn from_transaction(
+        settled_at: EpochMillis,
+        units: u128,
+        transaction: Transaction,
+        fees: Vec<Fee>
+    ) -> Result<Self, AppErrors> {
+        if !transaction.is_exchange() {
+            return Err(AppErrors::InvalidOperation(format!("{} transaction is not valid for a sell.", transaction)));
         }
-        
+        if get_total(&fees) + transaction.get_fees() > transaction.get_amount_currency().0 {
+            return Err(AppErrors::InvalidOperation("Sell fees are greater than the proceeds.".to_string()));
+        }
+        Ok(Sell { transaction, settled_at, units, lot: None, fees })
+    }
+
+    pub(crate) fn with_lot(mut self, lot: Option<usize>) -> Self {
+        self.lot = lot;
+        self
+    }
+
+    pub(crate) fn get_transaction(&self) -> &Transaction {
+        &self.transaction
     }
 
     pub(crate) fn get_transaction_amount_currency(&self) -> (u128, FiatCurrency) {
-        match self.transaction.clone() {
-            Transaction::FiatCash { amount, currency } => (amount, currency)
+        self.transaction.get_amount_currency()
+    }
+
+    /// Proceeds of the sale minus the fees of the sell and the transaction,
+    /// in the transaction currency.
+    pub(crate) fn get_net_proceeds(&self) -> (u128, FiatCurrency) {
+        let (amount, currency) = self.transaction.get_proceeds();
+        (amount - get_total(&self.fees), currency)
+    }
+
+    /// Fees of the disposal and the transaction.
+    pub(crate) fn get_fee_components(&self) -> Vec<Fee> {
+        self.fees.iter().chain(self.transaction.get_fee_components()).cloned().collect()
+    }
+
+    /// Exit amount is the proceeds of the sale minus the fees, in the user
+    /// currency at the rate of the settlement or the latest.
+    pub(crate) fn get_exit_amount(
+        &self,
+        user_fiat_currency: &FiatCurrency,
+        fx_rates: &FxRates,
+        rate_date: RateDate
+    ) -> Result<u128, AppErrors> {
+        let (amount, currency) = self.get_net_proceeds();
+        fx_rates.convert_settled(amount, &currency, user_fiat_currency, self.settled_at, rate_date)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum IncomeKind {
+    Dividend,
+    /// Interest of fixed income instruments.
+    Coupon,
+    Rent
+}
+
+impl FromStr for IncomeKind {
+    type Err = AppErrors;
+
+    fn from_str(input: &str) -> Result<IncomeKind, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "dividend" => Ok(IncomeKind::Dividend),
+            "coupon" => Ok(IncomeKind::Coupon),
+            "rent" => Ok(IncomeKind::Rent),
+            _ => Err(AppErrors::InvalidOperation(format!("Unknown income kind {}.", input)))
+        }
+    }
+}
+
+impl fmt::Display for IncomeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            IncomeKind::Dividend => write!(f, "DIVIDEND"),
+            IncomeKind::Coupon => write!(f, "COUPON"),
+            IncomeKind::Rent => write!(f, "RENT"),
         }
     }
+}
 
-    pub(crate) fn get_entrance_amount(&self, user_fiat_currency: FiatCurrency) -> u128 {
-        match self.transaction.clone() {
-            Transaction::FiatCash { amount, currency } => {
-                if currency == user_fiat_currency {
-                    amount
-                } else {
-                    unimplemented!();
-                }
-            }
+/// Cash received for holding the asset, it does not dispose any unit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Income {
+    pub kind: IncomeKind,
+    transaction: Transaction,
+    pub settled_at: EpochMillis
+}
+
+impl Income {
+    pub(crate) fn new(
+        kind: IncomeKind,
+        settled_at: EpochMillis,
+        transaction_str: String,
+        amount: u128,
+        currency: FiatCurrency
+    ) -> Result<Self, AppErrors> {
+        Income::from_transaction(kind, settled_at, Transaction::new(&transaction_str, amount, currency)?)
+    }
+
+    pub(crate) fn from_transaction(kind: IncomeKind, settled_at: EpochMillis, transaction: Transaction) -> Result<Self, AppErrors> {
+        if !transaction.is_exchange() {
+            return Err(AppErrors::InvalidOperation(format!("{} transaction is not valid for an income.", transaction)));
         }
+        Ok(Income { kind, transaction, settled_at })
+    }
+
+    pub(crate) fn get_transaction(&self) -> &Transaction {
+        &self.transaction
     }
 
-    // pub(crate) fn get_settled_at(&self) -> EpochMillis {
-    //     self.settled_at
-    // }
+    /// Received net of the fees.
+    pub(crate) fn get_transaction_amount_currency(&self) -> (u128, FiatCurrency) {
+        self.transaction.get_proceeds()
+    }
+
+    /// Amount received in the user currency.
+    pub(crate) fn get_amount(
+        &self,
+        user_fiat_currency: &FiatCurrency,
+        fx_rates: &FxRates,
+        rate_date: RateDate
+    ) -> Result<u128, AppErrors> {
+        let (amount, currency) = self.get_transaction_amount_currency();
+        fx_rates.convert_settled(amount, &currency, user_fiat_currency, self.settled_at, rate_date)
+    }
 }
 
+/// Cash paid for holding the asset: maintenance, property taxes, custody.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct Sell {
+pub struct Expense {
+    pub description: String,
     transaction: Transaction,
-    settled_at: EpochMillis
+    pub settled_at: EpochMillis
+}
+
+impl Expense {
+    pub(crate) fn new(
+        description: String,
+        settled_at: EpochMillis,
+        transaction_str: String,
+        amount: u128,
+        currency: FiatCurrency
+    ) -> Result<Self, AppErrors> {
+        Expense::from_transaction(description, settled_at, Transaction::new(&transaction_str, amount, currency)?)
+    }
+
+    pub(crate) fn from_transaction(description: String, settled_at: EpochMillis, transaction: Transaction) -> Result<Self, AppErrors> {
+        if !transaction.is_exchange() {
+            return Err(AppErrors::InvalidOperation(format!("{} transaction is not valid for an expense.", transaction)));
+        }
+        Ok(Expense { description, transaction, settled_at })
+    }
+
+    pub(crate) fn get_transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    /// Paid including the fees.
+    pub(crate) fn get_transaction_amount_currency(&self) -> (u128, FiatCurrency) {
+        self.transaction.get_cost()
+    }
+
+    /// Amount paid in the user currency.
+    pub(crate) fn get_amount(
+        &self,
+        user_fiat_currency: &FiatCurrency,
+        fx_rates: &FxRates,
+        rate_date: RateDate
+    ) -> Result<u128, AppErrors> {
+        let (amount, currency) = self.get_transaction_amount_currency();
+        fx_rates.convert_settled(amount, &currency, user_fiat_currency, self.settled_at, rate_date)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Fund {
     name: String,
-    location: Option<String>
+    location: Option<String>,
+    /// Target weight of each asset type, "GOLD" or "BTC", in basis points.
+    #[serde(default)]
+    targets: BTreeMap<String, u32>
 }
 
 impl Fund {
     pub fn new(name: FundName) -> Self {
         Fund {
             name,
-            location: None
+            location: None,
+            targets: BTreeMap::new()
         }
     }
+
+    pub fn get_name(&self) -> &FundName {
+        &self.name
+    }
+
+    pub fn get_targets(&self) -> &BTreeMap<String, u32> {
+        &self.targets
+    }
+
+    /// Targets must add up to 100%, 10_000 basis points. Empty clears them.
+    pub fn set_targets(&mut self, targets: BTreeMap<String, u32>) -> Result<(), AppErrors> {
+        let mut normalized: BTreeMap<String, u32> = BTreeMap::new();
+        for (asset_type_str, weight) in targets {
+            let entry = normalized.entry(asset_type_str.to_uppercase()).or_default();
+            *entry = entry.saturating_add(weight);
+        }
+        let total: u64 = normalized.values().map(|weight| *weight as u64).sum();
+        if !normalized.is_empty() && total != 10_000 {
+            return Err(AppErrors::InvalidOperation(format!(
+                "Targets of fund {} add up to {} basis points, not 10000.", self.name, total
+            )));
+        }
+        self.targets = normalized;
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MarketSnapshot {
     timestamp: EpochMillis,
-    asset_type_str: String,
+    /// Market of the price, "BTC" or "GOLD-GRAM-24K". The median is the
+    /// price of one unit of that market.
+    #[serde(alias = "asset_type_str")]
+    market_key: MarketKey,
     source: Option<String>,
     currency: FiatCurrency,
     market: Option<String>,
@@ -125,9 +394,10 @@ pub struct MarketSnapshot {
 }
 
 impl MarketSnapshot {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         timestamp: EpochMillis,
-        asset_type_str: String,
+        market_key: MarketKey,
         source: Option<String>,
         currency: FiatCurrency,
         market: Option<String>,
@@ -137,7 +407,7 @@ impl MarketSnapshot {
     ) -> Self {
         MarketSnapshot {
             timestamp,
-            asset_type_str,
+            market_key,
             source,
             currency,
             market,
@@ -146,6 +416,37 @@ impl MarketSnapshot {
             median
         }
     }
+
+    pub fn get_timestamp(&self) -> EpochMillis {
+        self.timestamp
+    }
+
+    /// A price older than `max_age_millis` must be refreshed.
+    pub fn is_stale(&self, max_age_millis: EpochMillis) -> bool {
+        Now::get_millis_since(self.timestamp) > max_age_millis
+    }
+
+    pub fn get_market_key(&self) -> &str {
+        &self.market_key
+    }
+
+    #[allow(dead_code)]
+    pub fn get_source(&self) -> Option<&String> {
+        self.source.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_market(&self) -> Option<&String> {
+        self.market.as_ref()
+    }
+
+    pub fn get_median(&self) -> u64 {
+        self.median
+    }
+
+    pub fn get_currency(&self) -> &FiatCurrency {
+        &self.currency
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -157,21 +458,47 @@ pub struct PriceSheet {
     pub doge_4_decimals: Option<u64>,
     pub ltc: Option<u64>,
     pub eth: Option<u64>,
+    /// Any other key of the sheet, tickers and symbols: "VOO", "BMV:AMXL".
+    #[serde(default)]
+    pub symbols: BTreeMap<MarketKey, u64>,
     pub created_at: EpochMillis
 }
 
+impl PriceSheet {
+    const KNOWN_KEYS: [&'static str; 6] = ["GOLD-GRAM-24K", "GOLD-GRAM-21K", "BTC", "DOGE4DECIMALS", "LTC", "ETH"];
+
+    pub fn from_file(file_path: &str) -> Result<Self, AppErrors> {
+        let price_sheet = read_json_file(file_path)?;
+        let mut symbols = BTreeMap::new();
+        for (key, _) in price_sheet.entries().filter(|(key, _)| !PriceSheet::KNOWN_KEYS.contains(key)) {
+            if let Some(price) = parse_option_u64(&price_sheet, key)? {
+                symbols.insert(key.to_uppercase(), price);
+            }
+        }
+        Ok(PriceSheet {
+            gold_gram_24k: parse_option_u64(&price_sheet, "GOLD-GRAM-24K")?,
+            gold_gram_21k: parse_option_u64(&price_sheet, "GOLD-GRAM-21K")?,
+            btc: parse_option_u64(&price_sheet, "BTC")?,
+            doge_4_decimals: parse_option_u64(&price_sheet, "DOGE4DECIMALS")?,
+            ltc: parse_option_u64(&price_sheet, "LTC")?,
+            eth: parse_option_u64(&price_sheet, "ETH")?,
+            symbols,
+            created_at: Now::new().to_epoch_millis()
+        })
+    }
+}
+
+/// An empty sheet, every price is missing until loaded from a file.
 impl Default for PriceSheet {
     fn default() -> Self {
-        println!("{}", &PRICE_SHEET_FILEPATH);
-        let content = fs::read_to_string(&PRICE_SHEET_FILEPATH).expect("Error reading prices mxn file.");
-        let price_sheet = json::parse(&content).unwrap();
         PriceSheet {
-            gold_gram_24k: parse_option_u64(&price_sheet, "GOLD-GRAM-24K"),
-            gold_gram_21k: parse_option_u64(&price_sheet, "GOLD-GRAM-21K"),
-            btc: parse_option_u64(&price_sheet, "BTC"),
-            doge_4_decimals: parse_option_u64(&price_sheet, "DOGE4DECIMALS"),
-            ltc: parse_option_u64(&price_sheet, "LTC"),
-            eth: parse_option_u64(&price_sheet, "ETH"),
+            gold_gram_24k: None,
+            gold_gram_21k: None,
+            btc: None,
+            doge_4_decimals: None,
+            ltc: None,
+            eth: None,
+            symbols: BTreeMap::new(),
             created_at: Now::new().to_epoch_millis()
         }
     }
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Months, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::errors::AppErrors;
+use crate::models::FiatCurrency;
+use crate::types::{AssetId, EpochMillis, FundName};
+use crate::utils::format_amount;
+use crate::utils::now::Now;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Interval {
+    Daily,
+    Weekly,
+    /// Same day of every month, or its last day.
+    Monthly
+}
+
+impl Interval {
+    /// Dates from `from` every interval, up to `to` inclusive.
+    pub fn get_dates(&self, from: EpochMillis, to: EpochMillis) -> Vec<EpochMillis> {
+        let days = match self {
+            Interval::Daily => 1,
+            Interval::Weekly => 7,
+            Interval::Monthly => 0
+        };
+        let start = Utc.timestamp_millis_opt(from as i64).unwrap();
+        (0u32..)
+            .map_while(|step| {
+                let date = if days == 0 {
+                    start.checked_add_months(Months::new(step))?.timestamp_millis() as EpochMillis
+                } else {
+                    Now::new_from_epoch_millis(from).increment_days(days * step as u64)?.to_epoch_millis()
+                };
+                (date <= to).then_some(date)
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Interval {
+    type Err = AppErrors;
+
+    fn from_str(input: &str) -> Result<Interval, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "daily" | "day" => Ok(Interval::Daily),
+            "weekly" | "week" => Ok(Interval::Weekly),
+            "monthly" | "month" => Ok(Interval::Monthly),
+            _ => Err(AppErrors::InvalidOperation(format!("Unknown interval {}.", input)))
+        }
+    }
+}
+
+/// An asset held at the date.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HoldingValue {
+    pub asset_id: AssetId,
+    pub fund: FundName,
+    pub asset_type_str: String,
+    pub held_units: u128,
+    pub amount: u128
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CashValue {
+    pub name: String,
+    pub fund: FundName,
+    pub amount: u128
+}
+
+/// What every fund held at a date, in the user currency at the rates and
+/// prices of the date.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Valuation {
+    pub currency: FiatCurrency,
+    pub at: EpochMillis,
+    pub holdings: Vec<HoldingValue>,
+    pub cash: Vec<CashValue>
+}
+
+impl Valuation {
+    pub fn get_assets_amount(&self) -> u128 {
+        self.holdings.iter().map(|holding| holding.amount).sum()
+    }
+
+    pub fn get_cash_amount(&self) -> u128 {
+        self.cash.iter().map(|cash| cash.amount).sum()
+    }
+
+    pub fn get_net_worth(&self) -> u128 {
+        self.get_assets_amount() + self.get_cash_amount()
+    }
+
+    /// Assets and cash of each fund.
+    pub fn get_by_fund(&self) -> BTreeMap<&FundName, u128> {
+        let mut by_fund = BTreeMap::new();
+        for (fund, amount) in self.holdings
+            .iter()
+            .map(|holding| (&holding.fund, holding.amount))
+            .chain(self.cash.iter().map(|cash| (&cash.fund, cash.amount))) {
+            *by_fund.entry(fund).or_default() += amount;
+        }
+        by_fund
+    }
+}
+
+/// Id | Fund | Type | Amount, the cash, then the net worth by fund.
+impl fmt::Display for Valuation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = self.currency.get_decimals();
+        writeln!(f, "Valuation at {}", Now::new_from_epoch_millis(self.at).to_date_string())?;
+        writeln!(f, "{:>4} | {:<16} | {:<14} | {:>18}", "Id", "Fund", "Type", format!("Amount {:?}", self.currency))?;
+        for holding in self.holdings.iter() {
+            writeln!(
+                f,
+                "{:>4} | {:<16} | {:<14} | {:>18}",
+                holding.asset_id,
+                holding.fund,
+                holding.asset_type_str,
+                format_amount(holding.amount as i128, decimals)
+            )?;
+        }
+        for cash in self.cash.iter() {
+            writeln!(f, "{:>4} | {:<16} | {:<14} | {:>18}", "", cash.fund, cash.name, format_amount(cash.amount as i128, decimals))?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Net worth")?;
+        for (fund, amount) in self.get_by_fund() {
+            writeln!(f, "{:<40} | {:>18}", fund, format_amount(amount as i128, decimals))?;
+        }
+        writeln!(f, "{:<40} | {:>18}", "TOTAL", format_amount(self.get_net_worth() as i128, decimals))
+    }
+}
+
+/// Net worth of every date of an interval, oldest first.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NetWorthSeries {
+    pub currency: FiatCurrency,
+    pub interval: Interval,
+    pub points: Vec<Valuation>
+}
+
+impl NetWorthSeries {
+    fn get_funds(&self) -> BTreeSet<&FundName> {
+        self.points.iter().flat_map(|point| point.get_by_fund().into_keys()).collect()
+    }
+
+    /// Writes a row per date with the totals and a column per fund,
+    /// amounts in minor units like the price files.
+    pub fn export_csv(&self, file_path: &str) -> Result<(), AppErrors> {
+        let io = |err: csv::Error| AppErrors::Io(format!("{}: {}", file_path, err));
+        let funds = self.get_funds();
+        let mut writer = csv::Writer::from_path(file_path).map_err(io)?;
+        let header = ["date", "assets", "cash", "net_worth"]
+            .into_iter()
+            .map(str::to_string)
+            .chain(funds.iter().map(|fund| fund.to_string()));
+        writer.write_record(header).map_err(io)?;
+        for point in self.points.iter() {
+            let by_fund = point.get_by_fund();
+            let record = [
+                Now::new_from_epoch_millis(point.at).to_date_string(),
+                point.get_assets_amount().to_string(),
+                point.get_cash_amount().to_string(),
+                point.get_net_worth().to_string()
+            ]
+                .into_iter()
+                .chain(funds.iter().map(|fund| by_fund.get(fund).copied().unwrap_or(0).to_string()));
+            writer.write_record(record).map_err(io)?;
+        }
+        writer.flush().map_err(|err| AppErrors::Io(format!("{}: {}", file_path, err)))
+    }
+}
+
+/// Date | Assets | Cash | Net worth | Change
+impl fmt::Display for NetWorthSeries {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = self.currency.get_decimals();
+        writeln!(
+            f,
+            "{:<10} | {:>18} | {:>18} | {:>18} | {:>18}",
+            "Date", format!("Assets {:?}", self.currency), "Cash", "Net worth", "Change"
+        )?;
+        let mut previous = None;
+        for point in self.points.iter() {
+            let net_worth = point.get_net_worth();
+            writeln!(
+                f,
+                "{:<10} | {:>18} | {:>18} | {:>18} | {:>18}",
+                Now::new_from_epoch_millis(point.at).to_date_string(),
+                format_amount(point.get_assets_amount() as i128, decimals),
+                format_amount(point.get_cash_amount() as i128, decimals),
+                format_amount(net_worth as i128, decimals),
+                match previous {
+                    Some(previous) => format_amount(net_worth as i128 - previous as i128, decimals),
+                    None => "-".to_string()
+                }
+            )?;
+            previous = Some(net_worth);
+        }
+        Ok(())
+    }
+}
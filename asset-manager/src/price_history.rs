@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::errors::AppErrors;
+use crate::models::MarketSnapshot;
+use crate::types::{EpochMillis, MarketKey};
+use crate::utils::{read_file, write_file};
+
+/// Append-only history of market prices, by market key and timestamp.
+/// Persisted as a JSON list of snapshots, `files/<env>/market.json`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PriceHistory {
+    snapshots: BTreeMap<MarketKey, BTreeMap<EpochMillis, MarketSnapshot>>
+}
+
+impl PriceHistory {
+    /// A missing file is an empty history.
+    pub fn from_file(file_path: &str) -> Result<Self, AppErrors> {
+        let mut price_history = PriceHistory::default();
+        if !Path::new(file_path).exists() {
+            return Ok(price_history);
+        }
+        let snapshots: Vec<MarketSnapshot> = serde_json::from_str(&read_file(file_path)?)
+            .map_err(|err| AppErrors::MalformedFile(format!("{}: {}", file_path, err)))?;
+        for snapshot in snapshots {
+            price_history.append(snapshot)?;
+        }
+        Ok(price_history)
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), AppErrors> {
+        let snapshots: Vec<&MarketSnapshot> = self.snapshots
+            .values()
+            .flat_map(|by_timestamp| by_timestamp.values())
+            .collect();
+        let content = serde_json::to_string_pretty(&snapshots)
+            .map_err(|err| AppErrors::MalformedFile(err.to_string()))?;
+        write_file(file_path, content)
+    }
+
+    /// Recorded snapshots are never replaced. Appending the same snapshot
+    /// twice is a no-op, a different price at the same timestamp fails.
+    pub fn append(&mut self, snapshot: MarketSnapshot) -> Result<(), AppErrors> {
+        let by_timestamp = self.snapshots
+            .entry(snapshot.get_market_key().to_string())
+            .or_default();
+        match by_timestamp.get(&snapshot.get_timestamp()) {
+            Some(recorded) if *recorded == snapshot => Ok(()),
+            Some(_) => Err(AppErrors::InvalidOperation(format!(
+                "{} already has a price at {}.", snapshot.get_market_key(), snapshot.get_timestamp()
+            ))),
+            None => {
+                by_timestamp.insert(snapshot.get_timestamp(), snapshot);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get_market_keys(&self) -> Vec<&MarketKey> {
+        self.snapshots.keys().collect()
+    }
+
+    /// Last known price on or before the date.
+    pub fn price_at(&self, market_key: &str, at: EpochMillis) -> Option<&MarketSnapshot> {
+        self.snapshots
+            .get(market_key)?
+            .range(..=at)
+            .next_back()
+            .map(|(_, snapshot)| snapshot)
+    }
+
+    /// Every price between both dates, inclusive, oldest first.
+    pub fn range(&self, market_key: &str, from: EpochMillis, to: EpochMillis) -> Vec<&MarketSnapshot> {
+        match self.snapshots.get(market_key) {
+            Some(by_timestamp) if from <= to => by_timestamp.range(from..=to).map(|(_, snapshot)| snapshot).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    pub fn latest(&self, market_key: &str) -> Option<&MarketSnapshot> {
+        self.snapshots
+            .get(market_key)?
+            .values()
+            .next_back()
+    }
+}
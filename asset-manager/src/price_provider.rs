@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use json::JsonValue;
+
+use crate::errors::AppErrors;
+use crate::models::{FiatCurrency, MarketSnapshot, PriceSheet};
+use crate::types::{EpochMillis, MarketKey};
+use crate::utils::now::Now;
+use crate::utils::{parse_option_string, parse_option_u64};
+
+/// A source of market prices. Every price is the price of one unit of the
+/// market, "BTC" or "GOLD-GRAM-24K", see `AssetType::get_market_key`.
+pub trait PriceProvider {
+    /// Recorded as the `source` of every snapshot of the provider.
+    fn get_source(&self) -> String;
+
+    /// Fails with `MissingPrice` when the provider does not quote the market.
+    fn get_price(&self, market_key: &str) -> Result<MarketSnapshot, AppErrors>;
+}
+
+/// The naive JSON sheet, prices in MXN.
+impl PriceProvider for PriceSheet {
+    fn get_source(&self) -> String {
+        "price_sheet".to_string()
+    }
+
+    fn get_price(&self, market_key: &str) -> Result<MarketSnapshot, AppErrors> {
+        let median = match market_key {
+            "BTC" => self.btc,
+            "LTC" => self.ltc,
+            "ETH" => self.eth,
+            "DOGE" => self.doge_4_decimals,
+            "GOLD-GRAM-24K" => self.gold_gram_24k,
+            "GOLD-GRAM-21K" => self.gold_gram_21k,
+            _ => self.symbols.get(market_key).copied()
+        }.ok_or_else(|| AppErrors::MissingPrice(market_key.to_string()))?;
+        Ok(MarketSnapshot::new(
+            self.created_at,
+            market_key.to_string(),
+            Some(self.get_source()),
+            FiatCurrency::MXN,
+            Some("naive".to_string()),
+            None,
+            None,
+            median
+        ))
+    }
+}
+
+/// A CSV price dump with the header
+/// `date,market_key,median,currency,market,top,bottom`.
+/// Only `date`, `market_key` and `median` are required, the currency
+/// defaults to MXN. The most recent row of each market is its price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvPriceProvider {
+    file_path: String,
+    latest: HashMap<MarketKey, MarketSnapshot>
+}
+
+impl CsvPriceProvider {
+    pub fn from_file(file_path: &str) -> Result<Self, AppErrors> {
+        let malformed = |err: csv::Error| AppErrors::MalformedFile(format!("{}: {}", file_path, err));
+        let mut reader = csv::Reader::from_path(file_path).map_err(malformed)?;
+        let headers = reader.headers().map_err(malformed)?.clone();
+        let source = format!("csv:{}", file_path);
+
+        let mut latest: HashMap<MarketKey, MarketSnapshot> = HashMap::new();
+        for record in reader.records() {
+            let record = record.map_err(malformed)?;
+            let field = |name: &str| headers
+                .iter()
+                .position(|header| header.trim() == name)
+                .and_then(|index| record.get(index))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty());
+            let amount = |name: &str| field(name)
+                .map(|value| value.parse::<u64>().map_err(|_| AppErrors::MalformedAmount(format!("{} = {}", name, value))))
+                .transpose();
+
+            let market_key = field("market_key")
+                .ok_or_else(|| AppErrors::MalformedFile(format!("{}: row without market_key", file_path)))?;
+            let date = field("date")
+                .ok_or_else(|| AppErrors::MalformedFile(format!("{}: row without date", file_path)))?;
+            let snapshot = MarketSnapshot::new(
+                Now::new_from_datetime_str(date, "%Y-%m-%d")?.to_epoch_millis(),
+                market_key.to_string(),
+                Some(source.clone()),
+                FiatCurrency::from_str(field("currency").unwrap_or("MXN"))?,
+                field("market").map(|market| market.to_string()),
+                amount("top")?,
+                amount("bottom")?,
+                amount("median")?.ok_or_else(|| AppErrors::MissingPrice(format!("{} at {}", market_key, date)))?
+            );
+            match latest.get(market_key) {
+                Some(recorded) if recorded.get_timestamp() > snapshot.get_timestamp() => {},
+                _ => { latest.insert(market_key.to_string(), snapshot); }
+            }
+        }
+        Ok(CsvPriceProvider { file_path: file_path.to_string(), latest })
+    }
+}
+
+impl PriceProvider for CsvPriceProvider {
+    fn get_source(&self) -> String {
+        format!("csv:{}", self.file_path)
+    }
+
+    fn get_price(&self, market_key: &str) -> Result<MarketSnapshot, AppErrors> {
+        self.latest
+            .get(market_key)
+            .cloned()
+            .ok_or_else(|| AppErrors::MissingPrice(market_key.to_string()))
+    }
+}
+
+/// A generic HTTP JSON endpoint. `{market_key}` in the url is replaced by
+/// the market, the response is
+/// `{ "median": 32481000, "currency": "MXN", "market": "bitso", "top", "bottom", "timestamp" }`
+/// where only the median is required and the timestamp is in epoch millis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpPriceProvider {
+    url: String,
+    timeout: Duration
+}
+
+impl HttpPriceProvider {
+    pub fn new(url: &str) -> Self {
+        HttpPriceProvider { url: url.to_string(), timeout: Duration::from_secs(10) }
+    }
+
+    fn parse_response(&self, market_key: &str, response: &JsonValue) -> Result<MarketSnapshot, AppErrors> {
+        let currency = match parse_option_string(response, "currency") {
+            Some(currency) => FiatCurrency::from_str(&currency)?,
+            None => FiatCurrency::MXN
+        };
+        let timestamp: EpochMillis = parse_option_u64(response, "timestamp")?
+            .unwrap_or_else(|| Now::new().to_epoch_millis());
+        Ok(MarketSnapshot::new(
+            timestamp,
+            market_key.to_string(),
+            Some(self.get_source()),
+            currency,
+            parse_option_string(response, "market"),
+            parse_option_u64(response, "top")?,
+            parse_option_u64(response, "bottom")?,
+            parse_option_u64(response, "median")?
+                .ok_or_else(|| AppErrors::MissingPrice(market_key.to_string()))?
+        ))
+    }
+}
+
+impl PriceProvider for HttpPriceProvider {
+    fn get_source(&self) -> String {
+        format!("http:{}", self.url)
+    }
+
+    fn get_price(&self, market_key: &str) -> Result<MarketSnapshot, AppErrors> {
+        let url = self.url.replace("{market_key}", market_key);
+        let body = match ureq::get(&url).timeout(self.timeout).call() {
+            Ok(response) => response.into_string()?,
+            Err(ureq::Error::Status(404, _)) => return Err(AppErrors::MissingPrice(market_key.to_string())),
+            Err(err) => return Err(AppErrors::Io(format!("{}: {}", url, err)))
+        };
+        let response = json::parse(&body)
+            .map_err(|err| AppErrors::MalformedFile(format!("{}: {}", url, err)))?;
+        self.parse_response(market_key, &response)
+    }
+}
+
+/// Asks every provider in order, the first price found wins.
+#[derive(Default)]
+pub struct PriceProviders {
+    providers: Vec<Box<dyn PriceProvider>>
+}
+
+impl PriceProviders {
+    pub fn new() -> Self {
+        PriceProviders::default()
+    }
+
+    pub fn push(&mut self, provider: Box<dyn PriceProvider>) {
+        self.providers.push(provider);
+    }
+}
+
+impl PriceProvider for PriceProviders {
+    fn get_source(&self) -> String {
+        self.providers
+            .iter()
+            .map(|provider| provider.get_source())
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// A failing provider falls back to the next one. Returns the error of
+    /// the last provider when none has the price.
+    fn get_price(&self, market_key: &str) -> Result<MarketSnapshot, AppErrors> {
+        let mut last_err = AppErrors::MissingPrice(market_key.to_string());
+        for provider in self.providers.iter() {
+            match provider.get_price(market_key) {
+                Ok(snapshot) => return Ok(snapshot),
+                Err(err) => last_err = err
+            }
+        }
+        Err(last_err)
+    }
+}
+
+/// The latest price of every market, skipping the stale ones so the next
+/// provider refreshes them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedPrices {
+    snapshots: HashMap<MarketKey, MarketSnapshot>,
+    max_age_millis: EpochMillis
+}
+
+impl CachedPrices {
+    pub fn new(snapshots: HashMap<MarketKey, MarketSnapshot>, max_age_millis: EpochMillis) -> Self {
+        CachedPrices { snapshots, max_age_millis }
+    }
+}
+
+impl PriceProvider for CachedPrices {
+    fn get_source(&self) -> String {
+        "cache".to_string()
+    }
+
+    /// Keeps the source of the cached snapshot.
+    fn get_price(&self, market_key: &str) -> Result<MarketSnapshot, AppErrors> {
+        match self.snapshots.get(market_key) {
+            Some(snapshot) if !snapshot.is_stale(self.max_age_millis) => Ok(snapshot.clone()),
+            Some(_) => Err(AppErrors::MissingPrice(format!("{}, the cached price is stale", market_key))),
+            None => Err(AppErrors::MissingPrice(market_key.to_string()))
+        }
+    }
+}
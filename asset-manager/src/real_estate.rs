@@ -0,0 +1,105 @@
+use std::str::FromStr;
+
+use json::JsonValue;
+use serde::{Serialize, Deserialize};
+
+use crate::errors::AppErrors;
+use crate::models::FiatCurrency;
+use crate::types::EpochMillis;
+use crate::utils::now::Now;
+use crate::utils::{parse_decimal, parse_option_string, parse_u128, proportional};
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+const YEAR_DAYS: u64 = 365;
+
+/// Dated valuation of a whole property.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Appraisal {
+    pub appraised_at: EpochMillis,
+    pub amount: u128,
+    pub currency: FiatCurrency,
+    pub appraiser: Option<String>
+}
+
+impl Appraisal {
+    /// `{ "appraised_at": "2023-05-10", "amount": "350000000", "currency": "MXN" }`,
+    /// the amount in minor units like every transaction.
+    pub fn from_json(data: &JsonValue) -> Result<Self, AppErrors> {
+        let currency = match parse_option_string(data, "currency") {
+            Some(currency) => FiatCurrency::from_str(&currency)?,
+            None => FiatCurrency::MXN
+        };
+        Ok(Appraisal {
+            appraised_at: Now::new_from_datetime_str(&data["appraised_at"].to_string(), "%Y-%m-%d")?.to_epoch_millis(),
+            amount: parse_u128(data, "amount")?,
+            currency,
+            appraiser: parse_option_string(data, "appraiser")
+        })
+    }
+}
+
+/// Description and valuation of a property.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Property {
+    /// Square meters with 2 decimals.
+    pub area: Option<u128>,
+    pub location: Option<String>,
+    /// Yearly appreciation in basis points, applied from the last appraisal.
+    pub appreciation_bps: Option<u32>,
+    /// Sorted by date.
+    pub appraisals: Vec<Appraisal>
+}
+
+impl Property {
+    /// Parses `area` in square meters, `location`, `appreciation` as a
+    /// yearly percentage, "4.5", and the `appraisals`.
+    pub fn from_json(data: &JsonValue) -> Result<Self, AppErrors> {
+        let mut property = Property {
+            area: parse_option_string(data, "area").map(|area| parse_decimal(&area, 2)).transpose()?,
+            location: parse_option_string(data, "location"),
+            appreciation_bps: parse_option_string(data, "appreciation")
+                .map(|appreciation| parse_decimal(&appreciation, 2).and_then(|bps| {
+                    u32::try_from(bps).map_err(|_| AppErrors::MalformedAmount(appreciation))
+                }))
+                .transpose()?,
+            appraisals: Vec::new()
+        };
+        for appraisal in data["appraisals"].members() {
+            property.add_appraisal(Appraisal::from_json(appraisal)?)?;
+        }
+        Ok(property)
+    }
+
+    /// Only one appraisal per date.
+    pub fn add_appraisal(&mut self, appraisal: Appraisal) -> Result<(), AppErrors> {
+        if self.appraisals.iter().any(|recorded| recorded.appraised_at == appraisal.appraised_at) {
+            return Err(AppErrors::InvalidOperation(format!(
+                "Property already has an appraisal at {}.", Now::new_from_epoch_millis(appraisal.appraised_at).to_date_string()
+            )));
+        }
+        let index = self.appraisals.partition_point(|recorded| recorded.appraised_at < appraisal.appraised_at);
+        self.appraisals.insert(index, appraisal);
+        Ok(())
+    }
+
+    /// The last appraisal up to `at`, appreciated by whole years compounded
+    /// and the remaining days pro rata. Fails before the first appraisal.
+    pub fn get_value_at(&self, at: EpochMillis) -> Result<(u128, FiatCurrency), AppErrors> {
+        let appraisal = self.appraisals
+            .iter()
+            .rev()
+            .find(|appraisal| appraisal.appraised_at <= at)
+            .ok_or_else(|| AppErrors::MissingPrice(format!(
+                "appraisal at {}", Now::new_from_epoch_millis(at).to_date_string()
+            )))?;
+        let mut value = appraisal.amount;
+        if let Some(appreciation_bps) = self.appreciation_bps {
+            let days = (at - appraisal.appraised_at) / DAY_MILLIS;
+            for _ in 0..days / YEAR_DAYS {
+                value += proportional(value, appreciation_bps as u128, 10_000);
+            }
+            value += proportional(value, appreciation_bps as u128 * (days % YEAR_DAYS) as u128, 10_000 * YEAR_DAYS as u128);
+        }
+        Ok((value, appraisal.currency.clone()))
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::asset::AssetEvaluation;
+use crate::errors::AppErrors;
+use crate::models::{FiatCurrency, Fund};
+use crate::types::FundName;
+use crate::utils::{format_amount, proportional};
+
+/// Current and target weight of an asset type in a fund.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DriftLine {
+    pub asset_type_str: String,
+    /// Market value of the units held.
+    pub now_amount: u128,
+    pub weight_bps: u32,
+    pub target_bps: u32,
+    /// weight - target, positive when the fund holds too much.
+    pub drift_bps: i64,
+    /// Amount to buy, or to sell when negative, to restore the target.
+    pub suggestion: i128
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DriftReport {
+    pub fund: FundName,
+    pub currency: FiatCurrency,
+    pub now_amount: u128,
+    pub lines: Vec<DriftLine>
+}
+
+impl DriftReport {
+    /// `evaluations` are the assets of the fund. Asset types held without
+    /// a target have a target of zero.
+    pub fn new(fund: &Fund, currency: FiatCurrency, evaluations: &[AssetEvaluation]) -> Result<Self, AppErrors> {
+        if fund.get_targets().is_empty() {
+            return Err(AppErrors::InvalidOperation(format!(
+                "Fund {} has no allocation targets.", fund.get_name()
+            )));
+        }
+        let mut held: BTreeMap<String, u128> = fund.get_targets()
+            .keys()
+            .map(|asset_type_str| (asset_type_str.clone(), 0))
+            .collect();
+        for evaluation in evaluations {
+            *held.entry(evaluation.get_asset_type_str().to_string()).or_default() += evaluation.get_now_amount();
+        }
+        let now_amount: u128 = held.values().sum();
+
+        let lines = held
+            .into_iter()
+            .map(|(asset_type_str, amount)| {
+                let target_bps = fund.get_targets().get(&asset_type_str).copied().unwrap_or(0);
+                let weight_bps = if now_amount == 0 { 0 } else { proportional(amount, 10_000, now_amount) as u32 };
+                let target_amount = proportional(now_amount, target_bps as u128, 10_000);
+                DriftLine {
+                    asset_type_str,
+                    now_amount: amount,
+                    weight_bps,
+                    target_bps,
+                    drift_bps: weight_bps as i64 - target_bps as i64,
+                    suggestion: target_amount as i128 - amount as i128
+                }
+            })
+            .collect();
+
+        Ok(DriftReport { fund: fund.get_name().clone(), currency, now_amount, lines })
+    }
+}
+
+/// Type | Now | Weight | Target | Drift | Suggestion
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = self.currency.get_decimals();
+        writeln!(
+            f,
+            "Fund {}, {} {:?}",
+            self.fund,
+            format_amount(self.now_amount as i128, decimals),
+            self.currency
+        )?;
+        writeln!(
+            f,
+            "{:<14} | {:>18} | {:>8} | {:>8} | {:>8} | {:>24}",
+            "Type", "Now", "Weight", "Target", "Drift", "Suggestion"
+        )?;
+        for line in self.lines.iter() {
+            let suggestion = match line.suggestion {
+                0 => "-".to_string(),
+                amount if amount > 0 => format!("BUY {}", format_amount(amount, decimals)),
+                amount => format!("SELL {}", format_amount(-amount, decimals))
+            };
+            writeln!(
+                f,
+                "{:<14} | {:>18} | {:>7}% | {:>7}% | {:>7}% | {:>24}",
+                line.asset_type_str,
+                format_amount(line.now_amount as i128, decimals),
+                format_amount(line.weight_bps as i128, 2),
+                format_amount(line.target_bps as i128, 2),
+                format_amount(line.drift_bps as i128, 2),
+                suggestion
+            )?;
+        }
+        Ok(())
+    }
+}
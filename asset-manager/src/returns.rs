@@ -0,0 +1,126 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::errors::AppErrors;
+use crate::fx::FxRates;
+use crate::price_history::PriceHistory;
+use crate::types::EpochMillis;
+use crate::utils::{annualize_growth_bps, YEAR_MILLIS};
+
+/// Yearly returns of a group of assets, in basis points. Cash accounts are
+/// not part of them.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GroupReturns {
+    pub label: String,
+    /// Money weighted, the rate that makes every buy, sell, income and
+    /// expense plus the value held worth zero.
+    pub xirr_bps: Option<i128>,
+    /// Time weighted, the growth between buys and sells chained and
+    /// annualized. None without the prices of the history at those dates.
+    pub twr_bps: Option<i128>
+}
+
+impl GroupReturns {
+    /// `now_amount` is the market value of the units held.
+    pub(crate) fn new(
+        label: String,
+        assets: &[&Asset],
+        now_amount: u128,
+        price_history: &PriceHistory,
+        fx_rates: &FxRates,
+        now: EpochMillis
+    ) -> Result<Self, AppErrors> {
+        let mut flows = Vec::new();
+        for asset in assets {
+            flows.extend(asset.get_cash_flows(fx_rates)?);
+        }
+        flows.push((now, now_amount as i128));
+        let twr_bps = match time_weighted_bps(assets, now_amount, price_history, fx_rates, now) {
+            Err(AppErrors::MissingPrice(_)) => None,
+            result => result?
+        };
+        Ok(GroupReturns { label, xirr_bps: xirr_bps(&flows), twr_bps })
+    }
+}
+
+fn net_present_value(flows: &[(EpochMillis, i128)], first_at: EpochMillis, rate: f64) -> f64 {
+    flows
+        .iter()
+        .map(|(at, amount)| *amount as f64 / (1.0 + rate).powf(at.saturating_sub(first_at) as f64 / YEAR_MILLIS))
+        .sum()
+}
+
+/// Yearly rate of the flows, negative money in and positive money out, in
+/// basis points. None when there is no rate, all the flows in one direction.
+pub fn xirr_bps(flows: &[(EpochMillis, i128)]) -> Option<i128> {
+    let first_at = flows.iter().map(|(at, _)| *at).min()?;
+    if !flows.iter().any(|(_, amount)| *amount < 0) || !flows.iter().any(|(_, amount)| *amount > 0) {
+        return None;
+    }
+    // The value decreases with the rate when the money goes in first.
+    let npv = |rate: f64| net_present_value(flows, first_at, rate);
+    let (mut low, mut high) = (-0.999_999, 1.0);
+    while npv(high) > 0.0 {
+        high *= 2.0;
+        if high > 1e9 {
+            return None;
+        }
+    }
+    if npv(low) < 0.0 {
+        return None;
+    }
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        if npv(middle) > 0.0 { low = middle } else { high = middle }
+    }
+    Some((low * 10_000.0).round() as i128)
+}
+
+/// Growth of each period between buys and sells, valued with the price
+/// history, chained and annualized until now. Income and expenses belong
+/// to the period they are settled in.
+fn time_weighted_bps(
+    assets: &[&Asset],
+    now_amount: u128,
+    price_history: &PriceHistory,
+    fx_rates: &FxRates,
+    now: EpochMillis
+) -> Result<Option<i128>, AppErrors> {
+    let mut dates = BTreeSet::new();
+    let mut payments = Vec::new();
+    for asset in assets {
+        dates.extend(asset.get_buys().iter().map(|buy| buy.settled_at));
+        dates.extend(asset.get_sells().iter().map(|sell| sell.settled_at));
+        payments.extend(asset.get_payment_flows(fx_rates)?);
+    }
+    let Some(first_at) = dates.first().copied() else {
+        return Ok(None);
+    };
+    let value_at = |at: EpochMillis, units_at: EpochMillis| -> Result<u128, AppErrors> {
+        assets
+            .iter()
+            .map(|asset| asset.get_value_at(at, asset.get_held_units_at(units_at), price_history, fx_rates))
+            .sum()
+    };
+
+    let mut growth = 1.0;
+    let mut periods = dates.iter().copied().filter(|at| *at < now).peekable();
+    while let Some(start) = periods.next() {
+        let end = periods.peek().copied().unwrap_or(now);
+        let start_value = value_at(start, start)?;
+        if start_value == 0 {
+            continue;
+        }
+        // Before the buys and sells of the end date.
+        let end_value = if end == now { now_amount } else { value_at(end, end - 1)? };
+        let paid: i128 = payments
+            .iter()
+            .filter(|(at, _)| *at > start && *at <= end)
+            .map(|(_, amount)| amount)
+            .sum();
+        growth *= (end_value as i128 + paid) as f64 / start_value as f64;
+    }
+    Ok(annualize_growth_bps(growth, now.saturating_sub(first_at)))
+}
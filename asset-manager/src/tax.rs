@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::asset::Asset;
+use crate::cost_basis::CostBasisReport;
+use crate::errors::AppErrors;
+use crate::inflation::InflationIndex;
+use crate::models::FiatCurrency;
+use crate::types::{AssetId, EpochMillis, FundName};
+use crate::utils::format_amount;
+use crate::utils::now::Now;
+
+/// ISR is computed in pesos, whatever the user currency.
+pub const TAX_CURRENCY: FiatCurrency = FiatCurrency::MXN;
+
+/// A sell with the basis of its lots updated by inflation, in MXN.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TaxDisposal {
+    pub asset_id: AssetId,
+    pub fund: FundName,
+    pub asset_type_str: String,
+    pub sold_at: EpochMillis,
+    pub units: u128,
+    /// Proceeds net of fees, at the rate of the settlement.
+    pub proceeds: u128,
+    /// Cost of the lots sold, fees included, at the rate of each purchase.
+    pub basis: u128,
+    /// Cost of each lot updated from its acquisition to the month before
+    /// the sell.
+    pub updated_basis: u128,
+    pub gain: i128
+}
+
+impl TaxDisposal {
+    pub fn get_year(&self) -> i32 {
+        Now::new_from_epoch_millis(self.sold_at).get_year()
+    }
+}
+
+/// The disposals of the asset in the year, or every year. `report` must be
+/// in the tax currency at the rate of each settlement.
+pub(crate) fn get_disposals(
+    asset: &Asset,
+    report: &CostBasisReport,
+    index: &InflationIndex,
+    year: Option<i32>
+) -> Result<Vec<TaxDisposal>, AppErrors> {
+    let mut disposals = Vec::new();
+    for disposal in report.disposals.iter() {
+        if year.is_some_and(|year| Now::new_from_epoch_millis(disposal.settled_at).get_year() != year) {
+            continue;
+        }
+        let updated_basis = disposal.pieces
+            .iter()
+            .map(|piece| index.update_for_disposal(piece.cost, piece.acquired_at, disposal.settled_at))
+            .sum::<Result<u128, AppErrors>>()?;
+        disposals.push(TaxDisposal {
+            asset_id: asset.id,
+            fund: asset.get_fund().get_name().clone(),
+            asset_type_str: asset.get_asset_type().to_string(),
+            sold_at: disposal.settled_at,
+            units: disposal.units,
+            proceeds: disposal.exit_amount,
+            basis: disposal.basis,
+            updated_basis,
+            gain: disposal.exit_amount as i128 - updated_basis as i128
+        });
+    }
+    Ok(disposals)
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct TaxYear {
+    pub year: i32,
+    pub disposals: usize,
+    pub proceeds: u128,
+    pub basis: u128,
+    pub updated_basis: u128,
+    pub gains: u128,
+    pub losses: u128
+}
+
+impl TaxYear {
+    /// Gains minus the losses of the same year.
+    pub fn get_taxable_gain(&self) -> i128 {
+        self.gains as i128 - self.losses as i128
+    }
+}
+
+/// Yearly ISR report of the disposals. Exemptions, like the sale of the
+/// home, and the depreciation of constructions are not applied.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TaxReport {
+    /// By date.
+    pub disposals: Vec<TaxDisposal>,
+    pub years: Vec<TaxYear>
+}
+
+impl TaxReport {
+    pub fn new(mut disposals: Vec<TaxDisposal>) -> Self {
+        disposals.sort_by_key(|disposal| (disposal.sold_at, disposal.asset_id));
+        let mut years: BTreeMap<i32, TaxYear> = BTreeMap::new();
+        for disposal in disposals.iter() {
+            let year = disposal.get_year();
+            let summary = years.entry(year).or_insert_with(|| TaxYear { year, ..TaxYear::default() });
+            summary.disposals += 1;
+            summary.proceeds += disposal.proceeds;
+            summary.basis += disposal.basis;
+            summary.updated_basis += disposal.updated_basis;
+            if disposal.gain >= 0 {
+                summary.gains += disposal.gain as u128;
+            } else {
+                summary.losses += disposal.gain.unsigned_abs();
+            }
+        }
+        TaxReport { disposals, years: years.into_values().collect() }
+    }
+
+    /// Writes the summary of each year as CSV, amounts in MXN cents like
+    /// the price files.
+    pub fn export_csv(&self, file_path: &str) -> Result<(), AppErrors> {
+        let io = |err: csv::Error| AppErrors::Io(format!("{}: {}", file_path, err));
+        let mut writer = csv::Writer::from_path(file_path).map_err(io)?;
+        writer
+            .write_record(["year", "disposals", "proceeds", "basis", "updated_basis", "gains", "losses", "taxable_gain"])
+            .map_err(io)?;
+        for year in self.years.iter() {
+            writer.write_record([
+                year.year.to_string(),
+                year.disposals.to_string(),
+                year.proceeds.to_string(),
+                year.basis.to_string(),
+                year.updated_basis.to_string(),
+                year.gains.to_string(),
+                year.losses.to_string(),
+                year.get_taxable_gain().to_string()
+            ]).map_err(io)?;
+        }
+        writer.flush().map_err(|err| AppErrors::Io(format!("{}: {}", file_path, err)))
+    }
+}
+
+/// Date | Id | Fund | Type | Proceeds | Basis | Updated basis | Gain, then
+/// the summary of each year.
+impl fmt::Display for TaxReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = TAX_CURRENCY.get_decimals();
+        let amount = |amount: i128| format_amount(amount, decimals);
+        writeln!(
+            f,
+            "{:<10} | {:>4} | {:<16} | {:<14} | {:>18} | {:>18} | {:>18} | {:>18}",
+            "Date", "Id", "Fund", "Type", "Proceeds MXN", "Basis", "Updated basis", "Gain"
+        )?;
+        for disposal in self.disposals.iter() {
+            writeln!(
+                f,
+                "{:<10} | {:>4} | {:<16} | {:<14} | {:>18} | {:>18} | {:>18} | {:>18}",
+                Now::new_from_epoch_millis(disposal.sold_at).to_date_string(),
+                disposal.asset_id,
+                disposal.fund,
+                disposal.asset_type_str,
+                amount(disposal.proceeds as i128),
+                amount(disposal.basis as i128),
+                amount(disposal.updated_basis as i128),
+                amount(disposal.gain)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<4} | {:>18} | {:>18} | {:>18} | {:>18}", "Year", "Proceeds", "Gains", "Losses", "Taxable gain")?;
+        for year in self.years.iter() {
+            writeln!(
+                f,
+                "{:<4} | {:>18} | {:>18} | {:>18} | {:>18}",
+                year.year,
+                amount(year.proceeds as i128),
+                amount(year.gains as i128),
+                amount(year.losses as i128),
+                amount(year.get_taxable_gain())
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,45 +1,1539 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+use std::str::FromStr;
+
 use crate::{user::User, models::AppEnv, app::App};
-use crate::models::AssetType;
+use chrono::{Datelike, Utc};
+use clap::Parser;
+
+use crate::asset::{Asset, AssetType};
+use crate::cash::{CashMovement, CashMovementKind};
+use crate::cli::{AssetCommand, CashCommand, Cli, Command, FundCommand, PricesCommand};
+use crate::errors::AppErrors;
+use crate::fees::{parse_fees, Fee, FeeKind};
+use crate::fixed_income::{FixedIncomeKind, FixedIncomeTerms, PaymentKind};
+use crate::fx::FxRates;
+use crate::inflation::InflationIndex;
+use crate::metals::{Metal, WeightUnit};
+use crate::cost_basis::CostBasis;
+use crate::net_worth::Interval;
+use crate::models::{Buy, Expense, FiatCurrency, Fund, Income, IncomeKind, MarketSnapshot, PriceSheet, Sell};
+use crate::price_history::PriceHistory;
+use crate::price_provider::{CsvPriceProvider, HttpPriceProvider, PriceProvider, PriceProviders};
+use crate::real_estate::{Appraisal, Property};
+use crate::transaction::Transaction;
+use crate::types::{AssetId, EpochMillis};
+use crate::returns::{xirr_bps, GroupReturns};
+use crate::utils::{annualize_growth_bps, annualized_bps, format_amount};
+use crate::utils::now::Now;
+
+/// A `%Y-%m-%d` date in epoch milliseconds.
+fn date(datetime: &str) -> EpochMillis {
+    Now::new_from_datetime_str(datetime, "%Y-%m-%d").unwrap().to_epoch_millis()
+}
+
+/// The app of a new test user with a liberty fund.
+fn new_app_with_fund(user_name: &str) -> (App, Fund) {
+    let mut app = App::new(User::new(user_name, "admin123", AppEnv::Dev).unwrap());
+    app.create_fund("liberty".to_string()).unwrap();
+    let fund = app.get_fund(&"liberty".to_string()).unwrap();
+    (app, fund)
+}
 
 #[test]
 fn create_and_load_state_hash() {
-    let mut user = User::new("TESTUSER", "admin123", AppEnv::Dev);
+    let mut user = User::new("TESTVAULT", "admin123", AppEnv::Dev).unwrap();
 
     let mut app = App::new(user.clone());
 
     let fund_name = String::from("liberty");
-    app.create_fund(fund_name.clone());
+    app.create_fund(fund_name.clone()).unwrap();
+
+    let fund = app.get_fund(&fund_name).unwrap();
+
+    let asset_type = AssetType::Bitcoin { address: Some("hash123".to_string()), sats: 100_000_000 };
+    let asset_id = app.create_new_asset(fund.clone(), asset_type);
+
+    assert!(app.get_asset(asset_id).is_ok());
+
+    let asset_type = AssetType::Bitcoin { address: Some("hash456".to_string()), sats: 250_000 };
+    app.create_new_asset(fund, asset_type);
+
+    user.app_state_hash = Some(app.save_state("admin123").unwrap());
+
+    let sealed = fs::read(user.app_state_file_path()).unwrap();
+    assert!(!String::from_utf8_lossy(&sealed).contains("hash123"), "App state must not be plaintext.");
+
+    assert_eq!(
+        App::load_state(user.clone(), "wrong-password").unwrap_err(),
+        AppErrors::InvalidPassword
+    );
+
+    let new_app = App::load_state(user.clone(), "admin123").unwrap();
+    let mut expected = app.clone();
+    expected.owner = user;
+    assert_eq!(new_app, expected);
+
+    fs::remove_file(new_app.owner.app_state_file_path()).unwrap();
+}
+
+#[test]
+fn user_login_lifecycle() {
+    let user_file_path = format!("{}/user_TESTUSER.json", AppEnv::Dev.get_data_dir());
+    let _ = fs::remove_file(&user_file_path);
+    for name in ["../../TESTUSER", "TEST USER", ""] {
+        assert_eq!(
+            User::create(name, "admin123", AppEnv::Dev).unwrap_err(),
+            AppErrors::InvalidUserName(name.to_string())
+        );
+    }
+    assert!(matches!(User::open("TEST/USER", "admin123", AppEnv::Dev).unwrap_err(), AppErrors::InvalidUserName(_)));
+
+    assert_eq!(
+        User::open("TESTUSER", "admin123", AppEnv::Dev).unwrap_err(),
+        AppErrors::UserNotFound("TESTUSER".to_string())
+    );
+
+    let mut user = User::create("TESTUSER", "admin123", AppEnv::Dev).unwrap();
+    assert_eq!(
+        User::create("TESTUSER", "admin123", AppEnv::Dev).unwrap_err(),
+        AppErrors::UserAlreadyExists("TESTUSER".to_string())
+    );
+
+    let mut app = App::new(user.clone());
+    let fund_name = String::from("liberty");
+    app.create_fund(fund_name.clone()).unwrap();
+    let fund = app.get_fund(&fund_name).unwrap();
+    app.create_new_asset(fund.clone(), AssetType::Bitcoin { address: None, sats: 100_000_000 });
+
+    assert_eq!(user.load_app_state("admin123").unwrap_err(), AppErrors::AppStateNotFound);
+    assert_eq!(user.new_app_state(&app, "wrong").unwrap_err(), AppErrors::InvalidPassword);
+    user.new_app_state(&app, "admin123").unwrap();
+    assert_eq!(user.new_app_state(&app, "admin123").unwrap_err(), AppErrors::AppStateAlreadyExists);
+
+    app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 250_000 });
+    user.save_app_state(&app, "admin123").unwrap();
+    user.save().unwrap();
+
+    assert_eq!(
+        User::open("TESTUSER", "wrong", AppEnv::Dev).unwrap_err(),
+        AppErrors::InvalidPassword
+    );
+    let new_user = User::open("TESTUSER", "admin123", AppEnv::Dev).unwrap();
+    let new_app = new_user.load_app_state("admin123").unwrap();
+
+    assert_eq!(new_user, user);
+    assert_eq!(new_app.owner, user);
+    assert_eq!(new_app.get_asset(1).unwrap(), app.get_asset(1).unwrap());
+
+    fs::remove_file(user.app_state_file_path()).unwrap();
+    fs::remove_file(&user_file_path).unwrap();
+}
+
+/// Scratch files of the tests, outside the data dir.
+fn temp_file_path(file_name: &str) -> String {
+    let dir = format!("{}/asset-manager", env::temp_dir().display());
+    fs::create_dir_all(&dir).unwrap();
+    format!("{}/{}", dir, file_name)
+}
+
+#[test]
+fn cli_subcommands_and_plaintext_export() {
+    let cli = Cli::try_parse_from(["asset-manager", "--env", "prod", "--user", "alice", "dashboard", "--json"]).unwrap();
+    assert_eq!(cli.env, AppEnv::Prod);
+    assert_eq!(cli.user, "alice");
+    assert!(matches!(cli.command, Command::Dashboard { json: true }));
+
+    let cli = Cli::try_parse_from(["asset-manager", "-u", "alice", "fund", "add", "liberty"]).unwrap();
+    assert_eq!(cli.env, AppEnv::Dev);
+    assert!(matches!(cli.command, Command::Fund { command: FundCommand::Add { ref name } } if name == "liberty"));
+
+    let cli = Cli::try_parse_from([
+        "asset-manager", "-u", "alice", "asset", "sell", "3", "--sold-at", "2024-05-01",
+        "--units", "10", "--lot", "1", "--amount", "5000", "--fee", "commission:250"
+    ]).unwrap();
+    assert!(matches!(
+        cli.command,
+        Command::Asset { command: AssetCommand::Sell { id: 3, units: Some(10), lot: Some(1), amount: 5000, ref fees, .. } }
+            if fees.len() == 1
+    ));
+    let cli = Cli::try_parse_from(["asset-manager", "-u", "alice", "cash", "deposit", "bank", "--at", "2024-01-02", "--amount", "100"]).unwrap();
+    assert!(matches!(cli.command, Command::Cash { command: CashCommand::Deposit { amount: 100, .. } }));
+    let cli = Cli::try_parse_from(["asset-manager", "-u", "alice", "prices", "update", "--force"]).unwrap();
+    assert!(matches!(cli.command, Command::Prices { command: PricesCommand::Update { force: true } }));
+    let cli = Cli::try_parse_from(["asset-manager", "-u", "alice", "tax", "--year", "2024"]).unwrap();
+    assert!(matches!(cli.command, Command::Tax { year: Some(2024), export: None }));
+    let cli = Cli::try_parse_from(["asset-manager", "-u", "alice", "net-worth", "--at", "2024-06-30"]).unwrap();
+    assert!(matches!(cli.command, Command::NetWorth { at: Some(_), .. }));
+    let cli = Cli::try_parse_from(["asset-manager", "-u", "alice", "export", "state.json"]).unwrap();
+    assert!(matches!(cli.command, Command::Export { plaintext: false, .. }));
+    let cli = Cli::try_parse_from(["asset-manager", "-u", "alice", "export", "state.json", "--plaintext"]).unwrap();
+    assert!(matches!(cli.command, Command::Export { plaintext: true, .. }));
+
+    assert!(Cli::try_parse_from(["asset-manager", "--env", "staging", "-u", "alice", "dashboard"]).is_err());
+    assert!(Cli::try_parse_from(["asset-manager", "-u", "alice", "asset", "sell", "3", "--sold-at", "2024-05-01", "--lot", "1", "--amount", "5000"]).is_err());
+    assert!(Cli::try_parse_from(["asset-manager", "-u", "alice", "fund", "remove", "liberty"]).is_err());
+
+    let user = User::new("TESTEXPORT", "admin123", AppEnv::Dev).unwrap();
+    let mut app = App::new(user.clone());
+    app.create_fund("liberty".to_string()).unwrap();
+    let fund = app.get_fund(&"liberty".to_string()).unwrap();
+    app.create_new_asset(fund, AssetType::Bitcoin { address: Some("hash123".to_string()), sats: 100_000_000 });
+
+    for file_path in [format!("{}/TESTEXPORT.json", user.get_data_dir()), format!("{}/backup/TESTEXPORT.json", user.get_data_dir())] {
+        assert!(matches!(app.export(&file_path).unwrap_err(), AppErrors::InvalidOperation(_)));
+        assert!(!Path::new(&file_path).exists());
+    }
+    let file_path = temp_file_path("TESTEXPORT.json");
+    app.export(&file_path).unwrap();
+    assert!(fs::read_to_string(&file_path).unwrap().contains("hash123"));
+
+    fs::remove_file(&file_path).unwrap();
+}
+
+fn write_import_file(file_path: &str, asset_type: &str, currency: &str) {
+    let content = json::object! {
+        "funds": [{ "name": "liberty" }],
+        "assets": [{
+            "fund": { "name": "liberty" },
+            "asset_type": { "type": asset_type, "data": { "sats": "100000000" } },
+            "buy": {
+                "settled_at": "2022-01-01",
+                "transaction": { "fiat_cash": { "amount": "500000", "currency": currency } }
+            }
+        }]
+    };
+    fs::write(file_path, content.dump()).unwrap();
+}
+
+#[test]
+fn readable_errors_instead_of_panics() {
+    let user = User::new("TESTERRORS", "admin123", AppEnv::Dev).unwrap();
+
+    let mut app = App::new(user.clone());
+    app.create_fund("liberty".to_string()).unwrap();
+    assert_eq!(
+        app.create_fund("liberty".to_string()).unwrap_err(),
+        AppErrors::DuplicateFund("liberty".to_string())
+    );
+    assert_eq!(
+        app.get_fund(&"savings".to_string()).unwrap_err(),
+        AppErrors::UnknownFund("savings".to_string())
+    );
+    assert_eq!(app.get_asset(7).unwrap_err(), AppErrors::AssetNotFound(7));
+
+    let file_path = &temp_file_path("test_import_errors.json");
+    write_import_file(file_path, "bitcoinn", "MXN");
+    assert_eq!(
+        App::new(user.clone()).import_user_file(file_path).unwrap_err(),
+        AppErrors::UnknownAssetType("bitcoinn".to_string())
+    );
+    write_import_file(file_path, "bitcoin", "MXM");
+    assert_eq!(
+        App::new(user.clone()).import_user_file(file_path).unwrap_err(),
+        AppErrors::UnknownCurrency("MXM".to_string())
+    );
+    fs::remove_file(file_path).unwrap();
+
+    assert_eq!(
+        AssetType::new("bitcoin".to_string(), json::object! { "sats": "1.5" }).unwrap_err(),
+        AppErrors::MalformedAmount("sats = 1.5".to_string())
+    );
+    assert_eq!(
+        Now::new_from_datetime_str("2022-13-01", "%Y-%m-%d").unwrap_err(),
+        AppErrors::MalformedDate("2022-13-01".to_string())
+    );
+    assert_eq!(
+        Now::new_from_datetime_str("2999-01-01", "%Y-%m-%d").unwrap_err(),
+        AppErrors::MalformedDate("2999-01-01".to_string())
+    );
+    let last = Now::new_from_datetime_str("2554-01-01", "%Y-%m-%d").unwrap();
+    assert!(last.increment_days(30).is_some());
+    assert!(last.increment_days(365).is_none());
+    assert!(matches!(
+        PriceSheet::from_file("./files/dev/missing_prices.json").unwrap_err(),
+        AppErrors::Io(_)
+    ));
+
+    let fund = app.get_fund(&"liberty".to_string()).unwrap();
+    let asset_id = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 100_000_000 });
+    let asset = app.get_asset_mut(asset_id).unwrap();
+    assert!(matches!(
+        asset.evaluate(&PriceSheet::default(), &FxRates::default()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+    asset.purchase(0, 100, FiatCurrency::from_str("usd").unwrap()).unwrap();
+    assert_eq!(
+        asset.evaluate(&PriceSheet::default(), &FxRates::default()).unwrap_err(),
+        AppErrors::MissingPrice("USD/MXN at 0".to_string())
+    );
+}
+
+#[test]
+fn sell_and_realized_gain() {
+    let (mut app, fund) = new_app_with_fund("TESTSELL");
+    let asset_id = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 50_000_000 });
+
+    let bought_at = Now::new_from_datetime_str("2021-01-10", "%Y-%m-%d").unwrap().to_epoch_millis();
+    let sold_at = Now::new_from_datetime_str("2021-04-20", "%Y-%m-%d").unwrap().to_epoch_millis();
+
+    let asset = app.get_asset_mut(asset_id).unwrap();
+    assert!(matches!(
+        asset.sell(sold_at, "fiat_cash".to_string(), 150_000, FiatCurrency::MXN, 0).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+    asset.purchase(bought_at, 100_000, FiatCurrency::MXN).unwrap();
+    assert!(matches!(
+        asset.sell(bought_at - 1, "fiat_cash".to_string(), 150_000, FiatCurrency::MXN, 0).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+    asset.sell(sold_at, "fiat_cash".to_string(), 150_000, FiatCurrency::MXN, 1_500).unwrap();
+    assert!(asset.is_sold());
+
+    // Sold assets never look at the price sheet.
+    let evaluation = asset.evaluate(&PriceSheet::default(), &FxRates::default()).unwrap();
+    assert_eq!(evaluation.get_now_amount(), 0);
+    assert_eq!(evaluation.get_exit_amount(), Some(148_500));
+    assert_eq!(evaluation.get_realized_gain(), Some(48_500));
+    assert_eq!(evaluation.get_holding_millis(), 100 * 24 * 60 * 60 * 1000);
+
+    let asset = app.get_asset_mut(asset_id).unwrap();
+    assert!(matches!(
+        asset.sell(sold_at, "fiat_cash".to_string(), 1, FiatCurrency::MXN, 0).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+}
+
+#[test]
+fn partial_sells_and_cost_basis_methods() {
+    let (mut app, fund) = new_app_with_fund("TESTLOTS");
+    let btc = 100_000_000;
+    let asset_id = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 3 * btc });
+
+    let asset = app.get_asset_mut(asset_id).unwrap();
+    for (settled_at, amount) in [("2020-01-01", 100_000), ("2021-01-01", 300_000), ("2022-01-01", 500_000)] {
+        asset.purchase_lot(Buy::new(date(settled_at), btc, "fiat_cash".to_string(), amount, FiatCurrency::MXN).unwrap()).unwrap();
+    }
+    assert!(matches!(
+        asset.purchase_lot(Buy::new(date("2022-02-01"), 1, "fiat_cash".to_string(), 1, FiatCurrency::MXN).unwrap()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+
+    // Only one lot was held in mid 2020.
+    let early_sell = Sell::new(date("2020-06-01"), 2 * btc, "fiat_cash".to_string(), 1, FiatCurrency::MXN, 0).unwrap();
+    assert!(matches!(asset.sell_lot(early_sell).unwrap_err(), AppErrors::InvalidOperation(_)));
+    assert!(asset.get_sells().is_empty());
+
+    let sell = Sell::new(date("2022-06-01"), 3 * btc / 2, "fiat_cash".to_string(), 600_000, FiatCurrency::MXN, 0).unwrap();
+    asset.sell_lot(sell.with_lot(Some(1))).unwrap();
+    assert!(!asset.is_sold());
+    assert_eq!(asset.get_held_units().unwrap(), 3 * btc / 2);
+
+    let price_sheet = PriceSheet { btc: Some(1_000_000), ..PriceSheet::default() };
+
+    let expected = [
+        (CostBasis::Fifo, 650_000, 350_000),
+        (CostBasis::Lifo, 250_000, -50_000),
+        (CostBasis::Average, 450_000, 150_000),
+    ];
+    for (method, entrance_amount, realized_gain) in expected {
+        app.set_cost_basis(method);
+        let evaluation = app.get_asset(asset_id).unwrap().evaluate(&price_sheet, &FxRates::default()).unwrap();
+        assert_eq!(evaluation.get_held_units(), 3 * btc / 2);
+        assert_eq!(evaluation.get_entrance_amount(), entrance_amount, "{}", method);
+        assert_eq!(evaluation.get_realized_gain(), Some(realized_gain), "{}", method);
+        assert_eq!(evaluation.get_now_amount(), 1_500_000);
+    }
+
+    // Lot 1 only holds one bitcoin.
+    app.set_cost_basis(CostBasis::SpecificLot);
+    assert!(matches!(
+        app.get_asset(asset_id).unwrap().evaluate(&price_sheet, &FxRates::default()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+}
+
+#[test]
+fn historical_and_latest_fx_rates() {
+    let fx_rates = FxRates::from_file("./files/dev/fx_rates.json").unwrap();
+
+    // Last rate on or before the date, the inverse pair and the latest rate.
+    assert_eq!(fx_rates.convert(10_000, &FiatCurrency::USD, &FiatCurrency::MXN, Some(date("2022-03-15"))).unwrap(), 205_835);
+    assert_eq!(fx_rates.convert(205_835, &FiatCurrency::MXN, &FiatCurrency::USD, Some(date("2022-03-15"))).unwrap(), 10_000);
+    assert_eq!(fx_rates.convert(10_000, &FiatCurrency::USD, &FiatCurrency::MXN, None).unwrap(), 175_788);
+    assert!(matches!(
+        fx_rates.convert(10_000, &FiatCurrency::USD, &FiatCurrency::MXN, Some(date("2020-01-01"))).unwrap_err(),
+        AppErrors::MissingPrice(_)
+    ));
+    assert!(matches!(
+        fx_rates.convert(10_000, &FiatCurrency::USD, &FiatCurrency::EUR, None).unwrap_err(),
+        AppErrors::MissingPrice(_)
+    ));
+
+    let (mut app, fund) = new_app_with_fund("TESTFX");
+    let asset_id = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 100_000_000 });
+    app.get_asset_mut(asset_id).unwrap().purchase(date("2022-01-03"), 100_000, FiatCurrency::USD).unwrap();
+
+    // The market price is in MXN, the latest rate takes it to USD.
+    let price_sheet = PriceSheet { btc: Some(175_788_000), ..PriceSheet::default() };
+    let evaluation = app.get_asset(asset_id).unwrap().evaluate(&price_sheet, &fx_rates).unwrap();
+    assert_eq!(evaluation.get_entrance_amount(), 2_058_350);
+    assert_eq!(evaluation.get_entrance_amount_latest_fx(), 1_757_880);
+    assert_eq!(evaluation.get_now_amount(), 175_788_000);
+
+    app.set_fiat_currency(FiatCurrency::USD);
+    let evaluation = app.get_asset(asset_id).unwrap().evaluate(&price_sheet, &fx_rates).unwrap();
+    assert_eq!(evaluation.get_entrance_amount(), 100_000);
+    assert_eq!(evaluation.get_entrance_amount_latest_fx(), 100_000);
+    assert_eq!(evaluation.get_now_amount(), 10_000_000);
+}
+
+#[test]
+fn price_history_append_and_queries() {
+    let file_path = &temp_file_path("market_TESTHISTORY.json");
+    let _ = fs::remove_file(file_path);
+    let snapshot = |datetime: &str, market_key: &str, median: u64| MarketSnapshot::new(
+        date(datetime), market_key.to_string(), None, FiatCurrency::MXN, None, None, None, median
+    );
+
+    let mut price_history = PriceHistory::from_file(file_path).unwrap();
+    assert_eq!(price_history.latest("BTC"), None);
+    price_history.append(snapshot("2023-01-01", "BTC", 32_000_000)).unwrap();
+    price_history.append(snapshot("2023-03-01", "BTC", 45_000_000)).unwrap();
+    price_history.append(snapshot("2023-02-01", "BTC", 41_000_000)).unwrap();
+    price_history.append(snapshot("2023-02-01", "GOLD-GRAM-24K", 115_058)).unwrap();
+    price_history.append(snapshot("2023-02-01", "BTC", 41_000_000)).unwrap();
+    assert!(matches!(
+        price_history.append(snapshot("2023-02-01", "BTC", 1)).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+
+    price_history.save(file_path).unwrap();
+    let price_history = PriceHistory::from_file(file_path).unwrap();
+    fs::remove_file(file_path).unwrap();
+
+    assert_eq!(price_history.latest("BTC").unwrap().get_median(), 45_000_000);
+    assert_eq!(price_history.price_at("BTC", date("2023-02-15")).unwrap().get_median(), 41_000_000);
+    assert_eq!(price_history.price_at("BTC", date("2022-12-31")), None);
+    let medians: Vec<u64> = price_history
+        .range("BTC", date("2023-01-15"), date("2023-03-01"))
+        .iter()
+        .map(|snapshot| snapshot.get_median())
+        .collect();
+    assert_eq!(medians, vec![41_000_000, 45_000_000]);
+    assert_eq!(price_history.get_market_keys(), vec!["BTC", "GOLD-GRAM-24K"]);
+}
+
+/// Answers every request with the same status and body, `times` times.
+fn mock_http_server(times: usize, status: &'static str, body: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming().take(times) {
+            let mut stream = stream.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, body.len(), body
+            ).unwrap();
+        }
+    });
+    format!("http://{}", address)
+}
+
+#[test]
+fn price_providers_with_fallback() {
+    let csv_path = &temp_file_path("prices_TESTPROVIDERS.csv");
+    fs::write(csv_path, "date,market_key,median,currency,market\n\
+        2023-01-01,BTC,30000000,MXN,bitso\n\
+        2023-02-01,BTC,41000000,MXN,bitso\n\
+        2023-01-15,BTC,35000000,MXN,bitso\n\
+        2023-02-01,ETH,3000000,USD,\n").unwrap();
+    let csv_provider = CsvPriceProvider::from_file(csv_path).unwrap();
+    fs::remove_file(csv_path).unwrap();
+
+    let snapshot = csv_provider.get_price("BTC").unwrap();
+    assert_eq!(snapshot.get_median(), 41_000_000);
+    assert_eq!(snapshot.get_source(), Some(&format!("csv:{}", csv_path)));
+    assert_eq!(snapshot.get_market(), Some(&"bitso".to_string()));
+    assert_eq!(csv_provider.get_price("ETH").unwrap().get_currency(), &FiatCurrency::USD);
+    assert!(matches!(csv_provider.get_price("LTC").unwrap_err(), AppErrors::MissingPrice(_)));
+
+    let url = mock_http_server(1, "200 OK", r#"{ "median": 42000000, "currency": "MXN", "market": "mock", "top": 42500000 }"#);
+    let http_provider = HttpPriceProvider::new(&format!("{}/prices/{{market_key}}", url));
+    let snapshot = http_provider.get_price("BTC").unwrap();
+    assert_eq!(snapshot.get_median(), 42_000_000);
+    assert_eq!(snapshot.get_source(), Some(&format!("http:{}/prices/{{market_key}}", url)));
+    assert_eq!(snapshot.get_market(), Some(&"mock".to_string()));
+
+    // The endpoint fails, the csv dump answers BTC and the sheet answers LTC.
+    let url = mock_http_server(2, "500 Internal Server Error", "{}");
+    let mut price_providers = PriceProviders::new();
+    price_providers.push(Box::new(HttpPriceProvider::new(&format!("{}/{{market_key}}", url))));
+    price_providers.push(Box::new(csv_provider));
+    price_providers.push(Box::new(PriceSheet { ltc: Some(150_000), ..PriceSheet::default() }));
+    assert_eq!(price_providers.get_price("BTC").unwrap().get_median(), 41_000_000);
+    let snapshot = price_providers.get_price("LTC").unwrap();
+    assert_eq!(snapshot.get_source(), Some(&"price_sheet".to_string()));
+    assert_eq!(snapshot.get_median(), 150_000);
+    assert!(matches!(price_providers.get_price("DOGE").unwrap_err(), AppErrors::MissingPrice(_)));
+}
+
+#[test]
+fn latest_prices_by_market_with_staleness() {
+    let file_path = &temp_file_path("market_TESTLATEST.json");
+    let _ = fs::remove_file(file_path);
+    let (mut app, fund) = new_app_with_fund("TESTLATEST");
+    for sats in [10_000_000, 20_000_000, 30_000_000] {
+        let asset_id = app.create_new_asset(fund.clone(), AssetType::Bitcoin { address: None, sats });
+        app.get_asset_mut(asset_id).unwrap().purchase(0, 1_000, FiatCurrency::MXN).unwrap();
+    }
+    let gold = AssetType::Gold { presentation: "bar".to_string(), weight: Some("10".to_string()), weight_unit: WeightUnit::Gram, purity: Some(9999), note: None };
+    let asset_id = app.create_new_asset(fund, gold);
+    app.get_asset_mut(asset_id).unwrap().purchase(0, 1_000, FiatCurrency::MXN).unwrap();
+
+    let day = 24 * 60 * 60 * 1000;
+    assert_eq!(app.get_stale_market_keys(day).unwrap(), vec!["BTC", "GOLD-GRAM-24K"]);
+
+    // Three bitcoin holdings share a single price.
+    let price_sheet = PriceSheet { btc: Some(1_000_000), gold_gram_24k: Some(1_000), ..PriceSheet::default() };
+    assert_eq!(app.update_market(&price_sheet, file_path, day).unwrap(), vec!["BTC", "GOLD-GRAM-24K"]);
+    assert_eq!(app.get_latest_price("BTC").unwrap().get_median(), 1_000_000);
+    assert!(app.get_stale_market_keys(day).unwrap().is_empty());
+    assert!(app.update_market(&price_sheet, file_path, day).unwrap().is_empty());
+    assert_eq!(PriceHistory::from_file(file_path).unwrap().range("BTC", 0, EpochMillis::MAX).len(), 1);
+    fs::remove_file(file_path).unwrap();
+
+    let cached_prices = app.get_cached_prices(day);
+    assert_eq!(cached_prices.get_price("GOLD-GRAM-24K").unwrap().get_source(), Some(&"price_sheet".to_string()));
+
+    // Ten days later every price is stale.
+    let old_sheet = PriceSheet { created_at: Now::new().to_epoch_millis() - 10 * day, ..price_sheet };
+    let (mut app, fund) = new_app_with_fund("TESTLATEST");
+    let asset_id = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 10_000_000 });
+    app.get_asset_mut(asset_id).unwrap().purchase(0, 1_000, FiatCurrency::MXN).unwrap();
+    app.update_market(&old_sheet, file_path, day).unwrap();
+    fs::remove_file(file_path).unwrap();
+    assert_eq!(app.get_stale_market_keys(day).unwrap(), vec!["BTC"]);
+    assert!(matches!(app.get_cached_prices(day).get_price("BTC").unwrap_err(), AppErrors::MissingPrice(_)));
+    assert!(app.get_cached_prices(20 * day).get_price("BTC").is_ok());
+}
+
+#[test]
+fn dashboard_subtotals_per_fund_and_asset_type() {
+    assert_eq!(format_amount(-1_234_567, 2), "-12,345.67");
+    assert_eq!(format_amount(5, 2), "0.05");
+    assert_eq!(format_amount(1_000, 0), "1,000");
+
+    let (mut app, liberty) = new_app_with_fund("TESTDASHBOARD");
+    app.create_fund("retirement".to_string()).unwrap();
+    let retirement = app.get_fund(&"retirement".to_string()).unwrap();
+
+    let btc = app.create_new_asset(liberty.clone(), AssetType::Bitcoin { address: None, sats: 100_000_000 });
+    app.get_asset_mut(btc).unwrap().purchase(date("2021-01-01"), 500_000, FiatCurrency::MXN).unwrap();
+    let gold = AssetType::Gold { presentation: "bar".to_string(), weight: Some("10".to_string()), weight_unit: WeightUnit::Gram, purity: Some(9999), note: None };
+    let gold = app.create_new_asset(liberty, gold);
+    app.get_asset_mut(gold).unwrap().purchase(date("2021-01-01"), 10_000, FiatCurrency::MXN).unwrap();
+    let sold = app.create_new_asset(retirement, AssetType::Bitcoin { address: None, sats: 50_000_000 });
+    let asset = app.get_asset_mut(sold).unwrap();
+    asset.purchase(date("2021-01-01"), 100_000, FiatCurrency::MXN).unwrap();
+    asset.sell(date("2021-01-11"), "fiat_cash".to_string(), 80_000, FiatCurrency::MXN, 0).unwrap();
+
+    let price_sheet = PriceSheet { btc: Some(1_000_000), gold_gram_24k: Some(1_500), ..PriceSheet::default() };
+    let dashboard = app.get_dashboard(&price_sheet, &PriceHistory::default()).unwrap();
+
+    assert_eq!(dashboard.rows.len(), 3);
+    assert_eq!(dashboard.rows[2].holding_days, 10);
+    assert!(dashboard.rows[2].sold);
+    let labels: Vec<&str> = dashboard.by_fund.iter().map(|total| total.label.as_str()).collect();
+    assert_eq!(labels, vec!["liberty", "retirement"]);
+    assert_eq!(dashboard.by_fund[0].now_amount, 1_015_000);
+    assert_eq!(dashboard.by_fund[0].get_gain(), 505_000);
+    assert_eq!(dashboard.by_fund[1].get_gain(), -20_000);
+    assert_eq!(dashboard.by_fund[1].get_gain_bps(), Some(-2_000));
+    let labels: Vec<&str> = dashboard.by_asset_type.iter().map(|total| total.label.as_str()).collect();
+    assert_eq!(labels, vec!["BTC", "GOLD"]);
+    assert_eq!(dashboard.by_asset_type[0].realized_gain, -20_000);
+    assert_eq!(dashboard.total.entrance_amount, 510_000);
+    assert_eq!(dashboard.total.get_gain(), 485_000);
+    assert_eq!(dashboard.total.get_gain_bps(), Some(7_950));
+
+    let rendered = dashboard.to_string();
+    assert!(rendered.contains("BTC SOLD"));
+    assert!(rendered.contains("10,150.00"));
+    assert!(rendered.contains("79.50%"));
+}
+
+#[test]
+fn fund_targets_drift_and_rebalance() {
+    let (mut app, fund) = new_app_with_fund("TESTREBALANCE");
+    assert!(matches!(
+        app.get_drift_report(&"liberty".to_string(), &PriceSheet::default()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+
+    let targets = |weights: &[(&str, u32)]| weights
+        .iter()
+        .map(|(asset_type_str, weight)| (asset_type_str.to_string(), *weight))
+        .collect::<std::collections::BTreeMap<String, u32>>();
+    assert!(matches!(
+        app.set_fund_targets(&"liberty".to_string(), targets(&[("GOLD", 4_000), ("BTC", 4_000)])).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+    assert_eq!(
+        app.set_fund_targets(&"missing".to_string(), targets(&[])).unwrap_err(),
+        AppErrors::UnknownFund("missing".to_string())
+    );
+    assert!(matches!(
+        app.set_fund_targets(&"liberty".to_string(), targets(&[("GOLD", u32::MAX), ("gold", 10_000)])).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+    let file_path = &temp_file_path("funds_TESTREBALANCE.json");
+    fs::write(file_path, json::object! { "funds": [{ "name": "savings", "targets": { "GOLD": "50000000" } }] }.dump()).unwrap();
+    assert_eq!(
+        app.import_user_file(file_path).unwrap_err(),
+        AppErrors::MalformedAmount("50000000".to_string())
+    );
+    fs::remove_file(file_path).unwrap();
+    app.set_fund_targets(&"liberty".to_string(), targets(&[("gold", 4_000), ("BTC", 4_000), ("REAL_STATE", 2_000)])).unwrap();
+
+    let btc = app.create_new_asset(fund.clone(), AssetType::Bitcoin { address: None, sats: 100_000_000 });
+    app.get_asset_mut(btc).unwrap().purchase(0, 1, FiatCurrency::MXN).unwrap();
+    let gold = AssetType::Gold { presentation: "bar".to_string(), weight: Some("100".to_string()), weight_unit: WeightUnit::Gram, purity: Some(9999), note: None };
+    let gold = app.create_new_asset(fund, gold);
+    app.get_asset_mut(gold).unwrap().purchase(0, 1, FiatCurrency::MXN).unwrap();
 
-    let fund = app.get_fund(&fund_name);
+    let price_sheet = PriceSheet { btc: Some(750_000), gold_gram_24k: Some(2_500), ..PriceSheet::default() };
+    let report = app.get_drift_report(&"liberty".to_string(), &price_sheet).unwrap();
+    assert_eq!(report.now_amount, 1_000_000);
+    let lines: Vec<(&str, u32, i64, i128)> = report.lines
+        .iter()
+        .map(|line| (line.asset_type_str.as_str(), line.weight_bps, line.drift_bps, line.suggestion))
+        .collect();
+    assert_eq!(lines, vec![
+        ("BTC", 7_500, 3_500, -350_000),
+        ("GOLD", 2_500, -1_500, 150_000),
+        ("REAL_STATE", 0, -2_000, 200_000),
+    ]);
+    assert!(report.to_string().contains("SELL 3,500.00"));
+}
 
-    let asset_type = AssetType::Bitcoin { utxo_hash: "hash123".to_string(), sats: 100_000_000 };
-    app.create_new_asset(fund.clone(), asset_type);
+#[test]
+fn other_asset_with_decimal_quantity_and_manual_price() {
+    assert_eq!(
+        AssetType::new("other".to_string(), json::object!{ quantity: "2" }).unwrap_err(),
+        AppErrors::MalformedFile("other asset without symbol".to_string())
+    );
+    let painting = AssetType::new("other".to_string(), json::object!{
+        symbol: "painting", quantity: "1", decimals: 0, unit_price: "85000.50"
+    }).unwrap();
+    assert_eq!(painting.get_market_key().unwrap(), "PAINTING");
+    assert_eq!(painting.get_manual_price(), Some((8_500_050, FiatCurrency::MXN)));
+    let shares = AssetType::new("other".to_string(), json::object!{
+        symbol: "voo", quantity: "2.5", decimals: 4, currency: "USD"
+    }).unwrap();
+    assert_eq!(shares.get_units(), 25_000);
+    assert_eq!(shares.get_manual_price(), None);
+    assert_eq!(
+        AssetType::new("other".to_string(), json::object!{ symbol: "painting", quantity: "1", unit_price: "184467440737095516.16" }).unwrap_err(),
+        AppErrors::MalformedAmount("184467440737095516.16".to_string())
+    );
+    // 10^39 does not fit the amounts.
+    assert!(matches!(
+        AssetType::new("other".to_string(), json::object!{ symbol: "dust", quantity: "1", decimals: 39 }).unwrap_err(),
+        AppErrors::MalformedFile(_)
+    ));
+
+    let (mut app, fund) = new_app_with_fund("TESTOTHER");
+    let painting = app.create_new_asset(fund.clone(), painting);
+    app.get_asset_mut(painting).unwrap().purchase(0, 5_000_000, FiatCurrency::MXN).unwrap();
+    let shares = app.create_new_asset(fund, shares);
+    app.get_asset_mut(shares).unwrap().purchase(0, 400_000, FiatCurrency::MXN).unwrap();
+    app.get_asset_mut(shares).unwrap()
+        .sell_lot(Sell::new(Now::new_from_datetime_str("2021-01-01", "%Y-%m-%d").unwrap().to_epoch_millis(), 5_000, "fiat_cash".to_string(), 90_000, FiatCurrency::MXN, 0).unwrap())
+        .unwrap();
+
+    // The painting is priced by hand, the shares by the providers.
+    assert_eq!(app.get_held_market_keys().unwrap(), vec!["VOO"]);
+    let csv_path = &temp_file_path("prices_TESTOTHER.csv");
+    fs::write(csv_path, "date,market_key,median\n2023-01-01,VOO,800000\n").unwrap();
+    let csv_provider = CsvPriceProvider::from_file(csv_path).unwrap();
+    fs::remove_file(csv_path).unwrap();
+
+    let evaluations = app.get_asset_evaluations(&csv_provider).unwrap();
+    assert_eq!(evaluations[0].get_now_amount(), 8_500_050);
+    assert_eq!(evaluations[1].get_held_units(), 20_000);
+    assert_eq!(evaluations[1].get_now_amount(), 1_600_000);
+
+    app.get_asset_mut(painting).unwrap().set_manual_price(None).unwrap();
+    assert!(matches!(app.get_asset_evaluations(&csv_provider).unwrap_err(), AppErrors::MissingPrice(_)));
+    let fund = app.get_fund(&"liberty".to_string()).unwrap();
+    let btc = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 1 });
+    assert!(matches!(
+        app.get_asset_mut(btc).unwrap().set_manual_price(Some((1, FiatCurrency::MXN))).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+}
+
+#[test]
+fn stocks_etfs_and_dividends() {
+    assert_eq!(
+        AssetType::new("etf".to_string(), json::object!{ shares: "1" }).unwrap_err(),
+        AppErrors::MalformedFile("etf without ticker".to_string())
+    );
+    let stock = AssetType::new("stock".to_string(), json::object!{
+        ticker: "walmex", exchange: "bmv", shares: "12.5"
+    }).unwrap();
+    assert_eq!(stock.get_market_key().unwrap(), "BMV:WALMEX");
+    assert_eq!(stock.get_units(), 12_500_000);
+    let etf = AssetType::new("etf".to_string(), json::object!{ ticker: "voo", shares: "2" }).unwrap();
+    assert_eq!(etf.get_market_key().unwrap(), "VOO");
+
+    let (mut app, fund) = new_app_with_fund("TESTSTOCKS");
+    let walmex = app.create_new_asset(fund.clone(), stock);
+    app.get_asset_mut(walmex).unwrap().purchase(1_000, 80_000, FiatCurrency::MXN).unwrap();
+    let voo = app.create_new_asset(fund.clone(), etf);
+    app.get_asset_mut(voo).unwrap().purchase(1_000, 1_500_000, FiatCurrency::MXN).unwrap();
+
+    let dividend = |settled_at: EpochMillis, amount: u128| {
+        Income::new(IncomeKind::Dividend, settled_at, "fiat_cash".to_string(), amount, FiatCurrency::MXN).unwrap()
+    };
+    app.get_asset_mut(voo).unwrap().add_income(dividend(2_000, 12_000)).unwrap();
+    app.get_asset_mut(voo).unwrap().add_income(dividend(3_000, 13_000)).unwrap();
+    assert!(matches!(
+        app.get_asset_mut(walmex).unwrap().add_income(dividend(500, 1_000)).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+    let btc = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 1 });
+    app.get_asset_mut(btc).unwrap().purchase(1_000, 100, FiatCurrency::MXN).unwrap();
+    assert!(matches!(
+        app.get_asset_mut(btc).unwrap().add_income(dividend(2_000, 1_000)).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+
+    // Tickers are quoted by the sheet symbols.
+    let sheet = PriceSheet {
+        btc: Some(1_000),
+        symbols: [("BMV:WALMEX".to_string(), 7_000), ("VOO".to_string(), 800_000)].into(),
+        ..PriceSheet::default()
+    };
+    let evaluations = app.get_asset_evaluations(&sheet).unwrap();
+    assert_eq!(evaluations[0].get_now_amount(), 87_500);
+    assert_eq!(evaluations[1].get_now_amount(), 1_600_000);
+    assert_eq!(evaluations[1].get_income_amount(), 25_000);
+
+    let dashboard = app.get_dashboard(&sheet, &PriceHistory::default()).unwrap();
+    assert_eq!(dashboard.rows[1].amounts.get_gain(), 125_000);
+    assert_eq!(dashboard.total.income, 25_000);
+}
+
+#[test]
+fn fixed_income_accrual_and_payment_schedule() {
+    assert_eq!(
+        AssetType::new("cetes".to_string(), json::object!{ name: "bi", rate: "10", issued_at: "2024-01-04" }).unwrap_err(),
+        AppErrors::MalformedFile("CETES without maturity_at".to_string())
+    );
+    // Out of range, not truncated into another instrument.
+    for (face_value, rate) in [("200000000000000000", "10"), ("100", "50000000")] {
+        assert!(matches!(
+            FixedIncomeTerms::from_json(FixedIncomeKind::Bond, &json::object!{
+                face_value: face_value, rate: rate, issued_at: "2024-01-04", maturity_at: "2025-01-02"
+            }).unwrap_err(),
+            AppErrors::MalformedAmount(_)
+        ));
+    }
+
+    // Two coupons of 182 days, actual days over 360.
+    let bond = FixedIncomeTerms::from_json(FixedIncomeKind::Bond, &json::object!{
+        face_value: "100", rate: "10", issued_at: "2024-01-04", maturity_at: "2025-01-02", coupon_days: 182
+    }).unwrap();
+    assert_eq!(bond.get_coupon_dates(), vec![date("2024-07-04"), date("2025-01-02")]);
+    assert_eq!(bond.get_value_at(date("2024-04-04"), 10), 102_527);
+    assert_eq!(bond.get_value_at(date("2024-07-04"), 10), 100_000);
+    assert_eq!(bond.get_value_at(date("2030-01-01"), 10), 100_000);
+    let upcoming = bond.get_upcoming_payments(date("2024-05-01"), 10);
+    assert_eq!(upcoming.len(), 2);
+    assert_eq!((upcoming[0].kind, upcoming[0].date, upcoming[0].amount), (PaymentKind::Coupon, date("2024-07-04"), 5_055));
+    assert_eq!((upcoming[1].kind, upcoming[1].amount), (PaymentKind::Maturity, 100_000));
+
+    // CETES are valued by the discount yield of the days to maturity.
+    let cetes = FixedIncomeTerms::from_json(FixedIncomeKind::Cetes, &json::object!{
+        rate: "10", issued_at: "2024-01-04", maturity_at: "2024-04-04"
+    }).unwrap();
+    assert_eq!(cetes.face_value, 1_000);
+    assert_eq!(cetes.get_value_at(date("2024-01-04"), 1_000), 974_723);
+    assert_eq!(cetes.get_value_at(date("2024-04-04"), 1_000), 1_000_000);
+    assert!(cetes.get_coupon_dates().is_empty());
+
+    // Term deposits pay the interest with the principal.
+    let deposit = FixedIncomeTerms::from_json(FixedIncomeKind::TermDeposit, &json::object!{
+        face_value: "50000", rate: "10.5", issued_at: "2024-01-04", maturity_at: "2024-02-01"
+    }).unwrap();
+    let payments = deposit.get_payments(1);
+    assert_eq!((payments[0].kind, payments[0].date, payments[0].amount), (PaymentKind::Coupon, date("2024-02-01"), 40_833));
+    assert_eq!((payments[1].kind, payments[1].amount), (PaymentKind::Maturity, 5_000_000));
+
+    let (mut app, fund) = new_app_with_fund("TESTFIXED");
+    let cetes = AssetType::new("cetes".to_string(), json::object!{
+        name: "bi cetes 990101", titles: "100", rate: "0.5", issued_at: "2024-01-04", maturity_at: "2099-01-01"
+    }).unwrap();
+    assert_eq!(format!("{}", cetes), "CETES");
+    let cetes = app.create_new_asset(fund, cetes);
+    app.get_asset_mut(cetes).unwrap().purchase(date("2024-01-04"), 50_000, FiatCurrency::MXN).unwrap();
+    let coupon = Income::new(IncomeKind::Coupon, date("2024-06-01"), "fiat_cash".to_string(), 100, FiatCurrency::MXN).unwrap();
+    app.get_asset_mut(cetes).unwrap().add_income(coupon).unwrap();
+
+    // Valued without a price provider.
+    assert!(app.get_held_market_keys().unwrap().is_empty());
+    let dashboard = app.get_dashboard(&PriceSheet::default(), &PriceHistory::default()).unwrap();
+    let now_amount = dashboard.rows[0].amounts.now_amount;
+    assert!(now_amount > 50_000 && now_amount < 100_000);
+    assert_eq!(dashboard.upcoming.len(), 1);
+    assert_eq!(dashboard.upcoming[0].name, "BI CETES 990101");
+    assert_eq!((dashboard.upcoming[0].payment.date, dashboard.upcoming[0].payment.amount), (date("2099-01-01"), 100_000));
+
+    // A title worth more than u64::MAX is an error, not a truncated price.
+    let terms = FixedIncomeTerms {
+        kind: FixedIncomeKind::Bond,
+        face_value: u64::MAX,
+        currency: FiatCurrency::MXN,
+        rate_bps: 1_000,
+        issued_at: date("2020-01-01"),
+        maturity_at: date("2099-01-01"),
+        coupon_days: None
+    };
+    let fund = app.get_fund(&"liberty".to_string()).unwrap();
+    let bond = app.create_new_asset(fund, AssetType::FixedIncome { name: "bond".to_string(), titles: 1, terms });
+    assert!(matches!(
+        app.get_asset(bond).unwrap().get_market_price(&PriceSheet::default()).unwrap_err(),
+        AppErrors::MalformedAmount(_)
+    ));
+}
+
+#[test]
+fn real_estate_appraisals_rent_and_expenses() {
+    let mut property = Property::from_json(&json::object!{
+        area: "84.5",
+        location: "Roma Norte, CDMX",
+        appreciation: "10",
+        appraisals: [
+            { appraised_at: "2023-01-01", amount: "2000000", currency: "MXN" },
+            { appraised_at: "2020-01-01", amount: "1000000" }
+        ]
+    }).unwrap();
+    assert_eq!(property.area, Some(8_450));
+    assert_eq!(property.appraisals[0].appraised_at, date("2020-01-01"));
+    assert!(matches!(
+        property.add_appraisal(Appraisal { appraised_at: date("2020-01-01"), amount: 1, currency: FiatCurrency::MXN, appraiser: None }),
+        Err(AppErrors::InvalidOperation(_))
+    ));
+    assert!(matches!(property.get_value_at(date("2019-12-31")), Err(AppErrors::MissingPrice(_))));
+    assert_eq!(property.get_value_at(date("2020-01-01")).unwrap(), (1_000_000, FiatCurrency::MXN));
+    // One year of 365 days compounded and 73 days pro rata, 2020 has 366.
+    assert_eq!(property.get_value_at(date("2021-03-14")).unwrap().0, 1_122_000);
+    assert_eq!(property.get_value_at(date("2023-01-01")).unwrap().0, 2_000_000);
+    property.appreciation_bps = None;
+    assert_eq!(property.get_value_at(date("2022-12-31")).unwrap().0, 1_000_000);
+    assert_eq!(
+        Property::from_json(&json::object!{ appreciation: "50000000" }).unwrap_err(),
+        AppErrors::MalformedAmount("50000000".to_string())
+    );
+
+    let (mut app, fund) = new_app_with_fund("TESTREALSTATE");
+    let house = AssetType::new("real_state".to_string(), json::object!{
+        name: "casa", appraisals: [{ appraised_at: "2020-01-01", amount: "300000000" }]
+    }).unwrap();
+    let house = app.create_new_asset(fund.clone(), house);
+    let asset = app.get_asset_mut(house).unwrap();
+    asset.purchase(date("2020-01-01"), 250_000_000, FiatCurrency::MXN).unwrap();
+    asset.add_appraisal(Appraisal { appraised_at: date("2022-06-01"), amount: 320_000_000, currency: FiatCurrency::MXN, appraiser: None }).unwrap();
+    let rent = |settled_at: &str| Income::new(IncomeKind::Rent, date(settled_at), "fiat_cash".to_string(), 1_500_000, FiatCurrency::MXN).unwrap();
+    asset.add_income(rent("2020-02-01")).unwrap();
+    asset.add_income(rent("2020-03-01")).unwrap();
+    asset.add_expense(Expense::new("predial".to_string(), date("2020-01-31"), "fiat_cash".to_string(), 400_000, FiatCurrency::MXN).unwrap()).unwrap();
+    assert!(matches!(
+        asset.add_expense(Expense::new("notary".to_string(), date("2019-12-01"), "fiat_cash".to_string(), 1, FiatCurrency::MXN).unwrap()),
+        Err(AppErrors::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        asset.add_income(Income::new(IncomeKind::Dividend, date("2020-02-01"), "fiat_cash".to_string(), 1, FiatCurrency::MXN).unwrap()),
+        Err(AppErrors::InvalidOperation(_))
+    ));
+    let btc = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 1 });
+    assert!(matches!(
+        app.get_asset_mut(btc).unwrap().add_appraisal(Appraisal { appraised_at: 0, amount: 1, currency: FiatCurrency::MXN, appraiser: None }),
+        Err(AppErrors::InvalidOperation(_))
+    ));
+    app.get_asset_mut(btc).unwrap().purchase(0, 100, FiatCurrency::MXN).unwrap();
+
+    // The sheet prices the bitcoin only, the house has no market.
+    assert_eq!(app.get_held_market_keys().unwrap(), vec!["BTC"]);
+    assert!(matches!(PriceSheet::default().get_price("REAL_STATE"), Err(AppErrors::MissingPrice(_))));
+    let sheet = PriceSheet { btc: Some(100), ..PriceSheet::default() };
+    let dashboard = app.get_dashboard(&sheet, &PriceHistory::default()).unwrap();
+    let amounts = &dashboard.rows[0].amounts;
+    assert_eq!((amounts.now_amount, amounts.income, amounts.expenses), (320_000_000, 3_000_000, 400_000));
+    assert_eq!(amounts.get_gain(), 72_600_000);
 
-    let asset = app.get_asset(0);
+    // An appraised value over u64::MAX is an error, not a truncated price.
+    let fund = app.get_fund(&"liberty".to_string()).unwrap();
+    let tower = AssetType::new("real_state".to_string(), json::object!{
+        name: "torre", appraisals: [{ appraised_at: "2020-01-01", amount: "20000000000000000000" }]
+    }).unwrap();
+    let tower = app.create_new_asset(fund, tower);
+    assert!(matches!(
+        app.get_asset(tower).unwrap().get_market_price(&sheet).unwrap_err(),
+        AppErrors::MalformedAmount(_)
+    ));
+}
 
-    println!("NICE: {:?}", asset);
+#[test]
+fn gold_purity_weight_units_and_coins() {
+    assert_eq!(WeightUnit::from_str("oz").unwrap().to_micrograms("1.5").unwrap(), 46_655_215);
+    assert_eq!(WeightUnit::from_str("kg").unwrap().to_micrograms("0.25").unwrap(), 250_000_000);
+    assert_eq!(WeightUnit::Gram.to_micrograms("2.345").unwrap(), 2_345_000);
+    assert!(WeightUnit::from_str("lb").is_err());
+
+    assert_eq!(Metal::Gold.get_coin_content("Centenario"), Some(37_500_000));
+    assert_eq!(Metal::Gold.get_coin_content("Libertad 1/2 oz"), Some(15_551_738));
+    assert_eq!(Metal::Gold.get_coin_content("Libertad onza"), Some(31_103_477));
+    assert_eq!(Metal::Gold.get_coin_content("American Eagle 1/10oz"), Some(3_110_347));
+    assert_eq!(Metal::Gold.get_coin_content("Libertad plata"), None);
+
+    // 21k is 875 parts per thousand, priced from the 24k gram.
+    let gold_21k = AssetType::new("gold".to_string(), json::object!{ presentation: "ring", weight: "10", purity: 8750 }).unwrap();
+    assert_eq!(gold_21k.get_market_key().unwrap(), "GOLD-GRAM-24K");
+    assert_eq!(gold_21k.get_fine_weight().unwrap(), 8_750_875);
+    assert_eq!(gold_21k.get_value(1_000).unwrap(), 8_750);
+    let libertad = AssetType::new("gold".to_string(), json::object!{ presentation: "Libertad 1oz" }).unwrap();
+    assert_eq!(libertad.get_value(1_000).unwrap(), 31_103);
+    let bar = AssetType::new("gold".to_string(), json::object!{
+        presentation: "Libertad 1oz", weight: "1", weight_unit: "kg", purity: 9999
+    }).unwrap();
+    assert_eq!(bar.get_value(1_000).unwrap(), 1_000_000);
+
+    let unknown = AssetType::new("gold".to_string(), json::object!{ presentation: "nugget" }).unwrap();
+    assert!(matches!(unknown.get_value(1_000), Err(AppErrors::MalformedAmount(_))));
+    let impure = AssetType::new("gold".to_string(), json::object!{ presentation: "bar", weight: "1", purity: 10_001 }).unwrap();
+    assert!(matches!(impure.get_value(1_000), Err(AppErrors::MalformedAmount(_))));
+}
+
+#[test]
+fn silver_platinum_and_palladium() {
+    assert_eq!(Metal::Silver.get_coin_content("Libertad onza"), Some(31_103_477));
+    assert_eq!(Metal::Platinum.get_coin_content("American Eagle 1/4 oz"), Some(7_775_869));
+    assert_eq!(Metal::Palladium.get_coin_content("Libertad 1oz"), None);
+    assert_eq!(Metal::Silver.get_coin_content("Centenario"), None);
 
-    user.new_app_state(app.clone(), "admin123");
+    let libertad = AssetType::new("silver".to_string(), json::object!{ presentation: "Libertad 1/2oz" }).unwrap();
+    assert_eq!(format!("{}", libertad), "SILVER");
+    assert_eq!(libertad.get_market_key().unwrap(), "SILVER-GRAM-999");
+    assert_eq!(libertad.get_value(1_000).unwrap(), 15_551);
+    // Sterling silver, 925 parts per thousand.
+    let sterling = AssetType::new("silver".to_string(), json::object!{ presentation: "tray", weight: "100", purity: 9250 }).unwrap();
+    assert_eq!(sterling.get_value(1_000).unwrap(), 92_592);
+    let bar = AssetType::new("palladium".to_string(), json::object!{
+        presentation: "bar", weight: "1", weight_unit: "kg", purity: 9995
+    }).unwrap();
+    assert_eq!(bar.get_market_key().unwrap(), "PALLADIUM-GRAM-9995");
+    assert_eq!(bar.get_value(1_000).unwrap(), 1_000_000);
+    let palladium = AssetType::new("palladium".to_string(), json::object!{ presentation: "Libertad" }).unwrap();
+    assert!(matches!(palladium.get_value(1_000), Err(AppErrors::MalformedAmount(_))));
 
-    println!("USER: {:?}", user);
+    let (mut app, fund) = new_app_with_fund("TESTMETALS");
+    for (asset_type, amount) in [(libertad, 30_000), (bar, 50_000_000)] {
+        let asset_id = app.create_new_asset(fund.clone(), asset_type);
+        app.get_asset_mut(asset_id).unwrap().purchase(0, amount, FiatCurrency::MXN).unwrap();
+    }
+    assert_eq!(app.get_held_market_keys().unwrap(), vec!["SILVER-GRAM-999", "PALLADIUM-GRAM-9995"]);
+    let sheet = PriceSheet {
+        symbols: [("SILVER-GRAM-999".to_string(), 1_420), ("PALLADIUM-GRAM-9995".to_string(), 56_280)].into(),
+        ..PriceSheet::default()
+    };
+    let evaluations = app.get_asset_evaluations(&sheet).unwrap();
+    assert_eq!(evaluations[0].get_now_amount(), 22_083);
+    assert_eq!(evaluations[1].get_now_amount(), 56_280_000);
+}
+
+#[test]
+fn cash_accounts_and_transfers() {
+    let (mut app, _) = new_app_with_fund("TESTCASH");
+    app.load_fx_rates("./files/dev/fx_rates.json").unwrap();
+    let liberty = "liberty".to_string();
+    app.create_cash_account(&liberty, "bbva".to_string(), FiatCurrency::MXN).unwrap();
+    app.create_cash_account(&liberty, "chase".to_string(), FiatCurrency::USD).unwrap();
+    assert!(matches!(
+        app.create_cash_account(&liberty, "bbva".to_string(), FiatCurrency::MXN).unwrap_err(),
+        AppErrors::DuplicateCashAccount(_)
+    ));
+    assert!(matches!(
+        app.create_cash_account(&"missing".to_string(), "hsbc".to_string(), FiatCurrency::MXN).unwrap_err(),
+        AppErrors::UnknownFund(_)
+    ));
+    assert!(matches!(
+        app.add_cash_movement("hsbc", CashMovement::new(CashMovementKind::Deposit, 0, 1)).unwrap_err(),
+        AppErrors::UnknownCashAccount(_)
+    ));
+
+    // USD 1,000 in, USD 100 to the MXN account at the rate of the date.
+    app.add_cash_movement("chase", CashMovement::new(CashMovementKind::Deposit, date("2022-01-03"), 100_000)).unwrap();
+    app.transfer_cash("chase", "bbva", date("2022-03-15"), 10_000).unwrap();
+    assert_eq!(app.get_cash_account("chase").unwrap().get_balance(), 90_000);
+    assert_eq!(app.get_cash_account("bbva").unwrap().get_balance(), 205_835);
+
+    // Neither more than the balance nor before the cash arrived.
+    for (at, amount) in [("2022-04-01", 300_000), ("2022-02-01", 1)] {
+        assert!(matches!(
+            app.add_cash_movement("bbva", CashMovement::new(CashMovementKind::Withdrawal, date(at), amount)).unwrap_err(),
+            AppErrors::InvalidOperation(_)
+        ));
+    }
+    assert_eq!(app.get_cash_account("bbva").unwrap().get_movements().len(), 1);
+}
+
+#[test]
+fn buys_and_sells_settled_in_cash_accounts() {
+    let (mut app, fund) = new_app_with_fund("TESTSETTLE");
+    app.load_fx_rates("./files/dev/fx_rates.json").unwrap();
+    app.create_cash_account(fund.get_name(), "bbva".to_string(), FiatCurrency::MXN).unwrap();
+    app.create_cash_account(fund.get_name(), "chase".to_string(), FiatCurrency::USD).unwrap();
+    app.add_cash_movement("bbva", CashMovement::new(CashMovementKind::Deposit, date("2022-03-15"), 205_835)).unwrap();
+    app.add_cash_movement("chase", CashMovement::new(CashMovementKind::Deposit, date("2022-01-03"), 90_000)).unwrap();
 
-    let asset_type = AssetType::Bitcoin { utxo_hash: "hash456".to_string(), sats: 250_000 };
-    app.create_new_asset(fund, asset_type);    
+    let asset_id = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 100_000_000 });
+    let asset = app.get_asset_mut(asset_id).unwrap();
+    asset.purchase(date("2022-04-01"), 200_000, FiatCurrency::MXN).unwrap();
+    let buy = asset.get_buys()[0].clone();
+    assert!(matches!(
+        app.pay_buy("chase", asset_id, &buy).unwrap_err(),
+        AppErrors::CurrencyMismatch { .. }
+    ));
+    app.pay_buy("bbva", asset_id, &buy).unwrap();
+    assert_eq!(app.get_cash_account("bbva").unwrap().get_balance(), 5_835);
 
-    user.save_app_state(app.clone(), "admin123");
+    let asset = app.get_asset_mut(asset_id).unwrap();
+    asset.sell(date("2022-06-01"), "fiat_cash".to_string(), 300_000, FiatCurrency::MXN, 1_000).unwrap();
+    let sell = asset.get_sells()[0].clone();
+    app.collect_sell("bbva", asset_id, &sell).unwrap();
+    let bbva = app.get_cash_account("bbva").unwrap();
+    assert_eq!(bbva.get_balance(), 304_835);
+    assert_eq!(bbva.get_balance_at(date("2022-05-01")), 5_835);
 
-    user.save();
+    // The USD balance at the latest rate, outside of the gain.
+    let dashboard = app.get_dashboard(&PriceSheet::default(), &PriceHistory::default()).unwrap();
+    assert_eq!(dashboard.cash.len(), 2);
+    assert_eq!(dashboard.total.cash, 304_835 + 1_582_092);
+    assert_eq!(dashboard.total.get_net_worth(), 304_835 + 1_582_092);
+    assert_eq!(dashboard.total.get_gain(), 99_000);
+}
+
+#[test]
+fn transaction_kinds_and_their_fees() {
+    assert!(matches!(Transaction::new("wire", 1, FiatCurrency::MXN).unwrap_err(), AppErrors::UnknownTransaction(_)));
+    assert!(matches!(Transaction::new("crypto_swap", 1, FiatCurrency::MXN).unwrap_err(), AppErrors::UnknownTransaction(_)));
+    assert!(matches!(
+        Transaction::from_json("crypto_swap", &json::object!{ amount: "100", currency: "MXN" }).unwrap_err(),
+        AppErrors::MalformedFile(_)
+    ));
+    let staking = Transaction::from_json("staking", &json::object!{ amount: "1000", currency: "MXN", fees: "10" }).unwrap();
+    assert_eq!(format!("{}", staking), "STAKING");
+    assert_eq!(staking.get_cost(), (1_010, FiatCurrency::MXN));
+    assert_eq!(staking.get_proceeds(), (990, FiatCurrency::MXN));
+    // Vaults saved before the fees still open.
+    let saved: Transaction = serde_json::from_str(r#"{"FiatCash":{"amount":5,"currency":"MXN"}}"#).unwrap();
+    assert_eq!(saved, Transaction::new("fiat_cash", 5, FiatCurrency::MXN).unwrap());
+}
+
+#[test]
+fn swaps_gifts_and_rewards() {
+    let (mut app, fund) = new_app_with_fund("TESTTRANSACTIONS");
+    let btc = app.create_new_asset(fund.clone(), AssetType::Bitcoin { address: None, sats: 100_000_000 });
+    let eth = app.create_new_asset(fund.clone(), AssetType::Ethereum { address: None, wei: 10 * 10u128.pow(18) });
+    let gift = app.create_new_asset(fund.clone(), AssetType::Bitcoin { address: None, sats: 10_000_000 });
+
+    // The fees of the bank transfer are part of the cost.
+    let transfer = Transaction::new("bank_transfer", 1_000_000, FiatCurrency::MXN).unwrap().with_fees(vec![Fee::new(FeeKind::Bank, 2_500)]);
+    app.get_asset_mut(btc).unwrap().purchase_with(date("2021-01-10"), transfer).unwrap();
+    // A gift without an appraisal has no basis and can't be sold as a gift.
+    let asset = app.get_asset_mut(gift).unwrap();
+    asset.purchase_with(date("2021-02-01"), Transaction::new("gift", 0, FiatCurrency::MXN).unwrap()).unwrap();
+    assert!(matches!(
+        asset.sell_with(date("2021-03-01"), Transaction::new("gift", 0, FiatCurrency::MXN).unwrap(), Vec::new()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+
+    // Half a bitcoin for 10 ether, the bitcoin disposal realizes its gain.
+    let swapped_at = date("2022-01-10");
+    app.swap_assets(btc, eth, swapped_at, (50_000_000, 10 * 10u128.pow(18)), (600_000, FiatCurrency::MXN), vec![Fee::new(FeeKind::Network, 1_000)]).unwrap();
+    let buy = app.get_asset(eth).unwrap().get_buys()[0].clone();
+    assert_eq!(format!("{}", buy.get_transaction()), "CRYPTO_SWAP BTC");
+    assert_eq!(buy.get_transaction_amount_currency(), (601_000, FiatCurrency::MXN));
+    assert!(matches!(app.pay_buy("bbva", eth, &buy).unwrap_err(), AppErrors::InvalidOperation(_)));
+    // Nothing is recorded when the lot doesn't fit the asset.
+    assert!(matches!(
+        app.swap_assets(btc, eth, swapped_at, (1, 1), (1, FiatCurrency::MXN), Vec::new()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+    assert_eq!(app.get_asset(btc).unwrap().get_sells().len(), 1);
+    assert!(matches!(
+        app.swap_assets(btc, btc, swapped_at, (1, 1), (1, FiatCurrency::MXN), Vec::new()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+
+    let sheet = PriceSheet { btc: Some(1_500_000), eth: Some(70_000), ..PriceSheet::default() };
+    let btc_evaluation = app.get_asset(btc).unwrap().evaluate(&sheet, &FxRates::default()).unwrap();
+    assert_eq!(btc_evaluation.get_entrance_amount(), 501_250);
+    assert_eq!(btc_evaluation.get_realized_gain(), Some(600_000 - 501_250));
+    let eth_evaluation = app.get_asset(eth).unwrap().evaluate(&sheet, &FxRates::default()).unwrap();
+    assert_eq!(eth_evaluation.get_entrance_amount(), 601_000);
+    assert_eq!(eth_evaluation.get_now_amount(), 700_000);
+    let gift_evaluation = app.get_asset(gift).unwrap().evaluate(&sheet, &FxRates::default()).unwrap();
+    assert_eq!(gift_evaluation.get_entrance_amount(), 0);
+    assert_eq!(gift_evaluation.get_now_amount(), 150_000);
+}
+
+#[test]
+fn fee_components_parsing() {
+    assert_eq!(Fee::from_str("premium:1500").unwrap(), Fee::new(FeeKind::Premium, 1_500));
+    assert_eq!(Fee::from_str("250").unwrap(), Fee::new(FeeKind::Other, 250));
+    assert!(matches!(Fee::from_str("tip:10").unwrap_err(), AppErrors::InvalidOperation(_)));
+    assert_eq!(
+        parse_fees(&json::object!{ commission: "250", notary: "80000" }).unwrap(),
+        vec![Fee::new(FeeKind::Commission, 250), Fee::new(FeeKind::Notary, 80_000)]
+    );
+    // Sells saved with a single total of fees.
+    let saved: Sell = serde_json::from_str(
+        r#"{"transaction":{"FiatCash":{"amount":190000,"currency":"MXN"}},"settled_at":0,"units":1,"lot":null,"fees":950}"#
+    ).unwrap();
+    assert_eq!(saved, Sell::new(0, 1, "fiat_cash".to_string(), 190_000, FiatCurrency::MXN, 950).unwrap());
+    assert_eq!(saved.get_fee_components(), vec![Fee::new(FeeKind::Other, 950)]);
+}
+
+#[test]
+fn fee_components_in_basis_proceeds_and_report() {
+    let (mut app, liberty) = new_app_with_fund("TESTFEES");
+    app.load_fx_rates("./files/dev/fx_rates.json").unwrap();
+    app.create_fund("retiro".to_string()).unwrap();
+    let retiro = app.get_fund(&"retiro".to_string()).unwrap();
 
-    let new_user = User::open("TESTUSER", "admin123", AppEnv::Dev);
-    let new_app = new_user.load_app_state("admin123");
+    // The dealer premium and the commission are part of the cost of the coin.
+    let coin = AssetType::new("gold".to_string(), json::object!{ presentation: "Centenario" }).unwrap();
+    let gold = app.create_new_asset(liberty, coin);
+    let asset = app.get_asset_mut(gold).unwrap();
+    let buy = Transaction::new("fiat_cash", 30_000, FiatCurrency::MXN).unwrap()
+        .with_fees(vec![Fee::new(FeeKind::Premium, 1_500), Fee::new(FeeKind::Commission, 250)]);
+    asset.purchase_with(date("2021-05-10"), buy).unwrap();
+    let sell = Transaction::new("bank_transfer", 40_000, FiatCurrency::MXN).unwrap()
+        .with_fees(vec![Fee::new(FeeKind::Bank, 50)]);
+    asset.sell_with(date("2022-08-01"), sell, vec![Fee::new(FeeKind::Commission, 300)]).unwrap();
+    let evaluation = asset.evaluate(&PriceSheet::default(), &FxRates::default()).unwrap();
+    assert_eq!(evaluation.get_realized_basis(), 31_750);
+    assert_eq!(evaluation.get_exit_amount(), Some(39_650));
+    assert_eq!(evaluation.get_realized_gain(), Some(7_900));
+    let restored: Asset = serde_json::from_str(&serde_json::to_string(asset).unwrap()).unwrap();
+    assert_eq!(&restored, asset);
 
-    assert!(new_user.is_equal(user));
-    assert!(new_app.is_equal(app));
+    let btc = app.create_new_asset(retiro, AssetType::Bitcoin { address: None, sats: 100_000_000 });
+    let buy = Transaction::new("card", 100_000, FiatCurrency::USD).unwrap()
+        .with_fees(vec![Fee::new(FeeKind::Commission, 1_000)]);
+    app.get_asset_mut(btc).unwrap().purchase_with(date("2022-03-15"), buy).unwrap();
 
-    // let users = app.import_user_app_state_hash();
+    let report = app.get_fees_report().unwrap();
+    let lines: Vec<(&str, i32, u128)> = report.lines
+        .iter()
+        .map(|line| (line.fund.as_str(), line.year, line.total))
+        .collect();
+    assert_eq!(lines, vec![("liberty", 2021, 1_750), ("liberty", 2022, 350), ("retiro", 2022, 20_583)]);
+    assert_eq!(report.lines[1].by_kind.get(&FeeKind::Commission), Some(&300));
+    assert_eq!(report.lines[0].by_kind.get(&FeeKind::Premium), Some(&1_500));
+    assert_eq!(report.by_year.iter().map(|line| line.total).collect::<Vec<u128>>(), vec![1_750, 20_933]);
+    assert_eq!(report.total, 22_683);
+}
 
-    // app.login(user);
-    // assert!(user.is_valid_password("admin123"));
-    // let app = App::new();
-    // create_new_asset()
-}
\ No newline at end of file
+#[test]
+fn inpc_update_for_disposal() {
+    let mut index = InflationIndex::default();
+    for (year, month, value) in [(2020, 1, 100_000_000), (2021, 1, 110_000_000), (2022, 2, 121_000_000)] {
+        index.insert(year, month, value);
+    }
+    // The month before the disposal, never below the acquisition index.
+    assert_eq!(index.update_for_disposal(100_000, date("2020-01-15"), date("2022-03-10")).unwrap(), 121_000);
+    assert_eq!(index.update_for_disposal(50_000, date("2022-02-10"), date("2022-03-05")).unwrap(), 50_000);
+    assert!(matches!(
+        index.update_for_disposal(100_000, date("2020-01-15"), date("2021-06-01")).unwrap_err(),
+        AppErrors::MissingPrice(_)
+    ));
+
+    // Made up values, only the file format is real.
+    let index = InflationIndex::from_file("./src/tests/fixtures/synthetic_inpc.json").unwrap();
+    assert_eq!(index.get(2018, 1).unwrap(), 98_795_000);
+}
+
+/// A bitcoin lot sold with a gain in 2022 and a gold coin sold at a loss in
+/// 2023, of a USD user.
+fn new_app_with_disposals(user_name: &str) -> (App, AssetId) {
+    let (mut app, liberty) = new_app_with_fund(user_name);
+    app.load_fx_rates("./files/dev/fx_rates.json").unwrap();
+    // The ISR is in pesos whatever the user currency.
+    app.set_fiat_currency(FiatCurrency::USD);
+    app.create_fund("retiro".to_string()).unwrap();
+    let retiro = app.get_fund(&"retiro".to_string()).unwrap();
+
+    // One and a half bitcoin from two lots, first in first out.
+    let btc = 100_000_000;
+    let bitcoin = app.create_new_asset(liberty, AssetType::Bitcoin { address: None, sats: 2 * btc });
+    let asset = app.get_asset_mut(bitcoin).unwrap();
+    for (settled_at, amount) in [("2020-01-15", 100_000), ("2021-01-15", 200_000)] {
+        asset.purchase_lot(Buy::new(date(settled_at), btc, "fiat_cash".to_string(), amount, FiatCurrency::MXN).unwrap()).unwrap();
+    }
+    asset.sell_lot(Sell::new(date("2022-03-10"), 3 * btc / 2, "fiat_cash".to_string(), 600_000, FiatCurrency::MXN, 1_000).unwrap()).unwrap();
+
+    let coin = AssetType::new("gold".to_string(), json::object!{ presentation: "Centenario" }).unwrap();
+    let gold = app.create_new_asset(retiro, coin);
+    let asset = app.get_asset_mut(gold).unwrap();
+    asset.purchase(date("2022-02-10"), 50_000, FiatCurrency::MXN).unwrap();
+    asset.sell(date("2023-03-05"), "fiat_cash".to_string(), 40_000, FiatCurrency::MXN, 0).unwrap();
+    (app, bitcoin)
+}
+
+fn disposals_inpc() -> InflationIndex {
+    let mut index = InflationIndex::default();
+    for (year, month, value) in [(2020, 1, 100_000_000), (2021, 1, 110_000_000), (2022, 2, 121_000_000), (2023, 2, 120_000_000)] {
+        index.insert(year, month, value);
+    }
+    index
+}
+
+#[test]
+fn isr_tax_report_with_inpc_updated_basis() {
+    let (app, bitcoin) = new_app_with_disposals("TESTISR");
+    let report = app.get_tax_report(&disposals_inpc(), None).unwrap();
+    assert_eq!(report.disposals.len(), 2);
+    let disposal = &report.disposals[0];
+    assert_eq!((disposal.asset_id, disposal.fund.as_str(), disposal.units), (bitcoin, "liberty", 150_000_000));
+    assert_eq!((disposal.proceeds, disposal.basis, disposal.updated_basis, disposal.gain), (599_000, 200_000, 231_000, 368_000));
+    assert_eq!(report.disposals[1].gain, -10_000);
+    let years: Vec<(i32, u128, u128, i128)> = report.years
+        .iter()
+        .map(|year| (year.year, year.gains, year.losses, year.get_taxable_gain()))
+        .collect();
+    assert_eq!(years, vec![(2022, 368_000, 0, 368_000), (2023, 0, 10_000, -10_000)]);
+
+    // Only the indexes of the year are needed.
+    let mut index = InflationIndex::default();
+    index.insert(2022, 2, 121_000_000);
+    index.insert(2023, 2, 120_000_000);
+    assert_eq!(app.get_tax_report(&index, Some(2023)).unwrap().disposals.len(), 1);
+    assert!(matches!(app.get_tax_report(&index, Some(2022)).unwrap_err(), AppErrors::MissingPrice(_)));
+}
+
+#[test]
+fn isr_tax_report_csv_export() {
+    let (app, _) = new_app_with_disposals("TESTISRCSV");
+    let report = app.get_tax_report(&disposals_inpc(), None).unwrap();
+    let file_path = temp_file_path("TESTISR_tax.csv");
+    report.export_csv(&file_path).unwrap();
+    let csv = fs::read_to_string(&file_path).unwrap();
+    fs::remove_file(&file_path).unwrap();
+    assert_eq!(
+        csv,
+        "year,disposals,proceeds,basis,updated_basis,gains,losses,taxable_gain\n\
+         2022,1,599000,200000,231000,368000,0,368000\n\
+         2023,1,40000,50000,50000,0,10000,-10000\n"
+    );
+}
+
+#[test]
+fn annualized_bps_and_inflation_index_lag() {
+    let two_years = 2 * 36_525 * 24 * 60 * 60 * 10;
+    assert_eq!(annualized_bps(100, 121, two_years), Some(1_000));
+    assert_eq!(annualized_bps(100, 81, two_years), Some(-1_000));
+    assert_eq!(annualized_bps(0, 121, two_years), None);
+    assert_eq!(annualized_bps(100, 0, two_years), None);
+
+    let mut index = InflationIndex::default();
+    for (year, month, value) in [(2020, 1, 100_000_000), (2020, 2, 110_000_000), (2020, 3, 125_000_000), (2020, 4, 150_000_000)] {
+        index.insert(year, month, value);
+    }
+    // Up to two months not published yet use the last index, then it is missing.
+    assert_eq!(index.adjust(100_000, date("2020-01-10"), date("2020-06-30")).unwrap(), 150_000);
+    assert!(index.is_stale_at(date("2020-06-30")));
+    assert!(!index.is_stale_at(date("2020-04-01")));
+    for (from, to) in [("2020-01-10", "2020-07-01"), ("2019-12-01", "2020-04-01")] {
+        assert!(matches!(index.adjust(100_000, date(from), date(to)).unwrap_err(), AppErrors::MissingPrice(_)));
+    }
+}
+
+/// 100 until 2021, 125 from 2022 and 150 last month, today not published.
+fn index_until_last_month() -> InflationIndex {
+    let today = Utc::now();
+    let mut index = InflationIndex::default();
+    for year in 2020..=today.year() {
+        for month in 1..=12 {
+            if (year, month) >= (today.year(), today.month()) {
+                break;
+            }
+            index.insert(year, month, if year < 2022 { 100_000_000 } else { 125_000_000 });
+        }
+    }
+    let (year, month) = if today.month() == 1 { (today.year() - 1, 12) } else { (today.year(), today.month() - 1) };
+    index.insert(year, month, 150_000_000);
+    index
+}
+
+/// A bitcoin lot of 2020 sold in 2022, and the lot of 2022 still held.
+fn new_app_with_real_returns(user_name: &str) -> (App, AssetId) {
+    let (mut app, fund) = new_app_with_fund(user_name);
+    let btc = 100_000_000;
+    let asset_id = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 2 * btc });
+    let asset = app.get_asset_mut(asset_id).unwrap();
+    for (settled_at, amount) in [("2020-01-10", 100_000), ("2022-01-10", 200_000)] {
+        asset.purchase_lot(Buy::new(date(settled_at), btc, "fiat_cash".to_string(), amount, FiatCurrency::MXN).unwrap()).unwrap();
+    }
+    asset.sell_lot(Sell::new(date("2022-01-20"), btc, "fiat_cash".to_string(), 250_000, FiatCurrency::MXN, 0).unwrap()).unwrap();
+    app.price_sheet = PriceSheet { btc: Some(400_000), ..PriceSheet::default() };
+    (app, asset_id)
+}
+
+#[test]
+fn real_returns_adjusted_by_inflation() {
+    let (app, asset_id) = new_app_with_real_returns("TESTREAL");
+    let asset = app.get_asset(asset_id).unwrap();
+    assert_eq!(asset.evaluate(&app.price_sheet, &FxRates::default()).unwrap().get_real_return(), None);
+    let evaluation = asset.evaluate_with_inflation(&app.price_sheet, &FxRates::default(), Some(&index_until_last_month())).unwrap();
+    assert_eq!(evaluation.get_entrance_amount(), 200_000);
+    assert_eq!(evaluation.get_realized_gain(), Some(150_000));
+    // Held lot 200,000 * 150/125, sold lot 100,000 * 150/100 for 250,000 * 150/125.
+    let real_return = evaluation.get_real_return().unwrap();
+    assert_eq!(real_return.entrance_amount, 240_000);
+    assert_eq!(real_return.gain, 400_000 + 300_000 - 240_000 - 150_000);
+    assert!(real_return.annualized_bps.unwrap() > 0);
+    assert!(real_return.stale);
+
+    let mut index = InflationIndex::default();
+    index.insert(2021, 1, 100_000_000);
+    assert!(matches!(
+        asset.evaluate_with_inflation(&app.price_sheet, &FxRates::default(), Some(&index)).unwrap_err(),
+        AppErrors::MissingPrice(_)
+    ));
+
+    // Without inflation the real return is the nominal one, both until the sell.
+    let today = Utc::now();
+    let mut flat = InflationIndex::default();
+    for year in 2020..=today.year() {
+        for month in (1..=12).filter(|month| (year, *month) < (today.year(), today.month())) {
+            flat.insert(year, month, 100_000_000);
+        }
+    }
+    let (mut app, fund) = new_app_with_fund("TESTREALSOLD");
+    let coin = AssetType::new("gold".to_string(), json::object!{ presentation: "Centenario" }).unwrap();
+    let gold = app.create_new_asset(fund, coin);
+    let asset = app.get_asset_mut(gold).unwrap();
+    asset.purchase(date("2021-01-01"), 100_000, FiatCurrency::MXN).unwrap();
+    asset.sell_lot(Sell::new(date("2023-01-01"), 1, "fiat_cash".to_string(), 121_000, FiatCurrency::MXN, 0).unwrap()).unwrap();
+    let evaluation = asset.evaluate_with_inflation(&PriceSheet::default(), &FxRates::default(), Some(&flat)).unwrap();
+    assert_eq!(evaluation.get_real_return().unwrap().annualized_bps, evaluation.get_annualized_bps());
+    assert!((995..=1_005).contains(&evaluation.get_annualized_bps().unwrap()));
+}
+
+#[test]
+fn dashboard_real_gain_with_the_inflation_index() {
+    let (mut app, _) = new_app_with_real_returns("TESTREALDASHBOARD");
+    let today = Utc::now();
+    let mut index = index_until_last_month();
+    index.insert(today.year(), today.month(), 150_000_000);
+    app.set_inflation_index(index);
+    let dashboard = app.get_dashboard(&app.price_sheet, &PriceHistory::default()).unwrap();
+    let real_return = dashboard.rows[0].real_return.as_ref().unwrap();
+    assert_eq!(dashboard.total.real_gain, Some(real_return.gain));
+    assert!(!real_return.stale);
+}
+
+#[test]
+fn xirr_of_cash_flows() {
+    let year = 36_525 * 24 * 60 * 60 * 10;
+    assert_eq!(xirr_bps(&[(0, -100_000), (year, 110_000)]), Some(1_000));
+    assert_eq!(xirr_bps(&[(0, -100_000), (year, 81_000), (2 * year, 0)]), Some(-1_900));
+    assert_eq!(xirr_bps(&[(0, -100_000), (year, -10_000)]), None);
+}
+
+#[test]
+fn annualized_return_over_the_holding_time() {
+    let (mut app, fund) = new_app_with_fund("TESTCAGR");
+    // CAGR over the holding time, until the sell.
+    let coin = AssetType::new("gold".to_string(), json::object!{ presentation: "Centenario" }).unwrap();
+    let gold = app.create_new_asset(fund, coin);
+    let asset = app.get_asset_mut(gold).unwrap();
+    asset.purchase(date("2021-01-01"), 100_000, FiatCurrency::MXN).unwrap();
+    asset.sell(date("2023-01-01"), "fiat_cash".to_string(), 121_000, FiatCurrency::MXN, 0).unwrap();
+    let evaluation = asset.evaluate(&PriceSheet::default(), &FxRates::default()).unwrap();
+    let held = date("2023-01-01") - date("2021-01-01");
+    assert_eq!(evaluation.get_annualized_bps(), annualized_bps(100_000, 121_000, held));
+    assert!((995..=1_005).contains(&evaluation.get_annualized_bps().unwrap()));
+
+    let dashboard = app.get_dashboard(&PriceSheet::default(), &PriceHistory::default()).unwrap();
+    assert_eq!(dashboard.rows[0].annualized_bps, evaluation.get_annualized_bps());
+}
+
+#[test]
+fn money_and_time_weighted_returns_of_a_fund() {
+    let (mut app, fund) = new_app_with_fund("TESTRETURNS");
+    // A second bitcoin bought after the price went up 50%, then doubled.
+    let btc = 100_000_000;
+    let bitcoin = app.create_new_asset(fund, AssetType::Bitcoin { address: None, sats: 2 * btc });
+    let asset = app.get_asset_mut(bitcoin).unwrap();
+    for (settled_at, amount) in [("2023-01-01", 100_000), ("2024-01-01", 150_000)] {
+        asset.purchase_lot(Buy::new(date(settled_at), btc, "fiat_cash".to_string(), amount, FiatCurrency::MXN).unwrap()).unwrap();
+    }
+    let mut price_history = PriceHistory::default();
+    for (datetime, median) in [("2023-01-01", 100_000), ("2024-01-01", 150_000)] {
+        price_history.append(MarketSnapshot::new(date(datetime), "BTC".to_string(), None, FiatCurrency::MXN, None, None, None, median)).unwrap();
+    }
+    let asset = app.get_asset(bitcoin).unwrap();
+    assert_eq!(asset.get_held_units_at(date("2023-06-01")), btc);
+    assert_eq!(asset.get_value_at(date("2023-06-01"), btc, &price_history, &FxRates::default()).unwrap(), 100_000);
+    let now = date("2025-01-01");
+    let returns = GroupReturns::new("liberty".to_string(), &[asset], 600_000, &price_history, &FxRates::default(), now).unwrap();
+    // 1.5 the first year and 2 the second, whatever was bought.
+    assert_eq!(returns.twr_bps, annualize_growth_bps(3.0, now - date("2023-01-01")));
+    assert!((7_300..=7_330).contains(&returns.twr_bps.unwrap()));
+    // 100 (1 + r)^2 + 150 (1 + r) = 600
+    assert!((8_100..=8_130).contains(&returns.xirr_bps.unwrap()));
+    let returns = GroupReturns::new("liberty".to_string(), &[asset], 600_000, &PriceHistory::default(), &FxRates::default(), now).unwrap();
+    assert_eq!(returns.twr_bps, None);
+
+    let price_sheet = PriceSheet { btc: Some(300_000), ..PriceSheet::default() };
+    let dashboard = app.get_dashboard(&price_sheet, &price_history).unwrap();
+    let labels: Vec<&str> = dashboard.returns.iter().map(|returns| returns.label.as_str()).collect();
+    assert_eq!(labels, vec!["liberty", "TOTAL"]);
+    assert!(dashboard.returns[0].xirr_bps.is_some());
+}
+
+fn date_strings(dates: Vec<EpochMillis>) -> Vec<String> {
+    dates.into_iter().map(|at| Now::new_from_epoch_millis(at).to_date_string()).collect()
+}
+
+#[test]
+fn net_worth_intervals() {
+    assert_eq!(
+        date_strings(Interval::from_str("monthly").unwrap().get_dates(date("2023-01-31"), date("2023-04-30"))),
+        vec!["2023-01-31", "2023-02-28", "2023-03-31", "2023-04-30"]
+    );
+    assert_eq!(Interval::Weekly.get_dates(date("2023-01-01"), date("2023-01-29")).len(), 5);
+    assert!(matches!(Interval::from_str("yearly").unwrap_err(), AppErrors::InvalidOperation(_)));
+
+    // Nothing bought or deposited to start from.
+    let (app, _) = new_app_with_fund("TESTNETWORTHEMPTY");
+    assert!(matches!(
+        app.get_net_worth_series(None, date("2023-04-01"), Interval::Monthly, &PriceHistory::default()).unwrap_err(),
+        AppErrors::InvalidOperation(_)
+    ));
+}
+
+/// Half a bitcoin sold in March, a gold coin since February, MXN cash in
+/// liberty and USD cash in retiro, with the prices of the history.
+fn new_app_with_holdings(user_name: &str) -> (App, PriceHistory) {
+    let (mut app, liberty) = new_app_with_fund(user_name);
+    app.load_fx_rates("./files/dev/fx_rates.json").unwrap();
+    app.create_fund("retiro".to_string()).unwrap();
+    let retiro = app.get_fund(&"retiro".to_string()).unwrap();
+
+    let btc = 100_000_000;
+    let bitcoin = app.create_new_asset(liberty.clone(), AssetType::Bitcoin { address: None, sats: btc });
+    let asset = app.get_asset_mut(bitcoin).unwrap();
+    asset.purchase(date("2023-01-10"), 100_000, FiatCurrency::MXN).unwrap();
+    asset.sell_lot(Sell::new(date("2023-03-10"), btc / 2, "fiat_cash".to_string(), 45_000, FiatCurrency::MXN, 0).unwrap()).unwrap();
+    let coin = AssetType::new("gold".to_string(), json::object!{ presentation: "Centenario" }).unwrap();
+    let gold = app.create_new_asset(retiro.clone(), coin);
+    app.get_asset_mut(gold).unwrap().purchase(date("2023-02-15"), 40_000, FiatCurrency::MXN).unwrap();
+    app.create_cash_account(liberty.get_name(), "bbva".to_string(), FiatCurrency::MXN).unwrap();
+    app.create_cash_account(retiro.get_name(), "chase".to_string(), FiatCurrency::USD).unwrap();
+    app.add_cash_movement("bbva", CashMovement::new(CashMovementKind::Deposit, date("2023-01-05"), 50_000)).unwrap();
+    app.add_cash_movement("chase", CashMovement::new(CashMovementKind::Deposit, date("2023-01-02"), 1_000)).unwrap();
+
+    let mut price_history = PriceHistory::default();
+    for (datetime, market_key, median) in [
+        ("2023-01-10", "BTC", 100_000),
+        ("2023-02-01", "BTC", 120_000),
+        ("2023-03-01", "BTC", 90_000),
+        ("2023-02-15", "GOLD-GRAM-24K", 1_000)
+    ] {
+        price_history.append(MarketSnapshot::new(date(datetime), market_key.to_string(), None, FiatCurrency::MXN, None, None, None, median)).unwrap();
+    }
+    (app, price_history)
+}
+
+/// The USD 10 of the chase account in pesos at the rate of the date.
+fn chase_at(app: &App, datetime: &str) -> u128 {
+    app.fx_rates.convert(1_000, &FiatCurrency::USD, &FiatCurrency::MXN, Some(date(datetime))).unwrap()
+}
+
+#[test]
+fn point_in_time_valuation() {
+    let (app, price_history) = new_app_with_holdings("TESTVALUATION");
+    // Bought after the date, nothing held yet.
+    let valuation = app.get_valuation_at(date("2023-02-01"), &price_history).unwrap();
+    assert_eq!(valuation.holdings.len(), 1);
+    assert_eq!(valuation.get_assets_amount(), 120_000);
+    assert_eq!(valuation.get_cash_amount(), 50_000 + chase_at(&app, "2023-02-01"));
+    // Half a bitcoin left after the sell and the 37.5 g of fine gold.
+    let valuation = app.get_valuation_at(date("2023-03-15"), &price_history).unwrap();
+    assert_eq!(valuation.holdings.iter().map(|holding| holding.amount).collect::<Vec<u128>>(), vec![45_000, 37_500]);
+    let by_fund: Vec<(&str, u128)> = valuation.get_by_fund().into_iter().map(|(fund, amount)| (fund.as_str(), amount)).collect();
+    assert_eq!(by_fund, vec![("liberty", 95_000), ("retiro", 37_500 + chase_at(&app, "2023-03-15"))]);
+    assert!(matches!(
+        app.get_valuation_at(date("2023-02-01"), &PriceHistory::default()).unwrap_err(),
+        AppErrors::MissingPrice(_)
+    ));
+
+    // A manual price wins over the history, as in the dashboard.
+    let (mut app, fund) = new_app_with_fund("TESTVALUATIONMANUAL");
+    let painting = AssetType::new("other".to_string(), json::object!{
+        symbol: "painting", quantity: "1", decimals: 0, unit_price: "850"
+    }).unwrap();
+    let painting = app.create_new_asset(fund, painting);
+    app.get_asset_mut(painting).unwrap().purchase(date("2023-01-10"), 50_000, FiatCurrency::MXN).unwrap();
+    let mut price_history = PriceHistory::default();
+    price_history.append(MarketSnapshot::new(date("2023-02-01"), "PAINTING".to_string(), None, FiatCurrency::MXN, None, None, None, 1)).unwrap();
+    let asset = app.get_asset(painting).unwrap();
+    assert_eq!(asset.get_value_at(date("2023-03-01"), 1, &price_history, &FxRates::default()).unwrap(), 85_000);
+    assert_eq!(asset.get_market_price(&PriceSheet::default()).unwrap().get_median(), 85_000);
+}
+
+#[test]
+fn net_worth_history() {
+    let (app, price_history) = new_app_with_holdings("TESTNETWORTH");
+    let series = app.get_net_worth_series(Some(date("2023-01-01")), date("2023-04-01"), Interval::Monthly, &price_history).unwrap();
+    let net_worth: Vec<u128> = series.points.iter().map(|point| point.get_net_worth()).collect();
+    assert_eq!(net_worth, vec![
+        0,
+        170_000 + chase_at(&app, "2023-02-01"),
+        90_000 + 37_500 + 50_000 + chase_at(&app, "2023-03-01"),
+        45_000 + 37_500 + 50_000 + chase_at(&app, "2023-04-01")
+    ]);
+    // From the first cash movement.
+    let series_from_start = app.get_net_worth_series(None, date("2023-04-01"), Interval::Monthly, &price_history).unwrap();
+    assert_eq!(date_strings(series_from_start.points.iter().map(|point| point.at).collect())[0], "2023-01-02");
+}
+
+#[test]
+fn net_worth_csv_export() {
+    let (app, price_history) = new_app_with_holdings("TESTNETWORTHCSV");
+    let series = app.get_net_worth_series(Some(date("2023-01-01")), date("2023-04-01"), Interval::Monthly, &price_history).unwrap();
+    let file_path = temp_file_path("TESTNETWORTH_series.csv");
+    series.export_csv(&file_path).unwrap();
+    let csv = fs::read_to_string(&file_path).unwrap();
+    fs::remove_file(&file_path).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "date,assets,cash,net_worth,liberty,retiro");
+    assert_eq!(lines[1], "2023-01-01,0,0,0,0,0");
+    let chase = chase_at(&app, "2023-02-01");
+    assert_eq!(lines[2], format!("2023-02-01,120000,{},{},170000,{}", 50_000 + chase, 170_000 + chase, chase));
+}
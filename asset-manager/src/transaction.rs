@@ -1,10 +1,187 @@
+use std::fmt;
+use std::str::FromStr;
+
+use json::JsonValue;
 use serde::{Serialize, Deserialize};
+
+use crate::errors::AppErrors;
+use crate::fees::{deserialize_fees, get_total, parse_fees, Fee};
 use crate::models::FiatCurrency;
+use crate::utils::{normal_input_string, parse_option_string, parse_u128};
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RewardKind {
+    Mining,
+    Staking
+}
+
+/// How a buy, sell, income or expense settled. The `amount` is always the
+/// fiat value of the transaction, the `fees` components are paid on top of it.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Transaction {
     FiatCash {
         amount: u128,
-        currency: FiatCurrency
+        currency: FiatCurrency,
+        #[serde(default, deserialize_with = "deserialize_fees")]
+        fees: Vec<Fee>
+    },
+    BankTransfer {
+        amount: u128,
+        currency: FiatCurrency,
+        #[serde(default, deserialize_with = "deserialize_fees")]
+        fees: Vec<Fee>
+    },
+    Card {
+        amount: u128,
+        currency: FiatCurrency,
+        #[serde(default, deserialize_with = "deserialize_fees")]
+        fees: Vec<Fee>
+    },
+    /// Exchanged for `units` of another crypto, `symbol`, worth `amount`
+    /// at the settlement.
+    CryptoSwap {
+        symbol: String,
+        units: u128,
+        amount: u128,
+        currency: FiatCurrency,
+        #[serde(default, deserialize_with = "deserialize_fees")]
+        fees: Vec<Fee>
+    },
+    /// Received for free. The basis is the appraised `amount`, zero without
+    /// an appraisal.
+    Gift {
+        amount: u128,
+        currency: FiatCurrency,
+        #[serde(default, deserialize_with = "deserialize_fees")]
+        fees: Vec<Fee>
+    },
+    Inheritance {
+        amount: u128,
+        currency: FiatCurrency,
+        #[serde(default, deserialize_with = "deserialize_fees")]
+        fees: Vec<Fee>
+    },
+    /// The basis is the market value of the units when received.
+    Reward {
+        kind: RewardKind,
+        amount: u128,
+        currency: FiatCurrency,
+        #[serde(default, deserialize_with = "deserialize_fees")]
+        fees: Vec<Fee>
+    }
+}
+
+impl Transaction {
+    /// Every kind except `crypto_swap`, which needs the units given.
+    pub fn new(transaction_str: &str, amount: u128, currency: FiatCurrency) -> Result<Self, AppErrors> {
+        Ok(match transaction_str.to_lowercase().as_str() {
+            "fiat_cash" => Transaction::FiatCash { amount, currency, fees: Vec::new() },
+            "bank_transfer" => Transaction::BankTransfer { amount, currency, fees: Vec::new() },
+            "card" => Transaction::Card { amount, currency, fees: Vec::new() },
+            "gift" => Transaction::Gift { amount, currency, fees: Vec::new() },
+            "inheritance" => Transaction::Inheritance { amount, currency, fees: Vec::new() },
+            "mining" => Transaction::Reward { kind: RewardKind::Mining, amount, currency, fees: Vec::new() },
+            "staking" => Transaction::Reward { kind: RewardKind::Staking, amount, currency, fees: Vec::new() },
+            _ => return Err(AppErrors::UnknownTransaction(transaction_str.to_string()))
+        })
+    }
+
+    /// Parses `{ "amount": "100", "currency": "MXN", "fees": "5" }` of the
+    /// user file. Swaps also have the `symbol` and `units` given or received.
+    pub fn from_json(transaction_str: &str, data: &JsonValue) -> Result<Self, AppErrors> {
+        let amount = parse_u128(data, "amount")?;
+        let currency = FiatCurrency::from_str(&data["currency"].to_string())?;
+        let transaction = match transaction_str.to_lowercase().as_str() {
+            "crypto_swap" => Transaction::CryptoSwap {
+                symbol: parse_option_string(data, "symbol")
+                    .map(|symbol| normal_input_string(&symbol))
+                    .ok_or_else(|| AppErrors::MalformedFile("crypto_swap without symbol".to_string()))?,
+                units: parse_u128(data, "units")?,
+                amount,
+                currency,
+                fees: Vec::new()
+            },
+            _ => Transaction::new(transaction_str, amount, currency)?
+        };
+        Ok(transaction.with_fees(parse_fees(&data["fees"])?))
+    }
+
+    pub fn with_fees(mut self, new_fees: Vec<Fee>) -> Self {
+        match &mut self {
+            Transaction::FiatCash { fees, .. }
+            | Transaction::BankTransfer { fees, .. }
+            | Transaction::Card { fees, .. }
+            | Transaction::CryptoSwap { fees, .. }
+            | Transaction::Gift { fees, .. }
+            | Transaction::Inheritance { fees, .. }
+            | Transaction::Reward { fees, .. } => *fees = new_fees
+        }
+        self
+    }
+
+    pub fn get_amount_currency(&self) -> (u128, FiatCurrency) {
+        match self {
+            Transaction::FiatCash { amount, currency, .. }
+            | Transaction::BankTransfer { amount, currency, .. }
+            | Transaction::Card { amount, currency, .. }
+            | Transaction::CryptoSwap { amount, currency, .. }
+            | Transaction::Gift { amount, currency, .. }
+            | Transaction::Inheritance { amount, currency, .. }
+            | Transaction::Reward { amount, currency, .. } => (*amount, currency.clone())
+        }
+    }
+
+    pub fn get_fee_components(&self) -> &[Fee] {
+        match self {
+            Transaction::FiatCash { fees, .. }
+            | Transaction::BankTransfer { fees, .. }
+            | Transaction::Card { fees, .. }
+            | Transaction::CryptoSwap { fees, .. }
+            | Transaction::Gift { fees, .. }
+            | Transaction::Inheritance { fees, .. }
+            | Transaction::Reward { fees, .. } => fees
+        }
+    }
+
+    pub fn get_fees(&self) -> u128 {
+        get_total(self.get_fee_components())
+    }
+
+    /// Amount plus fees, what an acquisition or expense costs.
+    pub fn get_cost(&self) -> (u128, FiatCurrency) {
+        let (amount, currency) = self.get_amount_currency();
+        (amount + self.get_fees(), currency)
+    }
+
+    /// Amount minus fees, what a disposal or income brings in.
+    pub fn get_proceeds(&self) -> (u128, FiatCurrency) {
+        let (amount, currency) = self.get_amount_currency();
+        (amount.saturating_sub(self.get_fees()), currency)
+    }
+
+    /// Paid or received in fiat, it can settle in a cash account.
+    pub fn is_fiat(&self) -> bool {
+        matches!(self, Transaction::FiatCash { .. } | Transaction::BankTransfer { .. } | Transaction::Card { .. })
+    }
+
+    /// Gifts, inheritances and rewards only acquire units, nothing is
+    /// exchanged for them.
+    pub fn is_exchange(&self) -> bool {
+        self.is_fiat() || matches!(self, Transaction::CryptoSwap { .. })
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transaction::FiatCash { .. } => write!(f, "FIAT_CASH"),
+            Transaction::BankTransfer { .. } => write!(f, "BANK_TRANSFER"),
+            Transaction::Card { .. } => write!(f, "CARD"),
+            Transaction::CryptoSwap { symbol, .. } => write!(f, "CRYPTO_SWAP {}", symbol),
+            Transaction::Gift { .. } => write!(f, "GIFT"),
+            Transaction::Inheritance { .. } => write!(f, "INHERITANCE"),
+            Transaction::Reward { kind: RewardKind::Mining, .. } => write!(f, "MINING"),
+            Transaction::Reward { kind: RewardKind::Staking, .. } => write!(f, "STAKING"),
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,8 @@
 pub type EpochMillis = u64;
-pub type FundId = String;
 pub type AssetId = u32;
 pub type UserName = String;
 pub type FundName = String;
-pub type HashString = String;
\ No newline at end of file
+pub type HashString = String;
+/// Market of a price, "BTC" or "GOLD-GRAM-24K". Every asset of the same
+/// market shares its price.
+pub type MarketKey = String;
\ No newline at end of file
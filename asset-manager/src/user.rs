@@ -1,14 +1,13 @@
 
-use std::fs;
 use std::path::Path;
 
 use serde::{Serialize, Deserialize};
 use crate::app::App;
+use crate::cost_basis::CostBasis;
+use crate::errors::AppErrors;
 use crate::types::{UserName, HashString};
 use crate::models::{AppEnv, FiatCurrency};
-// use crate::errors::AppErrors;
-// use std::fs;
-// use std::path::Path;
+use crate::utils::{read_file, write_file};
 
 use scrypt::{
     password_hash::{
@@ -18,7 +17,7 @@ use scrypt::{
     Scrypt
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct User {
     name: UserName,
     env: AppEnv,
@@ -27,115 +26,132 @@ pub(crate) struct User {
 }
 
 impl User {
-    pub fn new(name: &str, password: &str, env: AppEnv) -> Self {
+    pub fn new(name: &str, password: &str, env: AppEnv) -> Result<Self, AppErrors> {
+        User::check_name(name)?;
         let salt = SaltString::generate(&mut OsRng);
-        let password_hash = Scrypt.hash_password(password.as_bytes(), &salt).unwrap().to_string();
+        let password_hash = Scrypt
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|err| AppErrors::Crypto(err.to_string()))?
+            .to_string();
 
-        User {
+        Ok(User {
             name: name.to_string(),
             env,
             password_hash,
             app_state_hash: None
+        })
+    }
+
+    /// Creates a brand new user and writes its file. Fails if the user exists.
+    pub fn create(name: &str, password: &str, env: AppEnv) -> Result<Self, AppErrors> {
+        User::check_name(name)?;
+        if Path::new(&User::file_path(name, &env)).exists() {
+            return Err(AppErrors::UserAlreadyExists(name.to_string()));
+        }
+        let user = User::new(name, password, env)?;
+        user.save()?;
+        Ok(user)
+    }
+
+    /// Login. Reads `files/<env>/user_<name>.json` and verifies the password.
+    pub fn open(name: &str, password: &str, env: AppEnv) -> Result<Self, AppErrors> {
+        User::check_name(name)?;
+        let user_file_path = User::file_path(name, &env);
+        if !Path::new(&user_file_path).exists() {
+            return Err(AppErrors::UserNotFound(name.to_string()));
+        }
+        let content = read_file(&user_file_path)?;
+        let user: User = serde_json::from_str(&content)
+            .map_err(|err| AppErrors::MalformedFile(format!("{}: {}", user_file_path, err)))?;
+        if !user.is_valid_password(password) {
+            return Err(AppErrors::InvalidPassword);
+        }
+        Ok(user)
+    }
+
+    pub fn is_valid_password(&self, password: &str) -> bool {
+        match PasswordHash::new(&self.password_hash) {
+            Ok(parsed_hash) => Scrypt.verify_password(password.as_bytes(), &parsed_hash).is_ok(),
+            Err(_) => false
+        }
+    }
+
+    /// Seals the first app state of the user.
+    pub fn new_app_state(&mut self, app: &App, password: &str) -> Result<(), AppErrors> {
+        if !self.is_valid_password(password) {
+            return Err(AppErrors::InvalidPassword);
+        }
+        if self.app_state_hash.is_some() {
+            return Err(AppErrors::AppStateAlreadyExists);
+        }
+        self.app_state_hash = Some(app.save_state(password)?);
+        Ok(())
+    }
+
+    pub fn save_app_state(&mut self, app: &App, password: &str) -> Result<(), AppErrors> {
+        if !self.is_valid_password(password) {
+            return Err(AppErrors::InvalidPassword);
         }
+        if self.app_state_hash.is_none() {
+            return Err(AppErrors::AppStateNotFound);
+        }
+        self.app_state_hash = Some(app.save_state(password)?);
+        Ok(())
+    }
+
+    pub fn load_app_state(&self, password: &str) -> Result<App, AppErrors> {
+        if !self.is_valid_password(password) {
+            return Err(AppErrors::InvalidPassword);
+        }
+        if self.app_state_hash.is_none() {
+            return Err(AppErrors::AppStateNotFound);
+        }
+        App::load_state(self.clone(), password)
+    }
+
+    pub fn save(&self) -> Result<(), AppErrors> {
+        let content = serde_json::to_string_pretty(&self)
+            .map_err(|err| AppErrors::MalformedFile(err.to_string()))?;
+        write_file(&User::file_path(&self.name, &self.env), content)
+    }
+
+    pub fn get_name(&self) -> &UserName {
+        &self.name
     }
 
-    // pub fn open(name: &str, password: &str, env: AppEnv) -> User {
-    //     let salt = SaltString::generate(&mut OsRng);
-    //     let password_hash = Scrypt.hash_password(password.as_bytes(), &salt).unwrap().to_string();
-
-    //     let user_file_path = format!("./files/{}/user_{}.json", &env, name);
-    //     if Path::new(&user_file_path).exists() {
-    //         println!("USERS FILE EXIST!");
-    //         let content = fs::read_to_string(&user_file_path).expect("Error reading bond file");
-    //         // json::parse(&content).unwrap()
-    //         let result: User = serde_json::from_str(&content).unwrap();
-    //         assert!(result.is_valid_password(password), "Invalid password.");
-    //         result
-    //     } else {
-    //         // This is the default for ./ENV/users.json
-    //         println!("CREATING NEW USERS FILE!");
-    //         User::new(name, password, env)
-    //     }
-    // }
-
-    // fn is_valid_password(&self, password: &str) -> bool {
-    //     let parsed_hash = PasswordHash::new(&self.password_hash).unwrap();
-    //     Scrypt.verify_password(password.as_bytes(), &parsed_hash).is_ok()
-    // }
-
-    // pub fn new_app_state(&mut self, app: App, password: &str) {
-    //     println!("HERE 1");
-    //     assert!(self.is_valid_password(password), "Invalid password.");
-    //     println!("HERE 2");
-    //     assert!(self.app_state_hash.is_none(), "User already have a running app.");
-    //     println!("HERE 3");
-    //     self.app_state_hash = Some(app.get_state_hash(password));
-    // }
-
-    // pub fn save_app_state(&mut self, app: App, password: &str) {
-    //     assert!(self.is_valid_password(password), "Invalid password.");
-    //     assert!(self.app_state_hash.is_some(), "Create a new app state first.");
-    //     self.app_state_hash = Some(app.get_state_hash(password));
-    // }
-
-    // pub fn load_app_state(&self, password: &str) -> App {
-    //     assert!(self.is_valid_password(password), "Invalid password.");
-    //     assert!(self.app_state_hash.is_some(), "Create a new app state first.");
-
-    //     let mut app = App::new(self.clone());
-    //     app.load_owner_state(password);
-    //     app
-    // }
-
-    // pub fn save(&self) {
-    //     let user_file_path = format!("./files/{}/user_{}.json", &self.env, self.name);
-
-    //     std::fs::write(
-    //         user_file_path,
-    //         serde_json::to_string_pretty(&self).unwrap(),
-    //     )
-    //     .unwrap();
-    // }
-
-    // pub fn is_equal(&self, other: User) -> bool {
-    //     self.name == other.name
-    //         && self.env == other.env
-    //         && self.password_hash == other.password_hash
-    //         && self.app_state_hash == other.app_state_hash
-    // }
-
-    // fn import_app_state_hash(&mut self) {
-    //     assert!(self.app_state_hash.is_none());
-
-    //     let users_file_path = format!("./files/{}/users.json", &self.env);
-    //     let users = if Path::new(&users_file_path).exists() {
-    //         println!("USERS FILE EXIST!");
-    //         let content = fs::read_to_string(&users_file_path).expect("Error reading bond file");
-    //         json::parse(&content).unwrap()
-    //     } else {
-    //         // This is the default for ./ENV/users.json
-    //         println!("CREATING NEW USERS FILE!");
-    //         json::object! {
-    //             "users": []
-    //         }
-    //     };
-    //     for user in users["users"].members() {
-    //         if user["name"] == self.name && user["env"] == self.env.to_string() {
-    //             self.app_state_hash = user["app_state_hash"];
-    //         }           
-    //     }
-    // }
+    /// The name ends in the file paths, so `../` must not get in.
+    fn check_name(name: &str) -> Result<(), AppErrors> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(AppErrors::InvalidUserName(name.to_string()));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_data_dir(&self) -> String {
+        self.env.get_data_dir()
+    }
+
+    fn file_path(name: &str, env: &AppEnv) -> String {
+        format!("{}/user_{}.json", env.get_data_dir(), name)
+    }
+
+    /// The sealed app state lives next to the user file.
+    pub(crate) fn app_state_file_path(&self) -> String {
+        format!("{}/app_{}.vault", self.get_data_dir(), self.name)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct UserSettings {
     /// Preferred fiat currency.
-    pub(crate) fiat_currency: FiatCurrency
+    pub(crate) fiat_currency: FiatCurrency,
+    /// Method to compute the entrance amount and realized gains.
+    pub(crate) cost_basis: CostBasis
 }
 
 impl UserSettings {
     pub(crate) fn new(fiat_currency: FiatCurrency) -> Self {
-        UserSettings { fiat_currency }
+        UserSettings { fiat_currency, cost_basis: CostBasis::Fifo }
     }
 }
 
@@ -1,6 +1,14 @@
+// Expanded from `construct_uint!`.
+#![allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+
+use std::fs;
+use std::path::Path;
+
 use json::JsonValue;
 use uint::construct_uint;
 
+use crate::errors::AppErrors;
+
 construct_uint! {
     /// 256-bit unsigned integer.
     pub struct U256(4);
@@ -26,24 +34,129 @@ pub fn parse_option_string(data: &JsonValue, key: &str) -> Option<String> {
     }
 }
 
-pub fn parse_option_u16(data: &JsonValue, key: &str) -> Option<u16> {
+pub fn parse_option_u16(data: &JsonValue, key: &str) -> Result<Option<u16>, AppErrors> {
     if data[key].is_null() {
-        None
+        Ok(None)
     } else {
-        Some(data[key].as_u16().unwrap())
+        data[key].as_u16()
+            .map(Some)
+            .ok_or_else(|| AppErrors::MalformedAmount(format!("{} = {}", key, data[key])))
     }
 }
 
-pub fn parse_option_u64(data: &JsonValue, key: &str) -> Option<u64> {
+pub fn parse_option_u64(data: &JsonValue, key: &str) -> Result<Option<u64>, AppErrors> {
     if data[key].is_null() {
-        None
+        Ok(None)
     } else {
-        Some(data[key].as_u64().unwrap())
+        data[key].as_u64()
+            .map(Some)
+            .ok_or_else(|| AppErrors::MalformedAmount(format!("{} = {}", key, data[key])))
+    }
+}
+
+/// Amounts are stored as strings in the user file, "37000000", to avoid
+/// the f64 precision of json numbers.
+pub fn parse_u128(data: &JsonValue, key: &str) -> Result<u128, AppErrors> {
+    data[key]
+        .to_string()
+        .parse::<u128>()
+        .map_err(|_| AppErrors::MalformedAmount(format!("{} = {}", key, data[key])))
+}
+
+/// Parses a positive decimal string, "19.8752", into a fixed point integer
+/// with the given decimals, 19_875_200 for 6 decimals.
+pub fn parse_decimal(value: &str, decimals: u32) -> Result<u128, AppErrors> {
+    let malformed = || AppErrors::MalformedAmount(value.to_string());
+    let (integer, fraction) = match value.trim().split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (value.trim(), "")
+    };
+    if fraction.len() > decimals as usize
+        || (integer.is_empty() && fraction.is_empty())
+        || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(malformed());
+    }
+    let integer = if integer.is_empty() { 0 } else { integer.parse::<u128>().map_err(|_| malformed())? };
+    let fraction = format!("{:0<width$}", fraction, width = decimals as usize);
+    let fraction = if fraction.is_empty() { 0 } else { fraction.parse::<u128>().map_err(|_| malformed())? };
+    integer
+        .checked_mul(10u128.pow(decimals))
+        .and_then(|integer| integer.checked_add(fraction))
+        .ok_or_else(malformed)
+}
+
+/// Formats an integer amount with the given decimals and thousands
+/// separators, -1234567 with 2 decimals is "-12,345.67".
+pub fn format_amount(amount: i128, decimals: u32) -> String {
+    let scale = 10u128.pow(decimals);
+    let integer = (amount.unsigned_abs() / scale).to_string();
+    let mut grouped = String::with_capacity(integer.len() + integer.len() / 3);
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if amount < 0 { "-" } else { "" };
+    if decimals == 0 {
+        return format!("{}{}", sign, grouped);
     }
+    let fraction = amount.unsigned_abs() % scale;
+    format!("{}{}.{:0width$}", sign, grouped, fraction, width = decimals as usize)
+}
+
+pub(crate) fn read_file(file_path: &str) -> Result<String, AppErrors> {
+    fs::read_to_string(file_path).map_err(|err| AppErrors::Io(format!("{}: {}", file_path, err)))
+}
+
+pub(crate) fn read_json_file(file_path: &str) -> Result<JsonValue, AppErrors> {
+    json::parse(&read_file(file_path)?)
+        .map_err(|err| AppErrors::MalformedFile(format!("{}: {}", file_path, err)))
+}
+
+/// Writes the file, creating the parent directories if needed.
+pub(crate) fn write_file<C: AsRef<[u8]>>(file_path: &str, contents: C) -> Result<(), AppErrors> {
+    if let Some(dir) = Path::new(file_path).parent() {
+        fs::create_dir_all(dir).map_err(|err| AppErrors::Io(format!("{}: {}", file_path, err)))?;
+    }
+    fs::write(file_path, contents).map_err(|err| AppErrors::Io(format!("{}: {}", file_path, err)))
+}
+
+/// The path is the dir or inside it, links followed. A path not created yet
+/// is resolved from its closest existing ancestor.
+pub(crate) fn is_inside_dir(file_path: &str, dir: &str) -> bool {
+    let Ok(dir) = fs::canonicalize(dir) else {
+        return false;
+    };
+    Path::new(file_path)
+        .ancestors()
+        .map(|ancestor| if ancestor.as_os_str().is_empty() { Path::new(".") } else { ancestor })
+        .find_map(|ancestor| fs::canonicalize(ancestor).ok())
+        .is_some_and(|path| path.starts_with(dir))
 }
 
 #[inline]
 /// returns amount * numerator/denominator
 pub fn proportional(amount: u128, numerator: u128, denominator: u128) -> u128 {
-    return (U256::from(amount) * U256::from(numerator) / U256::from(denominator)).as_u128();
-}
\ No newline at end of file
+    (U256::from(amount) * U256::from(numerator) / U256::from(denominator)).as_u128()
+}
+
+/// Julian year, the period of the annualized returns.
+pub(crate) const YEAR_MILLIS: f64 = 365.25 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Compound yearly rate that turns `start` into `end` in `millis`, in basis
+/// points. None without a start, a period, or when everything was lost.
+pub fn annualized_bps(start: u128, end: i128, millis: u64) -> Option<i128> {
+    if start == 0 {
+        return None;
+    }
+    annualize_growth_bps(end as f64 / start as f64, millis)
+}
+
+/// Yearly rate of a total `growth` factor in `millis`, in basis points.
+pub fn annualize_growth_bps(growth: f64, millis: u64) -> Option<i128> {
+    if millis == 0 || growth <= 0.0 || !growth.is_finite() {
+        return None;
+    }
+    Some(((growth.powf(YEAR_MILLIS / millis as f64) - 1.0) * 10_000.0).round() as i128)
+}
@@ -1,8 +1,10 @@
 use std::{time::{SystemTime, UNIX_EPOCH}, fmt};
+use crate::errors::AppErrors;
 use crate::types::EpochMillis;
 
-use chrono::{Utc, NaiveDate};
+use chrono::{Datelike, Utc, NaiveDate, TimeZone};
 
+#[derive(Debug)]
 pub struct Now {
     nanosecs: u64
 }
@@ -17,49 +19,63 @@ impl Now {
         }
     }
 
+    /// Saturates after the year 2554, past the last date `new_from_datetime_str`
+    /// accepts.
     pub fn new_from_epoch_millis(epoch_millis: EpochMillis) -> Self {
-        Self { nanosecs: (epoch_millis * 1_000_000) as u64 }
+        Now::checked_from_epoch_millis(epoch_millis).unwrap_or(Self { nanosecs: u64::MAX })
+    }
+
+    /// None after the year 2554, the nanoseconds don't fit.
+    pub fn checked_from_epoch_millis(epoch_millis: EpochMillis) -> Option<Self> {
+        epoch_millis.checked_mul(1_000_000).map(|nanosecs| Self { nanosecs })
     }
 
     /// TODO: This converts a date to a timestamp at 13:00 hrs UTC / 07:00 hrs CST.
-    pub fn new_from_datetime_str(datetime: &str, format: &str) -> Self {
-        let epoch_millis = if format == "%Y-%m-%d" {
-            let dt = NaiveDate::parse_from_str(datetime, format)
-                .unwrap()
-                .and_hms_milli_opt(13, 0, 0, 0)
-                .unwrap()
-                .and_local_timezone(Utc)
-                .unwrap();
-            dt.timestamp_millis() as EpochMillis
-        } else {
-            unimplemented!();
-        };
+    pub fn new_from_datetime_str(datetime: &str, format: &str) -> Result<Self, AppErrors> {
+        if format != "%Y-%m-%d" {
+            return Err(AppErrors::MalformedDate(format!("unsupported format {}", format)));
+        }
+        let dt = NaiveDate::parse_from_str(datetime, format)
+            .map_err(|_| AppErrors::MalformedDate(datetime.to_string()))?
+            .and_hms_milli_opt(13, 0, 0, 0)
+            .unwrap()
+            .and_local_timezone(Utc)
+            .unwrap();
+        if dt.timestamp_millis() < 0 {
+            return Err(AppErrors::MalformedDate(datetime.to_string()));
+        }
 
-        Now::new_from_epoch_millis(epoch_millis)
+        Now::checked_from_epoch_millis(dt.timestamp_millis() as EpochMillis)
+            .ok_or_else(|| AppErrors::MalformedDate(datetime.to_string()))
     }
 
+    /// Zero for dates in the future.
     pub fn get_millis_since(epoch_millis: EpochMillis) -> EpochMillis {
-        Now::new().to_epoch_millis() - epoch_millis
+        Now::new().to_epoch_millis().saturating_sub(epoch_millis)
     }
 
-    pub fn to_epoch_millis(&self) -> EpochMillis {
+    pub fn to_epoch_millis(self) -> EpochMillis {
         (self.nanosecs / 1_000_000) as EpochMillis
     }
 
-    pub fn to_nanos(&self) -> u64 {
-        self.nanosecs
-    }
-
-    pub fn increment_min(&self, min: u64) -> Now {
-        Now { nanosecs: self.nanosecs + (min * 60 * 1_000_000_000) }
+    /// UTC date, %Y-%m-%d.
+    pub fn to_date_string(self) -> String {
+        Utc.timestamp_millis_opt(self.to_epoch_millis() as i64)
+            .unwrap()
+            .format("%Y-%m-%d")
+            .to_string()
     }
 
-    pub fn increment_days(&self, days: u64) -> Now {
-        Now { nanosecs: self.nanosecs + (days * 24 * 60 * 60 * 1_000_000_000) }
+    /// UTC calendar year.
+    pub fn get_year(self) -> i32 {
+        Utc.timestamp_millis_opt(self.to_epoch_millis() as i64).unwrap().year()
     }
 
-    pub fn reduce_min(&self, min: u64) -> Now {
-        Now { nanosecs: self.nanosecs - (min * 60 * 1_000_000_000) }
+    /// None past the year 2554.
+    pub fn increment_days(&self, days: u64) -> Option<Now> {
+        days.checked_mul(24 * 60 * 60 * 1_000_000_000)
+            .and_then(|nanosecs| self.nanosecs.checked_add(nanosecs))
+            .map(|nanosecs| Now { nanosecs })
     }
 }
 
@@ -0,0 +1,65 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce
+};
+use aes_gcm::aead::rand_core::RngCore;
+use scrypt::{scrypt, Params};
+use sha2::{Digest, Sha256};
+
+use crate::errors::AppErrors;
+use crate::types::HashString;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Derive the AES-256 key from the user password using scrypt.
+fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; KEY_LEN], AppErrors> {
+    let mut key = [0u8; KEY_LEN];
+    scrypt(password.as_bytes(), salt, &Params::recommended(), &mut key)
+        .map_err(|err| AppErrors::Crypto(err.to_string()))?;
+    Ok(key)
+}
+
+/// Encrypts the plaintext with AES-256-GCM.
+/// Sealed layout: salt (16 bytes) | nonce (12 bytes) | ciphertext.
+pub(crate) fn seal(plaintext: &[u8], password: &str) -> Result<Vec<u8>, AppErrors> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(password, &salt)?;
+
+    let cipher = Aes256Gcm::new_from_slice(&key)
+        .map_err(|err| AppErrors::Crypto(err.to_string()))?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|err| AppErrors::Crypto(err.to_string()))?;
+
+    let mut sealed = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypts a sealed buffer. A wrong password and a tampered buffer are
+/// indistinguishable for AES-GCM, both return `InvalidPassword`.
+pub(crate) fn unseal(sealed: &[u8], password: &str) -> Result<Vec<u8>, AppErrors> {
+    if sealed.len() < SALT_LEN + NONCE_LEN {
+        return Err(AppErrors::CorruptedAppState);
+    }
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let key = derive_key(password, salt)?;
+
+    let cipher = Aes256Gcm::new_from_slice(&key)
+        .map_err(|err| AppErrors::Crypto(err.to_string()))?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| AppErrors::InvalidPassword)
+}
+
+/// Hex encoded sha256 of the sealed buffer, stored in the user file.
+pub(crate) fn state_hash(sealed: &[u8]) -> HashString {
+    hex::encode(Sha256::digest(sealed))
+}